@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+/// The default `repl-backlog-size`, 1mb like Redis.
+pub const DEFAULT_BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// The tail of the replication stream, kept so a replica whose link dropped
+/// can catch up with PSYNC instead of loading a whole new snapshot. Once
+/// full, the oldest bytes make room for new ones.
+#[derive(Debug)]
+pub struct Backlog {
+    buffer: VecDeque<u8>,
+    size: usize,
+    /// The replication offset of the oldest byte held
+    first_byte_offset: i64,
+}
+
+impl Backlog {
+    /// An empty backlog for a stream that has reached `offset`.
+    pub fn new(size: usize, offset: i64) -> Self {
+        Backlog {
+            buffer: VecDeque::new(),
+            size,
+            first_byte_offset: offset + 1,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend(bytes);
+        self.trim();
+    }
+
+    pub fn resize(&mut self, size: usize) {
+        self.size = size;
+        self.trim();
+    }
+
+    pub fn first_byte_offset(&self) -> i64 {
+        self.first_byte_offset
+    }
+
+    pub fn histlen(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The stream from `offset` on, or `None` if part of it has already been
+    /// dropped or `offset` is past the end.
+    pub fn range_from(&self, offset: i64) -> Option<Vec<u8>> {
+        let skip = usize::try_from(offset - self.first_byte_offset).ok()?;
+        if skip > self.buffer.len() {
+            return None;
+        }
+
+        Some(self.buffer.range(skip..).copied().collect())
+    }
+
+    fn trim(&mut self) {
+        if self.buffer.len() > self.size {
+            let excess = self.buffer.len() - self.size;
+            self.buffer.drain(..excess);
+            self.first_byte_offset += excess as i64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_everything_fed_while_it_fits() {
+        let mut backlog = Backlog::new(8, 100);
+        backlog.feed(b"abcd");
+
+        assert_eq!(backlog.first_byte_offset(), 101);
+        assert_eq!(backlog.range_from(101), Some(b"abcd".to_vec()));
+        assert_eq!(backlog.range_from(103), Some(b"cd".to_vec()));
+        // A replica that is fully caught up is missing nothing
+        assert_eq!(backlog.range_from(105), Some(vec![]));
+        assert_eq!(backlog.range_from(106), None);
+        assert_eq!(backlog.range_from(100), None);
+    }
+
+    #[test]
+    fn drops_the_oldest_bytes_once_full() {
+        let mut backlog = Backlog::new(4, 0);
+        backlog.feed(b"abc");
+        backlog.feed(b"def");
+
+        assert_eq!(backlog.histlen(), 4);
+        assert_eq!(backlog.first_byte_offset(), 3);
+        assert_eq!(backlog.range_from(2), None);
+        assert_eq!(backlog.range_from(3), Some(b"cdef".to_vec()));
+        assert_eq!(backlog.range_from(6), Some(b"f".to_vec()));
+        assert_eq!(backlog.range_from(7), Some(vec![]));
+    }
+
+    #[test]
+    fn keeps_only_the_tail_of_a_write_larger_than_itself() {
+        let mut backlog = Backlog::new(3, 10);
+        backlog.feed(b"abcdefg");
+
+        assert_eq!(backlog.first_byte_offset(), 15);
+        assert_eq!(backlog.range_from(15), Some(b"efg".to_vec()));
+        assert_eq!(backlog.range_from(14), None);
+    }
+
+    #[test]
+    fn shrinking_drops_the_oldest_bytes() {
+        let mut backlog = Backlog::new(8, 0);
+        backlog.feed(b"abcdef");
+        backlog.resize(2);
+
+        assert_eq!(backlog.first_byte_offset(), 5);
+        assert_eq!(backlog.range_from(5), Some(b"ef".to_vec()));
+
+        backlog.resize(8);
+        backlog.feed(b"gh");
+        assert_eq!(backlog.range_from(5), Some(b"efgh".to_vec()));
+    }
+}
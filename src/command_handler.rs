@@ -1,85 +1,360 @@
-use bytes::BytesMut;
+use bytes::{Buf, Bytes, BytesMut};
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::{net::TcpStream, io::{AsyncReadExt, AsyncWriteExt}};
 use anyhow::Result;
-use itertools::join;
+use crate::util::parse_i64;
 
-#[derive(Clone, Debug)]
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Command {
     SimpleString(String),
-    BulkString(String),
+    Error(String),
+    Integer(i64),
+    BulkString(Bytes),
+    NullBulkString,
     Array(Vec<Command>),
+    NullArray,
+    // RESP3 only; downgraded by `into_resp2` for RESP2 connections
+    Map(Vec<(Command, Command)>),
+    Set(Vec<Command>),
+    Double(f64),
+    Boolean(bool),
+    Null,
+    BigNumber(String),
+    VerbatimString(String, Bytes),
+    Push(Vec<Command>),
 }
 
 impl Command {
-    pub fn serialize(self) -> String {
+    pub fn bulk(value: impl Into<Bytes>) -> Self {
+        Command::BulkString(value.into())
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Command::Error(message.into())
+    }
+
+    pub fn serialize(self) -> Vec<u8> {
+        let mut out = vec![];
+        self.serialize_into(&mut out);
+        out
+    }
+
+    /// Encodes the reply for a connection speaking `protocol`.
+    pub fn serialize_for(self, protocol: Protocol) -> Vec<u8> {
+        match protocol {
+            Protocol::Resp2 => self.into_resp2().serialize(),
+            Protocol::Resp3 => self.into_resp3().serialize(),
+        }
+    }
+
+    /// Maps RESP3-only types onto their RESP2 equivalents, the same way Redis
+    /// replies to a client that never sent `HELLO 3`.
+    pub fn into_resp2(self) -> Command {
         match self {
-            Command::SimpleString(s) => format!("+{}\r\n", s),
-            Command::BulkString(s) => if s.len() == 0 {
-                String::from("$-1\r\n")
-            } else {
-                format!("${}\r\n{}\r\n", s.chars().count(), s)
-            },
-            Command::Array(commands) => {
-                let number_of_elements = commands.len();
-                let mut elements: Vec<String> = vec![];
-
-                for command in commands.iter() {
-                    elements.push(command.clone().serialize())
-                }
+            Command::Array(items) => Command::Array(items.into_iter().map(Command::into_resp2).collect()),
+            Command::Map(pairs) => Command::Array(pairs.into_iter()
+                .flat_map(|(k, v)| [k.into_resp2(), v.into_resp2()])
+                .collect()),
+            Command::Set(items) | Command::Push(items) => Command::Array(items.into_iter().map(Command::into_resp2).collect()),
+            Command::Double(d) => Command::bulk(format_double(d)),
+            Command::Boolean(b) => Command::Integer(b as i64),
+            Command::Null => Command::NullBulkString,
+            Command::BigNumber(n) => Command::bulk(n),
+            Command::VerbatimString(_, text) => Command::BulkString(text),
+            other => other
+        }
+    }
+
+    fn into_resp3(self) -> Command {
+        match self {
+            Command::NullBulkString | Command::NullArray => Command::Null,
+            Command::Array(items) => Command::Array(items.into_iter().map(Command::into_resp3).collect()),
+            Command::Map(pairs) => Command::Map(pairs.into_iter().map(|(k, v)| (k.into_resp3(), v.into_resp3())).collect()),
+            Command::Set(items) => Command::Set(items.into_iter().map(Command::into_resp3).collect()),
+            Command::Push(items) => Command::Push(items.into_iter().map(Command::into_resp3).collect()),
+            other => other
+        }
+    }
 
-                format!("*{}\r\n{}", number_of_elements, join(elements, ""))
+    fn serialize_into(self, out: &mut Vec<u8>) {
+        match self {
+            Command::SimpleString(s) => out.extend_from_slice(format!("+{}\r\n", s).as_bytes()),
+            Command::Error(s) => out.extend_from_slice(format!("-{}\r\n", s).as_bytes()),
+            Command::Integer(i) => out.extend_from_slice(format!(":{}\r\n", i).as_bytes()),
+            Command::BulkString(s) => {
+                // Length prefix is the byte count, not the character count
+                out.extend_from_slice(format!("${}\r\n", s.len()).as_bytes());
+                out.extend_from_slice(&s);
+                out.extend_from_slice(b"\r\n");
+            }
+            Command::NullBulkString => out.extend_from_slice(b"$-1\r\n"),
+            Command::Array(commands) => serialize_aggregate(out, '*', commands),
+            Command::NullArray => out.extend_from_slice(b"*-1\r\n"),
+            Command::Map(pairs) => {
+                out.extend_from_slice(format!("%{}\r\n", pairs.len()).as_bytes());
+
+                for (key, value) in pairs {
+                    key.serialize_into(out);
+                    value.serialize_into(out);
+                }
+            }
+            Command::Set(items) => serialize_aggregate(out, '~', items),
+            Command::Push(items) => serialize_aggregate(out, '>', items),
+            Command::Double(d) => out.extend_from_slice(format!(",{}\r\n", format_double(d)).as_bytes()),
+            Command::Boolean(b) => out.extend_from_slice(if b { b"#t\r\n" } else { b"#f\r\n" }),
+            Command::Null => out.extend_from_slice(b"_\r\n"),
+            Command::BigNumber(n) => out.extend_from_slice(format!("({}\r\n", n).as_bytes()),
+            Command::VerbatimString(format, text) => {
+                out.extend_from_slice(format!("={}\r\n{}:", text.len() + 4, format).as_bytes());
+                out.extend_from_slice(&text);
+                out.extend_from_slice(b"\r\n");
             }
         }
     }
 }
 
+fn serialize_aggregate(out: &mut Vec<u8>, prefix: char, items: Vec<Command>) {
+    out.extend_from_slice(format!("{}{}\r\n", prefix, items.len()).as_bytes());
+
+    for item in items {
+        item.serialize_into(out);
+    }
+}
+
+/// Formats a double the way Redis does on the wire: `inf`, `-inf`, `nan`, and
+/// the shortest representation that round-trips otherwise.
+pub fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        String::from("nan")
+    } else if d.is_infinite() {
+        String::from(if d > 0.0 { "inf" } else { "-inf" })
+    } else {
+        format!("{}", d)
+    }
+}
+
+/// Significant digits `format_float_value` keeps. Redis prints its long
+/// doubles with 17 of their ~19, which hides the rounding noise of their last
+/// bits; `DBL_DIG` does the same for an f64's ~17.
+const FLOAT_VALUE_DIGITS: usize = 15;
+
+/// Formats the result of INCRBYFLOAT or HINCRBYFLOAT the way Redis stores it,
+/// like C's `%g` followed by trimming: `0.1 + 0.2` is "0.3" and `1e300` is
+/// "1e+300" rather than 301 digits.
+pub fn format_float_value(d: f64) -> String {
+    let scientific = format!("{:.*e}", FLOAT_VALUE_DIGITS - 1, d);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    if exponent < -4 || exponent >= FLOAT_VALUE_DIGITS as i32 {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim_fraction(mantissa), sign, exponent.abs())
+    } else {
+        let fixed = format!("{:.*}", (FLOAT_VALUE_DIGITS as i32 - 1 - exponent) as usize, d);
+        trim_fraction(&fixed).to_string()
+    }
+}
+
+fn trim_fraction(number: &str) -> &str {
+    if number.contains('.') { number.trim_end_matches('0').trim_end_matches('.') } else { number }
+}
+
+
+/// Most arguments a request may have, as Redis allows
+const MAX_MULTIBULK_LENGTH: i64 = 1024 * 1024;
+
+/// Largest argument a request may carry (Redis's `proto-max-bulk-len`)
+const MAX_BULK_LENGTH: i64 = 512 * 1024 * 1024;
+
+/// Longest a request header may get without its CRLF
+const MAX_HEADER_LENGTH: usize = 64 * 1024;
+
+/// How deeply aggregates may nest in a reply
+const MAX_NESTING_DEPTH: usize = 128;
 
 pub struct CommandHandler {
     pub stream: TcpStream,
+    pub id: u64,
+    pub protocol: Protocol,
     buffer: BytesMut,
+    request: PartialRequest,
+}
+
+/// What has been parsed of the request being read. It is kept between socket
+/// reads, so a request arriving in many pieces is only parsed once.
+#[derive(Default)]
+struct PartialRequest {
+    /// Arguments still to come, `None` until the `*<count>` header is read
+    remaining: Option<usize>,
+    args: Vec<Command>,
+    /// Bytes the request has taken up so far
+    len: usize,
+}
+
+impl PartialRequest {
+    /// Carries on parsing the request from where the last call stopped.
+    fn parse(&mut self, buffer: &mut BytesMut) -> Result<Option<(Command, usize)>> {
+        let mut remaining = loop {
+            if let Some(remaining) = self.remaining {
+                break remaining;
+            }
+
+            let (count, header) = match read_header(buffer, b'*', MAX_MULTIBULK_LENGTH, "multibulk length")? {
+                Some(header) => header,
+                None => return Ok(None)
+            };
+            buffer.advance(header);
+
+            // Like Redis, an empty request is skipped
+            if count > 0 {
+                *self = PartialRequest {
+                    remaining: Some(count as usize),
+                    args: Vec::with_capacity((count as usize).min(1024)),
+                    len: header,
+                };
+            }
+        };
+
+        while remaining > 0 {
+            let (len, header) = match read_header(buffer, b'$', MAX_BULK_LENGTH, "bulk length")? {
+                Some((len, header)) if len >= 0 => (len as usize, header),
+                Some(_) => return Err(anyhow::anyhow!("invalid bulk length")),
+                None => return Ok(None)
+            };
+
+            if buffer.len() < header + len + 2 {
+                buffer.reserve(header + len + 2 - buffer.len());
+                return Ok(None);
+            }
+            if &buffer[header + len..header + len + 2] != b"\r\n" {
+                return Err(anyhow::anyhow!("Bulk string is not terminated by CRLF"));
+            }
+
+            buffer.advance(header);
+            let arg = buffer.split_to(len).freeze();
+            buffer.advance(2);
+
+            self.args.push(Command::BulkString(arg));
+            self.len += header + len + 2;
+            remaining -= 1;
+            self.remaining = Some(remaining);
+        }
+
+        let request = std::mem::take(self);
+        Ok(Some((Command::Array(request.args), request.len)))
+    }
 }
 
 impl CommandHandler {
     pub fn new(stream: TcpStream) -> Self {
         CommandHandler {
             stream,
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::Resp2,
             buffer: BytesMut::with_capacity(512),
+            request: PartialRequest::default(),
         }
     }
 
+    /// Returns the next reply, reading from the socket only when the buffer
+    /// does not hold a whole one yet.
     pub async fn read(&mut self) -> Result<Option<Command>> {
-        let cursor = self.stream.read_buf(&mut self.buffer).await?;
+        loop {
+            if let Some((command, len)) = to_command(&self.buffer)? {
+                self.buffer.advance(len);
+                return Ok(Some(command));
+            }
 
-        if cursor == 0 {
-            return Ok(None);
+            if self.read_more().await? == 0 {
+                return self.eof();
+            }
+        }
+    }
+
+    /// Returns the next request, an array of bulk strings, along with how
+    /// many bytes it took up on the wire, which is what replication offsets
+    /// count. Bytes past the request stay buffered, so pipelined requests are
+    /// yielded one by one on subsequent calls.
+    ///
+    /// Safe to cancel: a partly read request is picked up by the next call.
+    pub async fn read_request(&mut self) -> Result<Option<(Command, usize)>> {
+        loop {
+            if let Some(request) = self.request.parse(&mut self.buffer)? {
+                return Ok(Some(request));
+            }
+
+            if self.read_more().await? == 0 {
+                return self.eof();
+            }
+        }
+    }
+
+    fn eof<T>(&self) -> Result<Option<T>> {
+        if !self.buffer.is_empty() || self.request.remaining.is_some() {
+            return Err(anyhow::anyhow!("Connection closed with a partial frame in the buffer"));
         }
 
-        let (command, _) = to_command(self.buffer.split())?;
-        Ok(Some(command))
+        Ok(None)
     }
 
-    // pub async fn writeQ(&mut self, bytes: &[u8]) -> Result<()> {
-    //     self.stream.write(bytes).await?;
-    //
-    //     Ok(())
-    // }
+    /// Reads a `$<len>\r\n` payload that, unlike a bulk string, is not
+    /// terminated by CRLF: the RDB a master sends after FULLRESYNC.
+    pub async fn read_payload(&mut self) -> Result<Bytes> {
+        let (len, header) = loop {
+            if let Some((line, header)) = read_line(&self.buffer) {
+                match line.strip_prefix(b"$") {
+                    Some(len) => break (usize::try_from(buffer_to_int(len)?)?, header),
+                    None => return Err(anyhow::anyhow!("Expected a payload, got {:?}", line))
+                }
+            }
+
+            if self.read_more().await? == 0 {
+                return Err(anyhow::anyhow!("Connection closed before the payload"));
+            }
+        };
+
+        while self.buffer.len() < header + len {
+            if self.read_more().await? == 0 {
+                return Err(anyhow::anyhow!("Connection closed in the middle of the payload"));
+            }
+        }
+
+        self.buffer.advance(header);
+        Ok(self.buffer.split_to(len).freeze())
+    }
+
+    /// Resolves once the peer has closed the connection, e.g. while its
+    /// command is blocked. Whatever it sends meanwhile is kept for later reads.
+    pub async fn disconnected(&mut self) {
+        while let Ok(read) = self.read_more().await {
+            if read == 0 {
+                break;
+            }
+        }
+    }
+
+    async fn read_more(&mut self) -> Result<usize> {
+        Ok(self.stream.read_buf(&mut self.buffer).await?)
+    }
 
     pub async fn write(&mut self, data: WriteData) -> Result<()> {
         let bytes = match data {
-            WriteData::Command(command) => command.serialize().into_bytes(),
+            WriteData::Command(command) => command.serialize_for(self.protocol),
             WriteData::String(string) => string.into_bytes(),
             WriteData::Raw(data) => data
         };
-        self.stream.write(bytes.as_slice()).await?;
+        self.stream.write_all(bytes.as_slice()).await?;
 
         Ok(())
     }
-
-    // pub async fn write2(&mut self, value: Command) -> Result<()> {
-    //     self.stream.write(value.serialize().as_bytes()).await?;
-    //
-    //     Ok(())
-    // }
 }
 
 pub enum WriteData {
@@ -88,65 +363,240 @@ pub enum WriteData {
     Raw(Vec<u8>),
 }
 
-fn to_command(buffer: BytesMut) -> Result<(Command, usize)> {
-    // println!("to_command-->");
+/// Reads a `<prefix><number>\r\n` request header, returning the number and
+/// the length of the header.
+fn read_header(buffer: &[u8], prefix: u8, max: i64, name: &str) -> Result<Option<(i64, usize)>> {
+    let first = match buffer.first() {
+        Some(first) => *first,
+        None => return Ok(None)
+    };
+    if first != prefix {
+        return Err(anyhow::anyhow!("expected '{}', got '{}'", prefix as char, first as char));
+    }
+
+    match read_line(&buffer[1..]) {
+        // Only canonical numbers, so a request takes up exactly as many bytes
+        // as it does once serialized again, e.g. for a replica's backlog
+        Some((line, len)) => match parse_i64(line) {
+            Some(number) if number <= max => Ok(Some((number, len + 1))),
+            _ => Err(anyhow::anyhow!("invalid {}", name))
+        },
+        None if buffer.len() > MAX_HEADER_LENGTH => Err(anyhow::anyhow!("too big {} string", name)),
+        None => Ok(None)
+    }
+}
+
+/// Parses a single frame from the start of `buffer`.
+///
+/// Returns `Ok(None)` when the buffer ends before the frame does, and the
+/// number of bytes consumed alongside the command otherwise.
+fn to_command(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    parse_frame(buffer, 0)
+}
+
+fn parse_frame(buffer: &[u8], depth: usize) -> Result<Option<(Command, usize)>> {
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+
+    if depth > MAX_NESTING_DEPTH {
+        return Err(anyhow::anyhow!("Aggregates nested more than {} levels deep", MAX_NESTING_DEPTH));
+    }
+
     match buffer[0] as char {
         '+' => parse_simple_string(buffer),
+        '-' => parse_error(buffer),
+        ':' => parse_integer(buffer),
         '$' => parse_bulk_string(buffer),
-        '*' => parse_array(buffer),
+        '*' => parse_array(buffer, depth),
+        '%' => parse_map(buffer, depth),
+        '~' => parse_aggregate(buffer, depth).map(|parsed| parsed.map(|(items, len)| (Command::Set(items), len))),
+        '>' => parse_aggregate(buffer, depth).map(|parsed| parsed.map(|(items, len)| (Command::Push(items), len))),
+        ',' => parse_double(buffer),
+        '#' => parse_boolean(buffer),
+        '_' => parse_null(buffer),
+        '(' => parse_big_number(buffer),
+        '=' => parse_verbatim_string(buffer),
         _ => Err(anyhow::anyhow!("Unknown value type {:?}", buffer))
     }
 }
 
 
-fn parse_simple_string(buffer: BytesMut) -> Result<(Command, usize)> {
-    // println!("parse_simple_string {:?}", buffer);
-    if let Some((line, len)) = read_line(&buffer[1..]) {
-        let string = String::from_utf8(line.to_vec()).unwrap();
+fn parse_simple_string(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    match read_line(&buffer[1..]) {
+        Some((line, len)) => {
+            let string = String::from_utf8(line.to_vec())?;
 
-        return Ok((Command::SimpleString(string), len + 1));
-    } else {
-        return Err(anyhow::anyhow!("Invalid string format {:?}", buffer));
-    };
+            Ok(Some((Command::SimpleString(string), len + 1)))
+        }
+        None => Ok(None)
+    }
 }
 
-fn parse_bulk_string(buffer: BytesMut) -> Result<(Command, usize)> {
-    // println!("parse_bulk_string {:?}", buffer);
-    let (bulk_str_len, cursor) = if let Some((next_line, cursor)) = read_line(&buffer[1..]) {
-        let bulk_str_len = buffer_to_int(next_line)?;
+fn parse_error(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    match read_line(&buffer[1..]) {
+        Some((line, len)) => {
+            let string = String::from_utf8(line.to_vec())?;
 
-        (bulk_str_len, cursor + 1)
-    } else {
-        return Err(anyhow::anyhow!("Invalid array format {:?}", buffer));
+            Ok(Some((Command::Error(string), len + 1)))
+        }
+        None => Ok(None)
+    }
+}
+
+fn parse_integer(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    match read_line(&buffer[1..]) {
+        Some((line, len)) => Ok(Some((Command::Integer(buffer_to_int(line)?), len + 1))),
+        None => Ok(None)
+    }
+}
+
+fn parse_bulk_string(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    let (bulk_str_len, cursor) = match read_line(&buffer[1..]) {
+        Some((next_line, cursor)) => (buffer_to_int(next_line)?, cursor + 1),
+        None => return Ok(None)
     };
 
+    if bulk_str_len == -1 {
+        return Ok(Some((Command::NullBulkString, cursor)));
+    }
+
+    if !(0..=MAX_BULK_LENGTH).contains(&bulk_str_len) {
+        return Err(anyhow::anyhow!("Invalid bulk string length {}", bulk_str_len));
+    }
+
     let end_of_bulk_str = cursor + bulk_str_len as usize;
     let total_parsed = end_of_bulk_str + 2;
 
-    Ok((Command::BulkString(String::from_utf8(buffer[cursor..end_of_bulk_str].to_vec())?), total_parsed))
+    if buffer.len() < total_parsed {
+        return Ok(None);
+    }
+
+    if &buffer[end_of_bulk_str..total_parsed] != b"\r\n" {
+        return Err(anyhow::anyhow!("Bulk string is not terminated by CRLF"));
+    }
+
+    Ok(Some((Command::BulkString(Bytes::copy_from_slice(&buffer[cursor..end_of_bulk_str])), total_parsed)))
 }
 
-fn parse_array(buffer: BytesMut) -> Result<(Command, usize)> {
-    // println!("parse_array {:?}", buffer);
+fn parse_array(buffer: &[u8], depth: usize) -> Result<Option<(Command, usize)>> {
+    if buffer.starts_with(b"*-1\r\n") {
+        return Ok(Some((Command::NullArray, 5)));
+    }
 
-    // Get number of items in array, and move cursor
-    let (array_length, mut cursor) = if let Some((next_line, cursor)) = read_line(&buffer[1..]) {
-        let arr_len = buffer_to_int(next_line)?;
-        (arr_len, cursor + 1)
-    } else {
-        return Err(anyhow::anyhow!("Invalid array format {:?}", buffer));
+    Ok(parse_aggregate(buffer, depth)?.map(|(commands, len)| (Command::Array(commands), len)))
+}
+
+fn parse_map(buffer: &[u8], depth: usize) -> Result<Option<(Command, usize)>> {
+    // A map header counts pairs, so read twice as many elements
+    let (pairs_length, cursor) = match read_length(buffer)? {
+        Some(header) => header,
+        None => return Ok(None)
     };
 
+    Ok(parse_items(buffer, pairs_length * 2, cursor, depth)?.map(|(items, len)| {
+        let mut items = items.into_iter();
+        let mut pairs = vec![];
+
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            pairs.push((key, value));
+        }
+
+        (Command::Map(pairs), len)
+    }))
+}
+
+/// Parses the `<prefix><count>\r\n` header followed by `count` elements.
+fn parse_aggregate(buffer: &[u8], depth: usize) -> Result<Option<(Vec<Command>, usize)>> {
+    match read_length(buffer)? {
+        Some((length, cursor)) => parse_items(buffer, length, cursor, depth),
+        None => Ok(None)
+    }
+}
+
+fn read_length(buffer: &[u8]) -> Result<Option<(usize, usize)>> {
+    // Get number of items in aggregate, and move cursor
+    let (length, cursor) = match read_line(&buffer[1..]) {
+        Some((next_line, cursor)) => (buffer_to_int(next_line)?, cursor + 1),
+        None => return Ok(None)
+    };
+
+    if !(0..=MAX_MULTIBULK_LENGTH).contains(&length) {
+        return Err(anyhow::anyhow!("Invalid aggregate length {}", length));
+    }
+
+    Ok(Some((length as usize, cursor)))
+}
+
+fn parse_items(buffer: &[u8], count: usize, mut cursor: usize, depth: usize) -> Result<Option<(Vec<Command>, usize)>> {
     let mut commands = vec![];
 
-    // Read each command in array
-    for _ in 0..array_length {
-        let (command, _cursor) = to_command(BytesMut::from(&buffer[cursor..]))?;
-        commands.push(command);
-        cursor += _cursor;
+    // Read each command in aggregate, bailing out if any of them is incomplete
+    for _ in 0..count {
+        match parse_frame(&buffer[cursor..], depth + 1)? {
+            Some((command, len)) => {
+                commands.push(command);
+                cursor += len;
+            }
+            None => return Ok(None)
+        }
     }
 
-    return Ok((Command::Array(commands), cursor));
+    Ok(Some((commands, cursor)))
+}
+
+fn parse_double(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    match read_line(&buffer[1..]) {
+        Some((line, len)) => {
+            let value = match String::from_utf8(line.to_vec())?.as_str() {
+                "inf" => f64::INFINITY,
+                "-inf" => f64::NEG_INFINITY,
+                other => other.parse::<f64>()?
+            };
+
+            Ok(Some((Command::Double(value), len + 1)))
+        }
+        None => Ok(None)
+    }
+}
+
+fn parse_boolean(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    match read_line(&buffer[1..]) {
+        Some((b"t", len)) => Ok(Some((Command::Boolean(true), len + 1))),
+        Some((b"f", len)) => Ok(Some((Command::Boolean(false), len + 1))),
+        Some((line, _)) => Err(anyhow::anyhow!("Invalid boolean {:?}", line)),
+        None => Ok(None)
+    }
+}
+
+fn parse_null(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    match read_line(&buffer[1..]) {
+        Some((_, len)) => Ok(Some((Command::Null, len + 1))),
+        None => Ok(None)
+    }
+}
+
+fn parse_big_number(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    match read_line(&buffer[1..]) {
+        Some((line, len)) => Ok(Some((Command::BigNumber(String::from_utf8(line.to_vec())?), len + 1))),
+        None => Ok(None)
+    }
+}
+
+fn parse_verbatim_string(buffer: &[u8]) -> Result<Option<(Command, usize)>> {
+    // Same framing as a bulk string, with a three letter format and a colon up front
+    let (text, len) = match parse_bulk_string(buffer)? {
+        Some((Command::BulkString(text), len)) => (text, len),
+        Some(_) => return Err(anyhow::anyhow!("Invalid verbatim string length")),
+        None => return Ok(None)
+    };
+
+    if text.len() < 4 || text[3] != b':' {
+        return Err(anyhow::anyhow!("Invalid verbatim string {:?}", text));
+    }
+
+    let format = String::from_utf8(text[..3].to_vec())?;
+    Ok(Some((Command::VerbatimString(format, text.slice(4..)), len)))
 }
 
 fn read_line(buffer: &[u8]) -> Option<(&[u8], usize)> {
@@ -156,9 +606,122 @@ fn read_line(buffer: &[u8]) -> Option<(&[u8], usize)> {
         }
     }
 
-    return None;
+    None
 }
 
 fn buffer_to_int(buffer: &[u8]) -> Result<i64> {
     Ok(String::from_utf8(buffer.to_vec())?.parse::<i64>()?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_requests(chunks: &[&[u8]]) -> Result<Vec<(Command, usize)>> {
+        let mut buffer = BytesMut::new();
+        let mut request = PartialRequest::default();
+        let mut parsed = vec![];
+
+        for chunk in chunks {
+            buffer.extend_from_slice(chunk);
+            while let Some(frame) = request.parse(&mut buffer)? {
+                parsed.push(frame);
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn request(args: &[&str]) -> Command {
+        Command::Array(args.iter().map(|arg| Command::bulk(arg.to_string())).collect())
+    }
+
+    #[test]
+    fn parses_a_request_split_at_every_byte() {
+        let wire = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n*1\r\n$4\r\nPING\r\n";
+        let chunks: Vec<&[u8]> = wire.chunks(1).collect();
+
+        let parsed = parse_requests(&chunks).unwrap();
+        assert_eq!(parsed, vec![(request(&["GET", "key"]), 22), (request(&["PING"]), 14)]);
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_request() {
+        assert_eq!(parse_requests(&[b"*2\r\n$3\r\nGET\r\n$3\r\nke"]).unwrap(), vec![]);
+        assert_eq!(parse_requests(&[b"*2\r"]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn skips_empty_requests() {
+        assert_eq!(parse_requests(&[b"*0\r\n*-1\r\n*1\r\n$4\r\nPING\r\n"]).unwrap(), vec![(request(&["PING"]), 14)]);
+    }
+
+    #[test]
+    fn rejects_nested_requests() {
+        let error = parse_requests(&[b"*1\r\n*1\r\n$4\r\nPING\r\n"]).unwrap_err();
+        assert_eq!(error.to_string(), "expected '$', got '*'");
+
+        let deep = b"*1\r\n".repeat(300_000);
+        assert!(parse_requests(&[&deep]).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_requests() {
+        let error = parse_requests(&[b"*1048577\r\n"]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid multibulk length");
+
+        let error = parse_requests(&[b"*1\r\n$536870913\r\n"]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid bulk length");
+
+        let error = parse_requests(&[b"*1\r\n$-1\r\n"]).unwrap_err();
+        assert_eq!(error.to_string(), "invalid bulk length");
+    }
+
+    #[test]
+    fn parses_partial_and_nested_replies() {
+        assert_eq!(to_command(b"*2\r\n$1\r\na\r\n*1\r\n:").unwrap(), None);
+
+        let (reply, len) = to_command(b"*2\r\n$1\r\na\r\n*1\r\n:5\r\n+extra").unwrap().unwrap();
+        assert_eq!(reply, Command::Array(vec![Command::bulk("a"), Command::Array(vec![Command::Integer(5)])]));
+        assert_eq!(len, 19);
+
+        let (reply, _) = to_command(b"%1\r\n+k\r\n~1\r\n#t\r\n").unwrap().unwrap();
+        assert_eq!(reply, Command::Map(vec![(Command::SimpleString(String::from("k")), Command::Set(vec![Command::Boolean(true)]))]));
+    }
+
+    #[test]
+    fn parses_verbatim_strings() {
+        let (reply, len) = to_command(b"=8\r\ntxt:text\r\n").unwrap().unwrap();
+        assert_eq!(reply, Command::VerbatimString(String::from("txt"), Bytes::from("text")));
+        assert_eq!(len, 14);
+
+        assert_eq!(to_command(b"=8\r\ntxt:te").unwrap(), None);
+        assert!(to_command(b"=-1\r\n").is_err());
+        assert!(to_command(b"=-5\r\n").is_err());
+        assert!(to_command(b"=x\r\n").is_err());
+        assert!(to_command(b"=4\r\ntext\r\n").is_err());
+    }
+
+    #[test]
+    fn rejects_deeply_nested_replies() {
+        let deep = b"*1\r\n".repeat(300_000);
+        assert!(to_command(&deep).is_err());
+
+        let mut nested = b"*1\r\n".repeat(MAX_NESTING_DEPTH);
+        nested.extend_from_slice(b":1\r\n");
+        assert!(to_command(&nested).unwrap().is_some());
+    }
+
+    #[test]
+    fn float_values_are_rounded_like_redis() {
+        assert_eq!(format_float_value(0.1 + 0.2), "0.3");
+        assert_eq!(format_float_value(10.5), "10.5");
+        assert_eq!(format_float_value(-3.0), "-3");
+        assert_eq!(format_float_value(0.0), "0");
+        assert_eq!(format_float_value(5.0e3), "5000");
+        assert_eq!(format_float_value(1e300), "1e+300");
+        assert_eq!(format_float_value(1.5e-7), "1.5e-07");
+        assert_eq!(format_float_value(0.0001), "0.0001");
+        assert_eq!(format_float_value(123456789012345678.0), "1.23456789012346e+17");
+    }
+}
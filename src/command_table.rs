@@ -0,0 +1,925 @@
+use crate::command_handler::Command;
+use crate::commands::{self, hashes, keyspace, lists, pubsub, sets, sorted_sets, streams, strings, transactions, CommandResult};
+use crate::error::CommandError;
+use crate::session::Session;
+use crate::storage::Storage;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+pub type KeyspaceFn = fn(&mut Storage, &[Command]) -> Result<Command, CommandError>;
+pub type SessionFn = for<'a> fn(&'a mut Session, &'a [Command]) -> Pin<Box<dyn Future<Output=CommandResult> + Send + 'a>>;
+
+pub enum Handler {
+    /// Runs synchronously while the storage lock is held; the returned command
+    /// is written back as the reply.
+    Keyspace(KeyspaceFn),
+    /// Needs the connection or server state and writes its own reply.
+    Session(SessionFn),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flag {
+    Write,
+    ReadOnly,
+    Admin,
+    Fast,
+    Stale,
+    Loading,
+    NoScript,
+    Blocking,
+    PubSub,
+}
+
+impl Flag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Flag::Write => "write",
+            Flag::ReadOnly => "readonly",
+            Flag::Admin => "admin",
+            Flag::Fast => "fast",
+            Flag::Stale => "stale",
+            Flag::Loading => "loading",
+            Flag::NoScript => "noscript",
+            Flag::Blocking => "blocking",
+            Flag::PubSub => "pubsub",
+        }
+    }
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Redis-style arity counting the command name: `n` means exactly `n`
+    /// arguments, `-n` means at least `n`.
+    pub arity: i32,
+    pub flags: &'static [Flag],
+    pub first_key: i32,
+    pub last_key: i32,
+    pub step: i32,
+    pub group: &'static str,
+    pub summary: &'static str,
+    pub since: &'static str,
+    pub handler: Handler,
+}
+
+impl CommandSpec {
+    pub fn arity_matches(&self, argc: usize) -> bool {
+        let argc = argc as i32;
+
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    pub fn has_flag(&self, flag: Flag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    pub fn acl_categories(&self) -> Vec<String> {
+        let mut categories = vec![format!("@{}", self.group)];
+
+        for flag in [Flag::Write, Flag::ReadOnly, Flag::Admin, Flag::Fast] {
+            if self.has_flag(flag) {
+                categories.push(format!("@{}", flag.as_str()));
+            }
+        }
+        if self.has_flag(Flag::Admin) {
+            categories.push(String::from("@dangerous"));
+        }
+        if !self.has_flag(Flag::Fast) {
+            categories.push(String::from("@slow"));
+        }
+
+        categories
+    }
+}
+
+/// Wraps an `async fn(&mut Session, &[Command]) -> CommandResult` into a
+/// [`SessionFn`] pointer.
+macro_rules! session_handler {
+    ($f:path) => {{
+        fn handler<'a>(session: &'a mut Session, args: &'a [Command]) -> Pin<Box<dyn Future<Output=CommandResult> + Send + 'a>> {
+            Box::pin($f(session, args))
+        }
+        Handler::Session(handler)
+    }};
+}
+
+fn command_specs() -> Vec<CommandSpec> {
+    use Flag::*;
+
+    vec![
+        CommandSpec {
+            name: "ping", arity: -1, flags: &[Fast, Stale, Loading], first_key: 0, last_key: 0, step: 0,
+            group: "connection", summary: "Returns the server's liveliness response.", since: "1.0.0",
+            handler: session_handler!(commands::ping_command),
+        },
+        CommandSpec {
+            name: "echo", arity: 2, flags: &[Fast, Stale, Loading], first_key: 0, last_key: 0, step: 0,
+            group: "connection", summary: "Returns the given string.", since: "1.0.0",
+            handler: session_handler!(commands::echo_command),
+        },
+        CommandSpec {
+            name: "hello", arity: -1, flags: &[Fast, Stale, Loading, NoScript], first_key: 0, last_key: 0, step: 0,
+            group: "connection", summary: "Handshakes with the Redis server.", since: "6.0.0",
+            handler: session_handler!(commands::hello_command),
+        },
+        CommandSpec {
+            name: "quit", arity: -1, flags: &[Fast, Stale, Loading, NoScript], first_key: 0, last_key: 0, step: 0,
+            group: "connection", summary: "Closes the connection.", since: "1.0.0",
+            handler: session_handler!(commands::quit_command),
+        },
+        CommandSpec {
+            name: "reset", arity: 1, flags: &[Fast, Stale, Loading, NoScript], first_key: 0, last_key: 0, step: 0,
+            group: "connection", summary: "Resets the connection.", since: "6.2.0",
+            handler: session_handler!(commands::reset_command),
+        },
+        CommandSpec {
+            name: "command", arity: -1, flags: &[Stale, Loading], first_key: 0, last_key: 0, step: 0,
+            group: "server", summary: "Returns detailed information about all commands.", since: "2.8.13",
+            handler: session_handler!(commands::command_command),
+        },
+        CommandSpec {
+            name: "info", arity: -1, flags: &[Stale, Loading], first_key: 0, last_key: 0, step: 0,
+            group: "server", summary: "Returns information and statistics about the server.", since: "1.0.0",
+            handler: session_handler!(commands::info_command),
+        },
+        CommandSpec {
+            name: "config", arity: -2, flags: &[Admin, NoScript, Loading, Stale], first_key: 0, last_key: 0, step: 0,
+            group: "server", summary: "A container for server configuration commands.", since: "2.0.0",
+            handler: session_handler!(commands::config_command),
+        },
+        CommandSpec {
+            name: "replconf", arity: -1, flags: &[Admin, Stale, Loading, NoScript], first_key: 0, last_key: 0, step: 0,
+            group: "server", summary: "An internal command for configuring the replication stream.", since: "3.0.0",
+            handler: session_handler!(commands::replconf_command),
+        },
+        CommandSpec {
+            name: "psync", arity: -3, flags: &[Admin, NoScript], first_key: 0, last_key: 0, step: 0,
+            group: "server", summary: "An internal command used in replication.", since: "2.8.0",
+            handler: session_handler!(commands::psync_command),
+        },
+        CommandSpec {
+            name: "wait", arity: 3, flags: &[NoScript], first_key: 0, last_key: 0, step: 0,
+            group: "generic", summary: "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.", since: "3.0.0",
+            handler: session_handler!(commands::wait_command),
+        },
+        CommandSpec {
+            name: "get", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Returns the string value of a key.", since: "1.0.0",
+            handler: Handler::Keyspace(strings::get_command),
+        },
+        CommandSpec {
+            name: "set", arity: -3, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(strings::set_command),
+        },
+        CommandSpec {
+            name: "expire", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Sets the expiration time of a key in seconds.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::expire_command),
+        },
+        CommandSpec {
+            name: "pexpire", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Sets the expiration time of a key in milliseconds.", since: "2.6.0",
+            handler: Handler::Keyspace(keyspace::pexpire_command),
+        },
+        CommandSpec {
+            name: "expireat", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Sets the expiration time of a key to a Unix timestamp.", since: "1.2.0",
+            handler: Handler::Keyspace(keyspace::expireat_command),
+        },
+        CommandSpec {
+            name: "pexpireat", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Sets the expiration time of a key to a Unix milliseconds timestamp.", since: "2.6.0",
+            handler: Handler::Keyspace(keyspace::pexpireat_command),
+        },
+        CommandSpec {
+            name: "ttl", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Returns the expiration time in seconds of a key.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::ttl_command),
+        },
+        CommandSpec {
+            name: "pttl", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Returns the expiration time in milliseconds of a key.", since: "2.6.0",
+            handler: Handler::Keyspace(keyspace::pttl_command),
+        },
+        CommandSpec {
+            name: "expiretime", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Returns the expiration time of a key as a Unix timestamp.", since: "7.0.0",
+            handler: Handler::Keyspace(keyspace::expiretime_command),
+        },
+        CommandSpec {
+            name: "pexpiretime", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Returns the expiration time of a key as a Unix milliseconds timestamp.", since: "7.0.0",
+            handler: Handler::Keyspace(keyspace::pexpiretime_command),
+        },
+        CommandSpec {
+            name: "persist", arity: 2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Removes the expiration time of a key.", since: "2.2.0",
+            handler: Handler::Keyspace(keyspace::persist_command),
+        },
+        CommandSpec {
+            name: "del", arity: -2, flags: &[Write], first_key: 1, last_key: -1, step: 1,
+            group: "keyspace", summary: "Deletes one or more keys.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::del_command),
+        },
+        CommandSpec {
+            name: "unlink", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: -1, step: 1,
+            group: "keyspace", summary: "Asynchronously deletes one or more keys.", since: "4.0.0",
+            handler: Handler::Keyspace(keyspace::del_command),
+        },
+        CommandSpec {
+            name: "exists", arity: -2, flags: &[ReadOnly, Fast], first_key: 1, last_key: -1, step: 1,
+            group: "keyspace", summary: "Determines whether one or more keys exist.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::exists_command),
+        },
+        CommandSpec {
+            name: "type", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "keyspace", summary: "Determines the type of value stored at a key.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::type_command),
+        },
+        CommandSpec {
+            name: "rename", arity: 3, flags: &[Write], first_key: 1, last_key: 2, step: 1,
+            group: "keyspace", summary: "Renames a key and overwrites the destination.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::rename_command),
+        },
+        CommandSpec {
+            name: "renamenx", arity: 3, flags: &[Write, Fast], first_key: 1, last_key: 2, step: 1,
+            group: "keyspace", summary: "Renames a key only when the target key name doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::renamenx_command),
+        },
+        CommandSpec {
+            name: "copy", arity: -3, flags: &[Write], first_key: 1, last_key: 2, step: 1,
+            group: "keyspace", summary: "Copies the value of a key to a new key.", since: "6.2.0",
+            handler: Handler::Keyspace(keyspace::copy_command),
+        },
+        CommandSpec {
+            name: "keys", arity: 2, flags: &[ReadOnly], first_key: 0, last_key: 0, step: 0,
+            group: "keyspace", summary: "Returns all key names that match a pattern.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::keys_command),
+        },
+        CommandSpec {
+            name: "dbsize", arity: 1, flags: &[ReadOnly, Fast], first_key: 0, last_key: 0, step: 0,
+            group: "server", summary: "Returns the number of keys in the database.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::dbsize_command),
+        },
+        CommandSpec {
+            name: "flushdb", arity: -1, flags: &[Write], first_key: 0, last_key: 0, step: 0,
+            group: "server", summary: "Removes all keys from the current database.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::flushall_command),
+        },
+        CommandSpec {
+            name: "flushall", arity: -1, flags: &[Write], first_key: 0, last_key: 0, step: 0,
+            group: "server", summary: "Removes all keys from all databases.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::flushall_command),
+        },
+        CommandSpec {
+            name: "randomkey", arity: 1, flags: &[ReadOnly], first_key: 0, last_key: 0, step: 0,
+            group: "keyspace", summary: "Returns a random key name from the database.", since: "1.0.0",
+            handler: Handler::Keyspace(keyspace::randomkey_command),
+        },
+        CommandSpec {
+            name: "scan", arity: -2, flags: &[ReadOnly], first_key: 0, last_key: 0, step: 0,
+            group: "keyspace", summary: "Iterates over the key names in the database.", since: "2.8.0",
+            handler: Handler::Keyspace(keyspace::scan_command),
+        },
+        CommandSpec {
+            name: "getdel", arity: 2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Returns the string value of a key after deleting the key.", since: "6.2.0",
+            handler: Handler::Keyspace(strings::getdel_command),
+        },
+        CommandSpec {
+            name: "getex", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Returns the string value of a key after setting its expiration time.", since: "6.2.0",
+            handler: Handler::Keyspace(strings::getex_command),
+        },
+        CommandSpec {
+            name: "mget", arity: -2, flags: &[ReadOnly, Fast], first_key: 1, last_key: -1, step: 1,
+            group: "string", summary: "Atomically returns the string values of one or more keys.", since: "1.0.0",
+            handler: Handler::Keyspace(strings::mget_command),
+        },
+        CommandSpec {
+            name: "mset", arity: -3, flags: &[Write], first_key: 1, last_key: -1, step: 2,
+            group: "string", summary: "Atomically creates or modifies the string values of one or more keys.", since: "1.0.1",
+            handler: Handler::Keyspace(strings::mset_command),
+        },
+        CommandSpec {
+            name: "msetnx", arity: -3, flags: &[Write], first_key: 1, last_key: -1, step: 2,
+            group: "string", summary: "Atomically modifies the string values of one or more keys only when all keys don't exist.", since: "1.0.1",
+            handler: Handler::Keyspace(strings::msetnx_command),
+        },
+        CommandSpec {
+            name: "strlen", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Returns the length of a string value.", since: "2.2.0",
+            handler: Handler::Keyspace(strings::strlen_command),
+        },
+        CommandSpec {
+            name: "append", arity: 3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Appends a string to the value of a key. Creates the key if it doesn't exist.", since: "2.0.0",
+            handler: Handler::Keyspace(strings::append_command),
+        },
+        CommandSpec {
+            name: "getrange", arity: 4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Returns a substring of the string stored at a key.", since: "2.4.0",
+            handler: Handler::Keyspace(strings::getrange_command),
+        },
+        CommandSpec {
+            name: "setrange", arity: 4, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.", since: "2.2.0",
+            handler: Handler::Keyspace(strings::setrange_command),
+        },
+        CommandSpec {
+            name: "incr", arity: 2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(strings::incr_command),
+        },
+        CommandSpec {
+            name: "decr", arity: 2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(strings::decr_command),
+        },
+        CommandSpec {
+            name: "incrby", arity: 3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(strings::incrby_command),
+        },
+        CommandSpec {
+            name: "decrby", arity: 3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(strings::decrby_command),
+        },
+        CommandSpec {
+            name: "incrbyfloat", arity: 3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "string", summary: "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist.", since: "2.6.0",
+            handler: Handler::Keyspace(strings::incrbyfloat_command),
+        },
+        CommandSpec {
+            name: "object", arity: -2, flags: &[ReadOnly], first_key: 2, last_key: 2, step: 1,
+            group: "keyspace", summary: "A container for object introspection commands.", since: "2.2.3",
+            handler: Handler::Keyspace(keyspace::object_command),
+        },
+        CommandSpec {
+            name: "lpush", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Prepends one or more elements to a list. Creates the key if it doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::lpush_command),
+        },
+        CommandSpec {
+            name: "rpush", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Appends one or more elements to a list. Creates the key if it doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::rpush_command),
+        },
+        CommandSpec {
+            name: "lpushx", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Prepends one or more elements to a list only when the list exists.", since: "2.2.0",
+            handler: Handler::Keyspace(lists::lpushx_command),
+        },
+        CommandSpec {
+            name: "rpushx", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Appends an element to a list only when the list exists.", since: "2.2.0",
+            handler: Handler::Keyspace(lists::rpushx_command),
+        },
+        CommandSpec {
+            name: "lpop", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::lpop_command),
+        },
+        CommandSpec {
+            name: "rpop", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Returns and removes the last elements of a list. Deletes the list if the last element was popped.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::rpop_command),
+        },
+        CommandSpec {
+            name: "llen", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Returns the length of a list.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::llen_command),
+        },
+        CommandSpec {
+            name: "lrange", arity: 4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Returns a range of elements from a list.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::lrange_command),
+        },
+        CommandSpec {
+            name: "lindex", arity: 3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Returns an element from a list by its index.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::lindex_command),
+        },
+        CommandSpec {
+            name: "lset", arity: 4, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Sets the value of an element in a list by its index.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::lset_command),
+        },
+        CommandSpec {
+            name: "lrem", arity: 4, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Removes elements from a list. Deletes the list if the last element was removed.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::lrem_command),
+        },
+        CommandSpec {
+            name: "ltrim", arity: 4, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Removes elements from both ends a list. Deletes the list if all elements were trimmed.", since: "1.0.0",
+            handler: Handler::Keyspace(lists::ltrim_command),
+        },
+        CommandSpec {
+            name: "linsert", arity: 5, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Inserts an element before or after another element in a list.", since: "2.2.0",
+            handler: Handler::Keyspace(lists::linsert_command),
+        },
+        CommandSpec {
+            name: "lpos", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "list", summary: "Returns the index of matching elements in a list.", since: "6.0.6",
+            handler: Handler::Keyspace(lists::lpos_command),
+        },
+        CommandSpec {
+            name: "lmove", arity: 5, flags: &[Write], first_key: 1, last_key: 2, step: 1,
+            group: "list", summary: "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last element was moved.", since: "6.2.0",
+            handler: Handler::Keyspace(lists::lmove_command),
+        },
+        CommandSpec {
+            name: "rpoplpush", arity: 3, flags: &[Write], first_key: 1, last_key: 2, step: 1,
+            group: "list", summary: "Returns the last element of a list after removing and pushing it to another list. Deletes the list if the last element was popped.", since: "1.2.0",
+            handler: Handler::Keyspace(lists::rpoplpush_command),
+        },
+        CommandSpec {
+            name: "blpop", arity: -3, flags: &[Write, Blocking], first_key: 1, last_key: -2, step: 1,
+            group: "list", summary: "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.", since: "2.0.0",
+            handler: session_handler!(lists::blpop_command),
+        },
+        CommandSpec {
+            name: "brpop", arity: -3, flags: &[Write, Blocking], first_key: 1, last_key: -2, step: 1,
+            group: "list", summary: "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.", since: "2.0.0",
+            handler: session_handler!(lists::brpop_command),
+        },
+        CommandSpec {
+            name: "blmove", arity: 6, flags: &[Write, Blocking], first_key: 1, last_key: 2, step: 1,
+            group: "list", summary: "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise. Deletes the list if the last element was moved.", since: "6.2.0",
+            handler: session_handler!(lists::blmove_command),
+        },
+        CommandSpec {
+            name: "brpoplpush", arity: 4, flags: &[Write, Blocking], first_key: 1, last_key: 2, step: 1,
+            group: "list", summary: "Pops an element from a list, pushes it to another list and returns it. Block until an element is available otherwise. Deletes the list if the last element was popped.", since: "2.2.0",
+            handler: session_handler!(lists::brpoplpush_command),
+        },
+        CommandSpec {
+            name: "hset", arity: -4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Creates or modifies the value of a field in a hash.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hset_command),
+        },
+        CommandSpec {
+            name: "hmset", arity: -4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Sets the values of multiple fields.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hmset_command),
+        },
+        CommandSpec {
+            name: "hsetnx", arity: 4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Sets the value of a field in a hash only when the field doesn't exist.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hsetnx_command),
+        },
+        CommandSpec {
+            name: "hget", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns the value of a field in a hash.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hget_command),
+        },
+        CommandSpec {
+            name: "hmget", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns the values of all fields in a hash.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hmget_command),
+        },
+        CommandSpec {
+            name: "hdel", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hdel_command),
+        },
+        CommandSpec {
+            name: "hgetall", arity: 2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns all fields and values in a hash.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hgetall_command),
+        },
+        CommandSpec {
+            name: "hkeys", arity: 2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns all fields in a hash.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hkeys_command),
+        },
+        CommandSpec {
+            name: "hvals", arity: 2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns all values in a hash.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hvals_command),
+        },
+        CommandSpec {
+            name: "hlen", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns the number of fields in a hash.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hlen_command),
+        },
+        CommandSpec {
+            name: "hexists", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Determines whether a field exists in a hash.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hexists_command),
+        },
+        CommandSpec {
+            name: "hstrlen", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns the length of the value of a field.", since: "3.2.0",
+            handler: Handler::Keyspace(hashes::hstrlen_command),
+        },
+        CommandSpec {
+            name: "hincrby", arity: 4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist.", since: "2.0.0",
+            handler: Handler::Keyspace(hashes::hincrby_command),
+        },
+        CommandSpec {
+            name: "hincrbyfloat", arity: 4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Increments the floating point value of a field by a number. Uses 0 as initial value if the field doesn't exist.", since: "2.6.0",
+            handler: Handler::Keyspace(hashes::hincrbyfloat_command),
+        },
+        CommandSpec {
+            name: "hrandfield", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns one or more random fields from a hash.", since: "6.2.0",
+            handler: Handler::Keyspace(hashes::hrandfield_command),
+        },
+        CommandSpec {
+            name: "hscan", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Iterates over fields and values of a hash.", since: "2.8.0",
+            handler: Handler::Keyspace(hashes::hscan_command),
+        },
+        CommandSpec {
+            name: "hexpire", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Set expiry for hash field using relative time to expire (seconds)", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::hexpire_command),
+        },
+        CommandSpec {
+            name: "hpexpire", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Set expiry for hash field using relative time to expire (milliseconds)", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::hpexpire_command),
+        },
+        CommandSpec {
+            name: "hexpireat", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Set expiry for hash field using an absolute Unix timestamp (seconds)", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::hexpireat_command),
+        },
+        CommandSpec {
+            name: "hpexpireat", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Set expiry for hash field using an absolute Unix timestamp (milliseconds)", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::hpexpireat_command),
+        },
+        CommandSpec {
+            name: "httl", arity: -5, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns the TTL in seconds of a hash field.", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::httl_command),
+        },
+        CommandSpec {
+            name: "hpttl", arity: -5, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns the TTL in milliseconds of a hash field.", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::hpttl_command),
+        },
+        CommandSpec {
+            name: "hexpiretime", arity: -5, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns the expiration time of a hash field as a Unix timestamp, in seconds.", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::hexpiretime_command),
+        },
+        CommandSpec {
+            name: "hpexpiretime", arity: -5, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Returns the expiration time of a hash field as a Unix timestamp, in msec.", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::hpexpiretime_command),
+        },
+        CommandSpec {
+            name: "hpersist", arity: -5, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "hash", summary: "Removes the expiration time for each specified field", since: "7.4.0",
+            handler: Handler::Keyspace(hashes::hpersist_command),
+        },
+        CommandSpec {
+            name: "sadd", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Adds one or more members to a set. Creates the key if it doesn't exist.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::sadd_command),
+        },
+        CommandSpec {
+            name: "srem", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Removes one or more members from a set. Deletes the set if the last member was removed.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::srem_command),
+        },
+        CommandSpec {
+            name: "smembers", arity: 2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Returns all members of a set.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::smembers_command),
+        },
+        CommandSpec {
+            name: "sismember", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Determines whether a member belongs to a set.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::sismember_command),
+        },
+        CommandSpec {
+            name: "smismember", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Determines whether multiple members belong to a set.", since: "6.2.0",
+            handler: Handler::Keyspace(sets::smismember_command),
+        },
+        CommandSpec {
+            name: "scard", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Returns the number of members in a set.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::scard_command),
+        },
+        CommandSpec {
+            name: "spop", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Returns one or more random members from a set after removing them. Deletes the set if the last member was popped.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::spop_command),
+        },
+        CommandSpec {
+            name: "srandmember", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Get one or multiple random members from a set", since: "1.0.0",
+            handler: Handler::Keyspace(sets::srandmember_command),
+        },
+        CommandSpec {
+            name: "smove", arity: 4, flags: &[Write, Fast], first_key: 1, last_key: 2, step: 1,
+            group: "set", summary: "Moves a member from one set to another.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::smove_command),
+        },
+        CommandSpec {
+            name: "sinter", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: -1, step: 1,
+            group: "set", summary: "Returns the intersect of multiple sets.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::sinter_command),
+        },
+        CommandSpec {
+            name: "sunion", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: -1, step: 1,
+            group: "set", summary: "Returns the union of multiple sets.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::sunion_command),
+        },
+        CommandSpec {
+            name: "sdiff", arity: -2, flags: &[ReadOnly], first_key: 1, last_key: -1, step: 1,
+            group: "set", summary: "Returns the difference of multiple sets.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::sdiff_command),
+        },
+        CommandSpec {
+            name: "sinterstore", arity: -3, flags: &[Write], first_key: 1, last_key: -1, step: 1,
+            group: "set", summary: "Stores the intersect of multiple sets in a key.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::sinterstore_command),
+        },
+        CommandSpec {
+            name: "sunionstore", arity: -3, flags: &[Write], first_key: 1, last_key: -1, step: 1,
+            group: "set", summary: "Stores the union of multiple sets in a key.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::sunionstore_command),
+        },
+        CommandSpec {
+            name: "sdiffstore", arity: -3, flags: &[Write], first_key: 1, last_key: -1, step: 1,
+            group: "set", summary: "Stores the difference of multiple sets in a key.", since: "1.0.0",
+            handler: Handler::Keyspace(sets::sdiffstore_command),
+        },
+        CommandSpec {
+            name: "sintercard", arity: -3, flags: &[ReadOnly], first_key: 0, last_key: 0, step: 0,
+            group: "set", summary: "Returns the number of members of the intersect of multiple sets.", since: "7.0.0",
+            handler: Handler::Keyspace(sets::sintercard_command),
+        },
+        CommandSpec {
+            name: "sscan", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "set", summary: "Iterates over members of a set.", since: "2.8.0",
+            handler: Handler::Keyspace(sets::sscan_command),
+        },
+        CommandSpec {
+            name: "zadd", arity: -4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.", since: "1.2.0",
+            handler: Handler::Keyspace(sorted_sets::zadd_command),
+        },
+        CommandSpec {
+            name: "zincrby", arity: 4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Increments the score of a member in a sorted set.", since: "1.2.0",
+            handler: Handler::Keyspace(sorted_sets::zincrby_command),
+        },
+        CommandSpec {
+            name: "zrem", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed.", since: "1.2.0",
+            handler: Handler::Keyspace(sorted_sets::zrem_command),
+        },
+        CommandSpec {
+            name: "zcard", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the number of members in a sorted set.", since: "1.2.0",
+            handler: Handler::Keyspace(sorted_sets::zcard_command),
+        },
+        CommandSpec {
+            name: "zscore", arity: 3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the score of a member in a sorted set.", since: "1.2.0",
+            handler: Handler::Keyspace(sorted_sets::zscore_command),
+        },
+        CommandSpec {
+            name: "zmscore", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the score of one or more members in a sorted set.", since: "6.2.0",
+            handler: Handler::Keyspace(sorted_sets::zmscore_command),
+        },
+        CommandSpec {
+            name: "zrank", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the index of a member in a sorted set ordered by ascending scores.", since: "2.0.0",
+            handler: Handler::Keyspace(sorted_sets::zrank_command),
+        },
+        CommandSpec {
+            name: "zrevrank", arity: -3, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the index of a member in a sorted set ordered by descending scores.", since: "2.0.0",
+            handler: Handler::Keyspace(sorted_sets::zrevrank_command),
+        },
+        CommandSpec {
+            name: "zrange", arity: -4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns members in a sorted set within a range of indexes.", since: "1.2.0",
+            handler: Handler::Keyspace(sorted_sets::zrange_command),
+        },
+        CommandSpec {
+            name: "zrangestore", arity: -5, flags: &[Write], first_key: 1, last_key: 2, step: 1,
+            group: "sorted_set", summary: "Stores a range of members from sorted set in a key.", since: "6.2.0",
+            handler: Handler::Keyspace(sorted_sets::zrangestore_command),
+        },
+        CommandSpec {
+            name: "zcount", arity: 4, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the count of members in a sorted set that have scores within a range.", since: "2.0.0",
+            handler: Handler::Keyspace(sorted_sets::zcount_command),
+        },
+        CommandSpec {
+            name: "zlexcount", arity: 4, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the number of members in a sorted set within a lexicographical range.", since: "2.8.9",
+            handler: Handler::Keyspace(sorted_sets::zlexcount_command),
+        },
+        CommandSpec {
+            name: "zpopmin", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.", since: "5.0.0",
+            handler: Handler::Keyspace(sorted_sets::zpopmin_command),
+        },
+        CommandSpec {
+            name: "zpopmax", arity: -2, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.", since: "5.0.0",
+            handler: Handler::Keyspace(sorted_sets::zpopmax_command),
+        },
+        CommandSpec {
+            name: "bzpopmin", arity: -3, flags: &[Write, Fast, Blocking], first_key: 1, last_key: -2, step: 1,
+            group: "sorted_set", summary: "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.", since: "5.0.0",
+            handler: session_handler!(sorted_sets::bzpopmin_command),
+        },
+        CommandSpec {
+            name: "bzpopmax", arity: -3, flags: &[Write, Fast, Blocking], first_key: 1, last_key: -2, step: 1,
+            group: "sorted_set", summary: "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.", since: "5.0.0",
+            handler: session_handler!(sorted_sets::bzpopmax_command),
+        },
+        CommandSpec {
+            name: "zunionstore", arity: -4, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Stores the union of multiple sorted sets in a key.", since: "2.0.0",
+            handler: Handler::Keyspace(sorted_sets::zunionstore_command),
+        },
+        CommandSpec {
+            name: "zinterstore", arity: -4, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Stores the intersect of multiple sorted sets in a key.", since: "2.0.0",
+            handler: Handler::Keyspace(sorted_sets::zinterstore_command),
+        },
+        CommandSpec {
+            name: "zscan", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "sorted_set", summary: "Iterates over members and scores of a sorted set.", since: "2.8.0",
+            handler: Handler::Keyspace(sorted_sets::zscan_command),
+        },
+        CommandSpec {
+            name: "xadd", arity: -5, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Appends a new message to a stream. Creates the key if it doesn't exist.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xadd_command),
+        },
+        CommandSpec {
+            name: "xtrim", arity: -4, flags: &[Write], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Deletes messages from the beginning of a stream.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xtrim_command),
+        },
+        CommandSpec {
+            name: "xlen", arity: 2, flags: &[ReadOnly, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Return the number of messages in a stream.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xlen_command),
+        },
+        CommandSpec {
+            name: "xrange", arity: -4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Returns the messages from a stream within a range of IDs.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xrange_command),
+        },
+        CommandSpec {
+            name: "xrevrange", arity: -4, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Returns the messages from a stream within a range of IDs in reverse order.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xrevrange_command),
+        },
+        CommandSpec {
+            name: "xdel", arity: -3, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Returns the number of messages after removing them from a stream.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xdel_command),
+        },
+        CommandSpec {
+            name: "xread", arity: -4, flags: &[ReadOnly, Blocking], first_key: 0, last_key: 0, step: 0,
+            group: "stream", summary: "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise.", since: "5.0.0",
+            handler: session_handler!(streams::xread_command),
+        },
+        CommandSpec {
+            name: "xreadgroup", arity: -7, flags: &[Write, Blocking], first_key: 0, last_key: 0, step: 0,
+            group: "stream", summary: "Returns new or historical messages from a stream for a consumer in a group. Blocks until a message is available otherwise.", since: "5.0.0",
+            handler: session_handler!(streams::xreadgroup_command),
+        },
+        CommandSpec {
+            name: "xack", arity: -4, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Returns the number of messages that were successfully acknowledged by the consumer group member of a stream.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xack_command),
+        },
+        CommandSpec {
+            name: "xpending", arity: -3, flags: &[ReadOnly], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Returns the information and entries from a stream consumer group's pending entries list.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xpending_command),
+        },
+        CommandSpec {
+            name: "xclaim", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Changes, or acquires, ownership of a message in a consumer group, as if the message was delivered a consumer group member.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xclaim_command),
+        },
+        CommandSpec {
+            name: "xautoclaim", arity: -6, flags: &[Write, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "stream", summary: "Changes, or acquires, ownership of messages in a consumer group, as if the messages were delivered to as consumer group member.", since: "6.2.0",
+            handler: Handler::Keyspace(streams::xautoclaim_command),
+        },
+        CommandSpec {
+            name: "xgroup", arity: -2, flags: &[Write], first_key: 2, last_key: 2, step: 1,
+            group: "stream", summary: "Creates, destroys and manages consumer groups and their consumers.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xgroup_command),
+        },
+        CommandSpec {
+            name: "xinfo", arity: -2, flags: &[ReadOnly], first_key: 2, last_key: 2, step: 1,
+            group: "stream", summary: "Returns information about a stream, its consumer groups or their consumers.", since: "5.0.0",
+            handler: Handler::Keyspace(streams::xinfo_command),
+        },
+        CommandSpec {
+            name: "subscribe", arity: -2, flags: &[PubSub, NoScript, Loading, Stale], first_key: 0, last_key: 0, step: 0,
+            group: "pubsub", summary: "Listens for messages published to channels.", since: "2.0.0",
+            handler: session_handler!(pubsub::subscribe_command),
+        },
+        CommandSpec {
+            name: "psubscribe", arity: -2, flags: &[PubSub, NoScript, Loading, Stale], first_key: 0, last_key: 0, step: 0,
+            group: "pubsub", summary: "Listens for messages published to channels that match one or more patterns.", since: "2.0.0",
+            handler: session_handler!(pubsub::psubscribe_command),
+        },
+        CommandSpec {
+            name: "ssubscribe", arity: -2, flags: &[PubSub, NoScript, Loading, Stale], first_key: 1, last_key: -1, step: 1,
+            group: "pubsub", summary: "Listens for messages published to shard channels.", since: "7.0.0",
+            handler: session_handler!(pubsub::ssubscribe_command),
+        },
+        CommandSpec {
+            name: "unsubscribe", arity: -1, flags: &[PubSub, NoScript, Loading, Stale], first_key: 0, last_key: 0, step: 0,
+            group: "pubsub", summary: "Stops listening to messages posted to channels.", since: "2.0.0",
+            handler: session_handler!(pubsub::unsubscribe_command),
+        },
+        CommandSpec {
+            name: "punsubscribe", arity: -1, flags: &[PubSub, NoScript, Loading, Stale], first_key: 0, last_key: 0, step: 0,
+            group: "pubsub", summary: "Stops listening to messages published to channels that match one or more patterns.", since: "2.0.0",
+            handler: session_handler!(pubsub::punsubscribe_command),
+        },
+        CommandSpec {
+            name: "sunsubscribe", arity: -1, flags: &[PubSub, NoScript, Loading, Stale], first_key: 1, last_key: -1, step: 1,
+            group: "pubsub", summary: "Stops listening to messages posted to shard channels.", since: "7.0.0",
+            handler: session_handler!(pubsub::sunsubscribe_command),
+        },
+        CommandSpec {
+            name: "publish", arity: 3, flags: &[PubSub, Loading, Stale, Fast], first_key: 0, last_key: 0, step: 0,
+            group: "pubsub", summary: "Posts a message to a channel.", since: "2.0.0",
+            handler: Handler::Keyspace(pubsub::publish_command),
+        },
+        CommandSpec {
+            name: "spublish", arity: 3, flags: &[PubSub, Loading, Stale, Fast], first_key: 1, last_key: 1, step: 1,
+            group: "pubsub", summary: "Post a message to a shard channel", since: "7.0.0",
+            handler: Handler::Keyspace(pubsub::spublish_command),
+        },
+        CommandSpec {
+            name: "pubsub", arity: -2, flags: &[PubSub, Loading, Stale], first_key: 0, last_key: 0, step: 0,
+            group: "pubsub", summary: "Inspects the state of the Pub/Sub subsystem.", since: "2.8.0",
+            handler: Handler::Keyspace(pubsub::pubsub_command),
+        },
+        CommandSpec {
+            name: "multi", arity: 1, flags: &[NoScript, Loading, Stale, Fast], first_key: 0, last_key: 0, step: 0,
+            group: "transactions", summary: "Starts a transaction.", since: "1.2.0",
+            handler: session_handler!(transactions::multi_command),
+        },
+        CommandSpec {
+            name: "exec", arity: 1, flags: &[NoScript, Loading, Stale], first_key: 0, last_key: 0, step: 0,
+            group: "transactions", summary: "Executes all commands in a transaction.", since: "1.2.0",
+            handler: session_handler!(transactions::exec_command),
+        },
+        CommandSpec {
+            name: "discard", arity: 1, flags: &[NoScript, Loading, Stale, Fast], first_key: 0, last_key: 0, step: 0,
+            group: "transactions", summary: "Discards a transaction.", since: "2.0.0",
+            handler: session_handler!(transactions::discard_command),
+        },
+        CommandSpec {
+            name: "watch", arity: -2, flags: &[NoScript, Loading, Stale, Fast], first_key: 1, last_key: -1, step: 1,
+            group: "transactions", summary: "Monitors changes to keys to determine the execution of a transaction.", since: "2.2.0",
+            handler: session_handler!(transactions::watch_command),
+        },
+        CommandSpec {
+            name: "unwatch", arity: 1, flags: &[NoScript, Loading, Stale, Fast], first_key: 0, last_key: 0, step: 0,
+            group: "transactions", summary: "Forgets about watched keys of a transaction.", since: "2.2.0",
+            handler: session_handler!(transactions::unwatch_command),
+        },
+    ]
+}
+
+fn table() -> &'static HashMap<&'static str, CommandSpec> {
+    static TABLE: OnceLock<HashMap<&'static str, CommandSpec>> = OnceLock::new();
+
+    TABLE.get_or_init(|| command_specs().into_iter().map(|spec| (spec.name, spec)).collect())
+}
+
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    table().get(name.to_lowercase().as_str())
+}
+
+pub fn all() -> impl Iterator<Item=&'static CommandSpec> {
+    table().values()
+}
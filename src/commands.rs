@@ -1,89 +1,544 @@
-use crate::command_handler::{Command, CommandHandler, WriteData};
-use crate::server::{unpack_bulk_str, ServerConfig};
-use crate::storage::Storage;
-use crate::util::generate_random_string;
-use chrono::Local;
+pub mod hashes;
+pub mod keyspace;
+pub mod lists;
+pub mod pubsub;
+pub mod sets;
+pub mod sorted_sets;
+pub mod streams;
+pub mod strings;
+pub mod transactions;
+
+use crate::command_handler::{Command, Protocol, WriteData};
+use crate::command_table::{self, CommandSpec};
+use crate::error::CommandError;
+use crate::rdb;
+use crate::server::{propagate, unpack_bulk_bytes, unpack_bulk_str, Replica, ServerConfig, REPLICA_OUTPUT_BUFFER_LIMIT};
+use crate::session::Session;
+use crate::storage::{BlockedOp, EventClasses, Storage};
+use crate::util::{glob_match, parse_memory};
+use bytes::Bytes;
 use itertools::join;
-use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
 use tokio::sync::Mutex;
 
-pub async fn ping_command(command_handler: &mut CommandHandler) {
-    command_handler.write(WriteData::Command(Command::SimpleString("PONG".to_string()))).await.unwrap()
+pub const SERVER_VERSION: &str = "7.2.0";
+
+pub type CommandResult = Result<(), CommandError>;
+
+pub async fn ping_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    if args.len() > 1 {
+        return Err(CommandError::wrong_number_of_arguments("ping"));
+    }
+
+    // A subscribed RESP2 connection gets PONG in the shape of a message
+    let command = if session.in_subscribe_mode() && session.command_handler.protocol == Protocol::Resp2 {
+        Command::Array(vec![Command::bulk("pong"), args.first().cloned().unwrap_or(Command::bulk(""))])
+    } else {
+        args.first().cloned().unwrap_or(Command::SimpleString("PONG".to_string()))
+    };
+
+    reply(session, command).await
+}
+
+pub async fn echo_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    reply(session, args[0].clone()).await
 }
 
-pub async fn echo_command(command_handler: &mut CommandHandler, args: &Vec<Command>) {
-    command_handler.write(WriteData::Command(args.first().unwrap().clone())).await.unwrap()
+/// QUIT: replies OK, after which the connection is closed.
+pub async fn quit_command(session: &mut Session, _args: &[Command]) -> CommandResult {
+    session.closing = true;
+    reply(session, Command::SimpleString("OK".to_string())).await
 }
 
-pub async fn set_command(command_handler: &mut CommandHandler, args: &Vec<Command>, storage: &Arc<Mutex<Storage>>) {
-    let k = unpack_bulk_str(args[0].clone()).unwrap();
-    let v = unpack_bulk_str(args[1].clone()).unwrap();
-    let mut exp_at: i64 = 0;
+/// RESET: leaves the connection as it was when opened, out of subscribe mode
+/// and any transaction, with no watched keys and back on RESP2.
+pub async fn reset_command(session: &mut Session, _args: &[Command]) -> CommandResult {
+    session.transaction = None;
+    session.unwatch_all().await;
+    session.unsubscribe_all().await;
+    session.command_handler.protocol = Protocol::Resp2;
+    reply(session, Command::SimpleString("RESET".to_string())).await
+}
 
-    if 2 < args.len() {
-        let next_arg = unpack_bulk_str(args[2].clone()).unwrap();
+pub async fn hello_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let mut args = args.iter().map(|arg| unpack_bulk_str(arg.clone()).unwrap_or_default());
 
-        if next_arg == String::from("px") {
-            let ttl_arg = unpack_bulk_str(args[3].clone()).unwrap();
-            exp_at = Local::now().timestamp_millis() + ttl_arg.parse::<i64>().unwrap_or_else(|_| 0)
+    let protocol = match args.next() {
+        None => session.command_handler.protocol,
+        Some(version) => match version.as_str() {
+            "2" => Protocol::Resp2,
+            "3" => Protocol::Resp3,
+            _ => return Err(CommandError::Custom(String::from("NOPROTO unsupported protocol version")))
+        }
+    };
+
+    // There is no ACL support, so AUTH credentials and client names are
+    // validated for shape only.
+    while let Some(option) = args.next() {
+        let consumed = match option.to_lowercase().as_str() {
+            "auth" => args.next().and(args.next()).is_some(),
+            "setname" => args.next().is_some(),
+            _ => false
+        };
+
+        if !consumed {
+            return Err(CommandError::Other(format!("Syntax error in HELLO option '{}'", option)));
         }
     }
-    storage.lock().await.set((k, v), exp_at);
 
+    session.command_handler.protocol = protocol;
+
+    let role = match session.config.lock().await.replica_of {
+        Some(_) => "replica",
+        None => "master"
+    };
+    let proto = match protocol {
+        Protocol::Resp2 => 2,
+        Protocol::Resp3 => 3
+    };
 
-    command_handler.write(WriteData::Command(Command::SimpleString("OK".to_string()))).await.unwrap();
+    let command = Command::Map(vec![
+        (Command::bulk("server"), Command::bulk("redis")),
+        (Command::bulk("version"), Command::bulk(SERVER_VERSION)),
+        (Command::bulk("proto"), Command::Integer(proto)),
+        (Command::bulk("id"), Command::Integer(session.command_handler.id as i64)),
+        (Command::bulk("mode"), Command::bulk("standalone")),
+        (Command::bulk("role"), Command::bulk(role)),
+        (Command::bulk("modules"), Command::Array(vec![])),
+    ]);
+
+    reply(session, command).await
 }
 
-pub async fn get_command(command_handler: &mut CommandHandler, key: &String, storage: &Arc<Mutex<Storage>>) {
-    let command = match storage.lock().await.get(key) {
-        Some(record) => {
-            Command::SimpleString(record.value.clone())
+pub async fn info_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let mut sections: Vec<String> = vec![];
+    for i in 0..args.len() {
+        sections.push(arg_str(args, i)?.to_lowercase())
+    }
+    let (stats, keyspace) = {
+        let storage = session.lock_storage().await;
+        (get_stats_info(&storage), get_keyspace_info(&storage))
+    };
+    let section_map = [
+        (String::from("stats"), stats),
+        (String::from("replication"), get_replication_info(&session.config).await),
+        (String::from("keyspace"), keyspace),
+    ];
+
+    // With no section (or "all"/"default") every known section is returned;
+    // unknown sections are skipped like Redis does.
+    let info = if sections.is_empty() || sections.iter().any(|s| s == "all" || s == "default" || s == "everything") {
+        join(section_map.iter().map(|(_, info)| info), "\n\n")
+    } else {
+        join(section_map.iter().filter(|(name, _)| sections.contains(name)).map(|(_, info)| info), "\n\n")
+    };
+
+    reply(session, Command::bulk(info)).await
+}
+
+pub async fn replconf_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let arg = arg_str(args, 0)?;
+    let val = arg_str(args, 1)?;
+
+    if arg.eq_ignore_ascii_case("listening-port") {
+        let host = session.command_handler.stream.peer_addr().map_err(anyhow::Error::from)?;
+        let id = session.command_handler.id;
+        session.config.lock().await.replicas.entry(id).or_default().address = format!("{}:{}", host.ip(), val);
+    } else if arg.eq_ignore_ascii_case("ack") {
+        // Acknowledgements are never replied to
+        let offset = arg_int(args, 1)?;
+        let id = session.command_handler.id;
+        session.config.lock().await.ack(id, offset);
+        return Ok(());
+    } else if arg.eq_ignore_ascii_case("getack") {
+        // Sent by the master, so this goes out even though the master link
+        // gets no replies otherwise. The offset only ever counts what the
+        // master has sent, as clients cannot write to a replica.
+        let offset = session.config.lock().await.replication_offset;
+        let ack = Command::Array(vec![Command::bulk("REPLCONF"), Command::bulk("ACK"), Command::bulk(offset.to_string())]);
+        return Ok(session.command_handler.write(WriteData::Command(ack)).await?);
+    }
+
+    reply(session, Command::SimpleString("OK".to_string())).await
+}
+
+/// WAIT numreplicas timeout
+pub async fn wait_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let needed = arg_int(args, 0)?;
+    let timeout = match arg_str(args, 1)?.parse::<i64>() {
+        Ok(timeout) if timeout < 0 => return Err(CommandError::Other(String::from("timeout is negative"))),
+        Ok(0) => None,
+        Ok(timeout) => Some(Duration::from_millis(timeout as u64)),
+        Err(_) => return Err(CommandError::Other(String::from("timeout is not an integer or out of range")))
+    };
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let in_exec = session.exec.is_some();
+
+    let (offset, acks) = {
+        let config = session.config.lock().await;
+
+        // A replica's offset is its master's, which its own clients never
+        // write to
+        if config.replica_of.is_some() {
+            return Err(CommandError::Other(String::from("WAIT cannot be used with replica instances.")));
         }
-        None => Command::BulkString(String::from(""))
+        (config.replication_offset, Arc::clone(&config.acks))
     };
 
-    command_handler.write(WriteData::Command(command)).await.unwrap()
+    let mut asked = false;
+    let count = loop {
+        let notified = acks.notified();
+        tokio::pin!(notified);
+
+        {
+            let mut config = session.config.lock().await;
+            let count = config.count_acks(offset);
+
+            // Commands run by EXEC never block
+            if count as i64 >= needed || in_exec {
+                break count;
+            }
+
+            // Replicas only ACK when asked, so ask once
+            if !asked {
+                config.propagate(vec![Command::Array(vec![Command::bulk("REPLCONF"), Command::bulk("GETACK"), Command::bulk("*")])]);
+                asked = true;
+            }
+
+            // Registered before the lock is released so no ACK slips past
+            notified.as_mut().enable();
+        }
+
+        let acked = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, notified).await.is_ok(),
+            None => {
+                notified.await;
+                true
+            }
+        };
+
+        if !acked {
+            break session.config.lock().await.count_acks(offset);
+        }
+    };
+
+    reply(session, Command::Integer(count as i64)).await
 }
 
-pub async fn info_command(command_handler: &mut CommandHandler, config: &Arc<Mutex<ServerConfig>>, args: &Vec<Command>) {
-    let mut sections: Vec<String> = vec![];
-    for section in args.iter() {
-        sections.push(unpack_bulk_str(section.clone()).unwrap())
+/// PSYNC replicationid offset
+pub async fn psync_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let requested_id = arg_str(args, 0)?;
+    let requested_offset = arg_str(args, 1)?.parse::<i64>().unwrap_or(-1);
+    let config = Arc::clone(&session.config);
+    let (id, stream) = (session.command_handler.id, session.subscriber.clone());
+    session.output.set_limit(REPLICA_OUTPUT_BUFFER_LIMIT);
+
+    // The snapshot or the backlog is read and the stream registered under the
+    // same storage lock, so the replica gets every write made after what it
+    // already has and none made before
+    let (sync, replication_id, offset) = {
+        let mut storage = session.lock_storage().await;
+        propagate(&mut storage, &config).await;
+
+        let mut config = config.lock().await;
+        let sync = match config.missing_since(&requested_id, requested_offset) {
+            Some(missing) => Sync::Partial(missing),
+            None => {
+                config.create_backlog();
+                Sync::Full(rdb::dump(&storage))
+            }
+        };
+        config.replicas.entry(id).or_default().stream = Some(stream);
+        (sync, config.replication_id.clone(), config.replication_offset)
+    };
+
+    match sync {
+        Sync::Partial(missing) => {
+            session.command_handler.write(WriteData::Command(Command::SimpleString(format!("CONTINUE {}", replication_id)))).await?;
+            session.command_handler.write(WriteData::Raw(missing)).await?;
+        }
+        Sync::Full(rdb) => {
+            session.command_handler.write(WriteData::Command(Command::SimpleString(format!("FULLRESYNC {} {}", replication_id, offset)))).await?;
+            session.command_handler.write(WriteData::String(format!("${}\r\n", rdb.len()))).await?;
+            session.command_handler.write(WriteData::Raw(rdb)).await?;
+        }
     }
-    let section_map = HashMap::from([
-        (String::from("replication"), get_replication_info(config).await)
-    ]);
 
+    Ok(())
+}
 
-    let command = if sections.len() == 1 {
-        Command::BulkString(section_map.get(&sections[0]).unwrap().clone())
-    } else {
-        Command::BulkString(String::from(""))
+/// How PSYNC brings a replica up to date.
+enum Sync {
+    /// The part of the stream the replica is missing, from the backlog
+    Partial(Vec<u8>),
+    /// A snapshot of the whole dataset
+    Full(Vec<u8>),
+}
+
+/// Parameters known to CONFIG GET and CONFIG SET.
+const CONFIG_PARAMETERS: &[&str] = &["notify-keyspace-events", "repl-backlog-size", "repl-timeout"];
+
+/// A validated CONFIG SET value, applied only once every pair has parsed.
+enum ConfigChange {
+    NotifyKeyspaceEvents(EventClasses),
+    ReplBacklogSize(usize),
+    ReplTimeout(Duration),
+}
+
+pub async fn config_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let subcommand = arg_str(args, 0)?.to_uppercase();
+
+    let command = match subcommand.as_str() {
+        "GET" if args.len() >= 2 => {
+            let mut patterns = vec![];
+            for i in 1..args.len() {
+                patterns.push(arg_bytes(args, i)?);
+            }
+
+            let mut values = vec![];
+            for name in CONFIG_PARAMETERS {
+                if patterns.iter().any(|pattern| glob_match(pattern, name.as_bytes())) {
+                    values.push((Command::bulk(*name), Command::bulk(config_value(session, name).await)));
+                }
+            }
+
+            Command::Map(values)
+        }
+        "SET" if args.len() >= 3 && !args.len().is_multiple_of(2) => {
+            let mut changes = vec![];
+            for i in (1..args.len()).step_by(2) {
+                changes.push(parse_config_change(&arg_str(args, i)?.to_lowercase(), &arg_str(args, i + 1)?)?);
+            }
+
+            for change in changes {
+                match change {
+                    ConfigChange::NotifyKeyspaceEvents(classes) => session.lock_storage().await.notify_keyspace_events = classes,
+                    ConfigChange::ReplBacklogSize(size) => session.config.lock().await.set_backlog_size(size),
+                    ConfigChange::ReplTimeout(timeout) => session.config.lock().await.repl_timeout = timeout,
+                }
+            }
+
+            Command::SimpleString("OK".to_string())
+        }
+        "HELP" if args.len() == 1 => Command::Array([
+            "CONFIG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "GET <pattern>",
+            "    Return parameters matching the glob-like <pattern> and their values.",
+            "SET <directive> <value>",
+            "    Set the configuration <directive> to <value>.",
+            "HELP",
+            "    Print this help.",
+        ].iter().map(|line| Command::SimpleString(line.to_string())).collect()),
+        "GET" | "SET" | "HELP" => return Err(CommandError::Other(format!(
+            "wrong number of arguments for 'config|{}' command", subcommand.to_lowercase()
+        ))),
+        _ => return Err(CommandError::Other(format!("unknown subcommand '{}'. Try CONFIG HELP.", arg_str(args, 0)?)))
     };
 
-    command_handler.write(WriteData::Command(command)).await.unwrap()
+    reply(session, command).await
 }
 
-pub async fn replconf_command(command_handler: &mut CommandHandler, config: &Arc<Mutex<ServerConfig>>, args: &Vec<Command>) {
-    let arg = unpack_bulk_str(args[0].clone()).unwrap();
-    let val = unpack_bulk_str(args[1].clone()).unwrap();
+async fn config_value(session: &mut Session, name: &str) -> String {
+    match name {
+        "notify-keyspace-events" => session.lock_storage().await.notify_keyspace_events.to_string(),
+        "repl-backlog-size" => session.config.lock().await.backlog_size.to_string(),
+        "repl-timeout" => session.config.lock().await.repl_timeout.as_secs().to_string(),
+        _ => String::new()
+    }
+}
+
+fn parse_config_change(name: &str, value: &str) -> Result<ConfigChange, CommandError> {
+    let failed = |reason: &str| CommandError::Other(format!(
+        "CONFIG SET failed (possibly related to argument '{}') - {}", name, reason
+    ));
+
+    match name {
+        "notify-keyspace-events" => EventClasses::parse(value)
+            .map(ConfigChange::NotifyKeyspaceEvents)
+            .ok_or_else(|| failed("Invalid event class character. Use 'Ag$lshzxeKEtmdn'.")),
+        "repl-backlog-size" => parse_memory(value)
+            .filter(|size| *size > 0)
+            .map(ConfigChange::ReplBacklogSize)
+            .ok_or_else(|| failed("argument must be a memory value")),
+        "repl-timeout" => value.parse::<u64>().ok()
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| ConfigChange::ReplTimeout(Duration::from_secs(seconds)))
+            .ok_or_else(|| failed("argument must be a positive number of seconds")),
+        _ => Err(CommandError::Other(format!("Unknown option or number of arguments for CONFIG SET - '{}'", name)))
+    }
+}
+
+pub async fn command_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let subcommand = match args.first() {
+        Some(_) => arg_str(args, 0)?.to_lowercase(),
+        None => {
+            let specs = command_table::all().map(command_info).collect();
+            return reply(session, Command::Array(specs)).await;
+        }
+    };
+
+    let command = match subcommand.as_str() {
+        "count" if args.len() == 1 => Command::Integer(command_table::all().count() as i64),
+        "info" => {
+            let specs = requested_specs(args)?;
+            Command::Array(specs.into_iter().map(|spec| spec.map_or(Command::NullArray, command_info)).collect())
+        }
+        "docs" => {
+            // Unknown names are skipped rather than answered with nil
+            let specs = requested_specs(args)?.into_iter().flatten();
+            Command::Map(specs.map(|spec| (Command::bulk(spec.name), command_docs(spec))).collect())
+        }
+        "count" => return Err(CommandError::wrong_number_of_arguments("command|count")),
+        _ => return Err(CommandError::Other(format!("unknown subcommand '{}'. Try COMMAND HELP.", subcommand)))
+    };
+
+    reply(session, command).await
+}
+
+/// Specs named after the subcommand, or the whole table when none are given.
+fn requested_specs(args: &[Command]) -> Result<Vec<Option<&'static CommandSpec>>, CommandError> {
+    if args.len() == 1 {
+        return Ok(command_table::all().map(Some).collect());
+    }
+
+    let mut specs = vec![];
+    for i in 1..args.len() {
+        specs.push(command_table::lookup(&arg_str(args, i)?));
+    }
+
+    Ok(specs)
+}
+
+fn command_info(spec: &CommandSpec) -> Command {
+    Command::Array(vec![
+        Command::bulk(spec.name),
+        Command::Integer(spec.arity as i64),
+        Command::Set(spec.flags.iter().map(|flag| Command::SimpleString(flag.as_str().to_string())).collect()),
+        Command::Integer(spec.first_key as i64),
+        Command::Integer(spec.last_key as i64),
+        Command::Integer(spec.step as i64),
+        Command::Set(spec.acl_categories().into_iter().map(Command::SimpleString).collect()),
+        Command::Array(vec![]),
+        Command::Array(vec![]),
+        Command::Array(vec![]),
+    ])
+}
+
+fn command_docs(spec: &CommandSpec) -> Command {
+    Command::Map(vec![
+        (Command::bulk("summary"), Command::bulk(spec.summary)),
+        (Command::bulk("since"), Command::bulk(spec.since)),
+        (Command::bulk("group"), Command::bulk(spec.group)),
+    ])
+}
+
+/// Writes `command` back to the client, or collects it for the EXEC reply.
+pub async fn reply(session: &mut Session, command: Command) -> CommandResult {
+    if session.master_link {
+        return Ok(());
+    }
+
+    if let Some(exec) = &mut session.exec {
+        exec.replies.push(command);
+        return Ok(());
+    }
+
+    Ok(session.command_handler.write(WriteData::Command(command)).await?)
+}
+
+/// Runs `op` right away, and if it has nothing to serve yet parks the client
+/// on `keys` until a write makes `op` succeed or `timeout` elapses, in which
+/// case `timeout_reply` is returned.
+pub async fn block_on(session: &mut Session, keys: Vec<Bytes>, timeout: Option<Duration>, mut op: BlockedOp, timeout_reply: Command) -> Result<Command, CommandError> {
+    let (id, mut receiver) = {
+        let in_exec = session.exec.is_some();
+        let mut storage = session.lock_storage().await;
+
+        if let Some(command) = op(&mut storage)? {
+            return Ok(command);
+        }
 
-    if arg == String::from("listening-port") {
-        let host = command_handler.stream.peer_addr().unwrap();
-        config.lock().await.replicas.insert(format!("{}:{}", host.ip(), val));
+        // Commands run by EXEC never block, they time out right away
+        if in_exec {
+            return Ok(timeout_reply);
+        }
+
+        storage.block(keys, op)
+    };
+
+    let wait = async {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut receiver).await.ok(),
+            None => Some((&mut receiver).await)
+        }
+    };
+    let served = tokio::select! {
+        served = wait => served,
+        _ = session.command_handler.disconnected() => None,
+    };
+
+    match served {
+        Some(Ok(reply)) => reply,
+        _ => {
+            // Deregister under the lock, then pick up a reply that may have
+            // been sent right before the timeout fired or the client left.
+            session.storage.lock().await.unblock(id);
+
+            receiver.try_recv().unwrap_or(Ok(timeout_reply))
+        }
+    }
+}
+
+pub fn arg_bytes(args: &[Command], index: usize) -> Result<Bytes, CommandError> {
+    match args.get(index) {
+        Some(arg) => unpack_bulk_bytes(arg.clone()).map_err(|_| CommandError::Syntax),
+        None => Err(CommandError::Syntax)
+    }
+}
+
+pub fn arg_str(args: &[Command], index: usize) -> Result<String, CommandError> {
+    String::from_utf8(arg_bytes(args, index)?.to_vec()).map_err(|_| CommandError::Syntax)
+}
+
+pub fn arg_int(args: &[Command], index: usize) -> Result<i64, CommandError> {
+    arg_str(args, index)?.parse::<i64>().map_err(|_| CommandError::NotAnInteger)
+}
+
+/// A blocking timeout in seconds; `0` blocks forever and yields `None`.
+pub fn arg_timeout(args: &[Command], index: usize) -> Result<Option<Duration>, CommandError> {
+    let timeout = arg_str(args, index)?
+        .parse::<f64>()
+        .ok()
+        .filter(|timeout| timeout.is_finite())
+        .ok_or_else(|| CommandError::Other(String::from("timeout is not a float or out of range")))?;
+
+    if timeout < 0.0 {
+        return Err(CommandError::Other(String::from("timeout is negative")));
     }
 
-    command_handler.write(WriteData::Command(Command::SimpleString("OK".to_string()))).await.unwrap()
+    Ok((timeout > 0.0).then(|| Duration::from_secs_f64(timeout)))
 }
 
-pub async fn psync_command(command_handler: &mut CommandHandler) {
-    let data = "524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
-    let rdb = hex::decode(data).unwrap_or_else(|_| panic!());
+fn get_stats_info(storage: &Storage) -> String {
+    join(vec![
+        String::from("# Stats"),
+        format!("expired_keys:{}", storage.stats.expired_keys),
+        format!("expired_stale_perc:{:.2}", storage.stats.expired_stale_perc),
+        format!("expire_cycle_cpu_milliseconds:{}", storage.stats.expire_cycle_cpu_milliseconds),
+        format!("expired_subkeys:{}", storage.stats.expired_subkeys),
+    ], "\n")
+}
 
-    command_handler.write(WriteData::Command(Command::SimpleString(format!("FULLRESYNC {} {}", generate_random_string(40), 0)))).await.unwrap();
-    command_handler.write(WriteData::String(String::from(format!("${}\r\n", rdb.len())))).await.unwrap();
-    command_handler.write(WriteData::Raw(rdb)).await.unwrap();
+fn get_keyspace_info(storage: &Storage) -> String {
+    let mut lines = vec![String::from("# Keyspace")];
+
+    if !storage._set.is_empty() {
+        lines.push(format!("db0:keys={},expires={},avg_ttl=0", storage._set.len(), storage.volatile_len()));
+    }
+
+    join(lines, "\n")
 }
 
 async fn get_replication_info(config: &Arc<Mutex<ServerConfig>>) -> String {
@@ -94,9 +549,35 @@ async fn get_replication_info(config: &Arc<Mutex<ServerConfig>>) -> String {
         None => String::from("role:master")
     };
 
-    join(vec![
-        role,
+    // Only replicas past PSYNC count as connected
+    let replicas: Vec<&Replica> = config.replicas.values().filter(|replica| replica.stream.is_some()).collect();
+
+    let mut lines = vec![String::from("# Replication"), role];
+    if let Some(replica_of) = &config.replica_of {
+        let link = &config.master_link;
+        lines.extend([
+            format!("master_host:{}", replica_of.host),
+            format!("master_port:{}", replica_of.port),
+            format!("master_link_status:{}", if link.up { "up" } else { "down" }),
+            format!("master_last_io_seconds_ago:{}", link.last_io.map_or(-1, |last_io| last_io.elapsed().as_secs() as i64)),
+            format!("master_sync_in_progress:{}", link.sync_in_progress as u8),
+        ]);
+    }
+    lines.push(format!("connected_slaves:{}", replicas.len()));
+    lines.extend(replicas.iter().enumerate().map(|(i, replica)| {
+        let (ip, port) = replica.address.rsplit_once(':').unwrap_or(("", ""));
+        format!("slave{}:ip={},port={},state=online,offset={}", i, ip, port, replica.acked_offset)
+    }));
+    lines.extend([
         format!("master_replid:{}", config.replication_id),
-        format!("master_repl_offset:{}", config.replication_offset)
-    ], "\n")
-}
\ No newline at end of file
+        format!("master_replid2:{}", config.replication_id2),
+        format!("master_repl_offset:{}", config.replication_offset),
+        format!("second_repl_offset:{}", config.second_replication_offset),
+        format!("repl_backlog_active:{}", config.backlog.is_some() as u8),
+        format!("repl_backlog_size:{}", config.backlog_size),
+        format!("repl_backlog_first_byte_offset:{}", config.backlog.as_ref().map_or(0, |backlog| backlog.first_byte_offset())),
+        format!("repl_backlog_histlen:{}", config.backlog.as_ref().map_or(0, |backlog| backlog.histlen())),
+    ]);
+
+    join(lines, "\n")
+}
@@ -0,0 +1,507 @@
+use crate::command_handler::{format_float_value, Command};
+use crate::commands::keyspace::{arg_cursor, scan_reply, ScanOptions, ScanTarget};
+use crate::commands::strings::parse_f64;
+use crate::commands::{arg_bytes, arg_int, arg_str};
+use crate::error::CommandError;
+use crate::storage::{EventClasses, HashField, HashValue, LiveFields, Storage, Value};
+use crate::util::{now_millis, parse_i64};
+use bytes::Bytes;
+
+/// Field TTLs are capped like in Redis, which stores them in 48 bits.
+const HASH_FIELD_MAX_EXPIRE: i64 = 1 << 48;
+
+#[derive(Clone, Copy)]
+enum ExpireUnit {
+    Seconds,
+    Milliseconds,
+}
+
+#[derive(Clone, Copy)]
+enum ExpireBase {
+    /// The argument is relative to now (HEXPIRE, HPEXPIRE)
+    Relative,
+    /// The argument is a Unix timestamp (HEXPIREAT, HPEXPIREAT)
+    Absolute,
+}
+
+/// HSET key field value [field value ...]
+pub fn hset_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let added = set_fields(storage, args, "hset")?;
+
+    Ok(Command::Integer(added as i64))
+}
+
+/// HMSET key field value [field value ...], the pre-4.0 spelling of HSET
+pub fn hmset_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_fields(storage, args, "hmset")?;
+
+    Ok(Command::SimpleString("OK".to_string()))
+}
+
+pub fn hsetnx_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let field = arg_bytes(args, 1)?;
+    let value = arg_bytes(args, 2)?;
+
+    let hash = storage.get_or_insert(&key, || Value::Hash(HashValue::new())).as_hash_mut()?;
+    if hash.contains_key(&field) {
+        return Ok(Command::Integer(0));
+    }
+
+    hash.insert(field, HashField::new(value));
+    storage.notify(EventClasses::HASH, "hset", &key);
+    Ok(Command::Integer(1))
+}
+
+pub fn hget_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let field = arg_bytes(args, 1)?;
+
+    Ok(match hash(storage, args)?.and_then(|hash| hash.get(&field)) {
+        Some(field) => Command::BulkString(field.value.clone()),
+        None => Command::NullBulkString
+    })
+}
+
+pub fn hmget_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let hash = hash(storage, args)?;
+
+    let mut values = vec![];
+    for i in 1..args.len() {
+        let field = arg_bytes(args, i)?;
+        values.push(match hash.and_then(|hash| hash.get(&field)) {
+            Some(field) => Command::BulkString(field.value.clone()),
+            None => Command::NullBulkString
+        });
+    }
+
+    Ok(Command::Array(values))
+}
+
+pub fn hdel_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let hash = match storage.get_mut(&key) {
+        Some(record) => record.value.as_hash_mut()?,
+        None => return Ok(Command::Integer(0))
+    };
+
+    let mut removed = 0;
+    for i in 1..args.len() {
+        if hash.remove(&arg_bytes(args, i)?).is_some() {
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        storage.notify(EventClasses::HASH, "hdel", &key);
+    }
+    storage.remove_if_empty(&key);
+    Ok(Command::Integer(removed))
+}
+
+pub fn hgetall_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let pairs = match hash(storage, args)? {
+        Some(hash) => hash.iter()
+            .map(|(field, value)| (Command::BulkString(field.clone()), Command::BulkString(value.value.clone())))
+            .collect(),
+        None => vec![]
+    };
+
+    Ok(Command::Map(pairs))
+}
+
+pub fn hkeys_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let fields = match hash(storage, args)? {
+        Some(hash) => hash.iter().map(|(field, _)| Command::BulkString(field.clone())).collect(),
+        None => vec![]
+    };
+
+    Ok(Command::Array(fields))
+}
+
+pub fn hvals_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let values = match hash(storage, args)? {
+        Some(hash) => hash.iter().map(|(_, field)| Command::BulkString(field.value.clone())).collect(),
+        None => vec![]
+    };
+
+    Ok(Command::Array(values))
+}
+
+pub fn hlen_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let len = hash(storage, args)?.map_or(0, |hash| hash.len());
+
+    Ok(Command::Integer(len as i64))
+}
+
+pub fn hexists_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let field = arg_bytes(args, 1)?;
+    let exists = hash(storage, args)?.is_some_and(|hash| hash.contains_key(&field));
+
+    Ok(Command::Integer(exists as i64))
+}
+
+pub fn hstrlen_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let field = arg_bytes(args, 1)?;
+    let len = hash(storage, args)?.and_then(|hash| hash.get(&field)).map_or(0, |field| field.value.len());
+
+    Ok(Command::Integer(len as i64))
+}
+
+/// HINCRBY key field increment. Like the other in-place updates, the field
+/// keeps its TTL.
+pub fn hincrby_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let field = arg_bytes(args, 1)?;
+    let increment = arg_int(args, 2)?;
+
+    let hash = storage.get_or_insert(&key, || Value::Hash(HashValue::new())).as_hash_mut()?;
+    let entry = hash.get_or_insert_with(field, || HashField::new(Bytes::from_static(b"0")));
+
+    let current = parse_i64(&entry.value).ok_or_else(|| CommandError::Other(String::from("hash value is not an integer")))?;
+    let result = match current.checked_add(increment) {
+        Some(result) => result,
+        None => return Err(CommandError::Other(String::from("increment or decrement would overflow")))
+    };
+
+    entry.value = Bytes::from(result.to_string());
+    storage.notify(EventClasses::HASH, "hincrby", &key);
+    Ok(Command::Integer(result))
+}
+
+pub fn hincrbyfloat_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let field = arg_bytes(args, 1)?;
+    let increment = parse_f64(&arg_bytes(args, 2)?)?;
+
+    // Read like a write, so the master's stream finds what the master saw
+    let current = match storage.get(&key).map(|record| record.value.as_hash()).transpose()?.and_then(|hash| hash.get(&field)) {
+        Some(field) => parse_f64(&field.value).map_err(|_| CommandError::Other(String::from("hash value is not a float")))?,
+        None => 0.0
+    };
+
+    let result = current + increment;
+    if result.is_nan() || result.is_infinite() {
+        return Err(CommandError::Other(String::from("increment would produce NaN or Infinity")));
+    }
+
+    let value = Bytes::from(format_float_value(result));
+    let hash = storage.get_or_insert(&key, || Value::Hash(HashValue::new())).as_hash_mut()?;
+    hash.get_or_insert_with(field, || HashField::new(Bytes::new())).value = value.clone();
+    storage.notify(EventClasses::HASH, "hincrbyfloat", &key);
+
+    Ok(Command::BulkString(value))
+}
+
+/// HRANDFIELD key [count [WITHVALUES]]: a positive count returns distinct
+/// fields, a negative one may repeat them.
+pub fn hrandfield_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let count = match args.get(1) {
+        Some(_) => Some(arg_int(args, 1)?),
+        None => None
+    };
+    let with_values = match args.get(2) {
+        Some(_) if arg_str(args, 2)?.eq_ignore_ascii_case("WITHVALUES") && args.len() == 3 => true,
+        Some(_) => return Err(CommandError::Syntax),
+        None => false
+    };
+
+    let hash = match hash(storage, args)? {
+        Some(hash) => hash,
+        None => return Ok(match count {
+            Some(_) => Command::Array(vec![]),
+            None => Command::NullBulkString
+        })
+    };
+
+    let picked: Vec<(&Bytes, &HashField)> = match count {
+        None => return Ok(match hash.random() {
+            Some((field, _)) => Command::BulkString(field.clone()),
+            None => Command::NullBulkString
+        }),
+        Some(count) if count >= 0 => hash.sample(count as usize),
+        Some(count) => (0..count.unsigned_abs()).filter_map(|_| hash.random()).collect(),
+    };
+
+    let mut reply = vec![];
+    for (field, value) in picked {
+        reply.push(Command::BulkString(field.clone()));
+        if with_values {
+            reply.push(Command::BulkString(value.value.clone()));
+        }
+    }
+
+    Ok(Command::Array(reply))
+}
+
+/// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+pub fn hscan_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let cursor = arg_cursor(args, 1)?;
+    let options = ScanOptions::parse(args, 2, ScanTarget::Hash)?;
+
+    let hash = match hash(storage, args)? {
+        Some(hash) => hash,
+        None => return Ok(scan_reply(0, vec![]))
+    };
+
+    let (next_cursor, entries) = hash.scan(cursor, options.count);
+
+    let mut items = vec![];
+    for (field, value) in entries.into_iter().filter(|(field, _)| options.matches(field)) {
+        items.push(Command::BulkString(field.clone()));
+        if !options.no_values {
+            items.push(Command::BulkString(value.value.clone()));
+        }
+    }
+
+    Ok(scan_reply(next_cursor, items))
+}
+
+pub fn hexpire_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_field_expiry(storage, args, "hexpire", ExpireUnit::Seconds, ExpireBase::Relative)
+}
+
+pub fn hpexpire_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_field_expiry(storage, args, "hpexpire", ExpireUnit::Milliseconds, ExpireBase::Relative)
+}
+
+pub fn hexpireat_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_field_expiry(storage, args, "hexpireat", ExpireUnit::Seconds, ExpireBase::Absolute)
+}
+
+pub fn hpexpireat_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_field_expiry(storage, args, "hpexpireat", ExpireUnit::Milliseconds, ExpireBase::Absolute)
+}
+
+pub fn httl_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    field_expiry_info(storage, args, |expires_at| (expires_at - now_millis() + 500).max(0) / 1000)
+}
+
+pub fn hpttl_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    field_expiry_info(storage, args, |expires_at| (expires_at - now_millis()).max(0))
+}
+
+pub fn hexpiretime_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    field_expiry_info(storage, args, |expires_at| expires_at / 1000)
+}
+
+pub fn hpexpiretime_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    field_expiry_info(storage, args, |expires_at| expires_at)
+}
+
+/// HPERSIST key FIELDS numfields field [field ...]: -2 for a missing field,
+/// -1 for one without a TTL, 1 when the TTL was removed.
+pub fn hpersist_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let fields = parse_fields(args, 1)?;
+
+    let hash = match storage.get_mut(&key) {
+        Some(record) => record.value.as_hash_mut()?,
+        None => return Ok(Command::Array(fields.iter().map(|_| Command::Integer(-2)).collect()))
+    };
+
+    let replies: Vec<Command> = fields.iter()
+        .map(|field| Command::Integer(match hash.get_mut(field) {
+            None => -2,
+            Some(field) if field.expires_at == 0 => -1,
+            Some(field) => {
+                field.expires_at = 0;
+                1
+            }
+        }))
+        .collect();
+
+    if replies.contains(&Command::Integer(1)) {
+        storage.notify(EventClasses::HASH, "hpersist", &key);
+    }
+    Ok(Command::Array(replies))
+}
+
+/// The live fields of the hash at `args[0]`, or WRONGTYPE if the key holds
+/// another kind of value.
+fn hash<'a>(storage: &'a mut Storage, args: &[Command]) -> Result<Option<LiveFields<'a>>, CommandError> {
+    match storage.get(&arg_bytes(args, 0)?) {
+        Some(record) => Ok(Some(record.value.as_hash()?.live(now_millis()))),
+        None => Ok(None)
+    }
+}
+
+/// Stores the field/value pairs following the key, returning how many fields
+/// are new. Overwritten fields lose their TTL, as in Redis.
+fn set_fields(storage: &mut Storage, args: &[Command], name: &str) -> Result<usize, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    if !(args.len() - 1).is_multiple_of(2) {
+        return Err(CommandError::wrong_number_of_arguments(name));
+    }
+
+    let mut pairs = vec![];
+    for i in (1..args.len()).step_by(2) {
+        pairs.push((arg_bytes(args, i)?, arg_bytes(args, i + 1)?));
+    }
+
+    let hash = storage.get_or_insert(&key, || Value::Hash(HashValue::new())).as_hash_mut()?;
+    let mut added = 0;
+    for (field, value) in pairs {
+        if hash.insert(field, HashField::new(value)).is_none() {
+            added += 1;
+        }
+    }
+
+    storage.notify(EventClasses::HASH, "hset", &key);
+    Ok(added)
+}
+
+/// Parses `FIELDS numfields field [field ...]` starting at `args[index]`,
+/// which must run to the end of the arguments.
+fn parse_fields(args: &[Command], index: usize) -> Result<Vec<Bytes>, CommandError> {
+    if !arg_str(args, index).is_ok_and(|keyword| keyword.eq_ignore_ascii_case("FIELDS")) {
+        return Err(CommandError::Other(String::from("Mandatory argument FIELDS is missing or not at the right position")));
+    }
+
+    let count = arg_int(args, index + 1).map_err(|_| CommandError::Other(String::from("Number of fields must be a positive integer")))?;
+    if count <= 0 {
+        return Err(CommandError::Other(String::from("Number of fields must be a positive integer")));
+    }
+
+    let first = index + 2;
+    if args.len() - first != count as usize {
+        return Err(CommandError::Other(String::from("The `numfields` parameter must match the number of arguments")));
+    }
+
+    (first..args.len()).map(|i| arg_bytes(args, i)).collect()
+}
+
+/// HEXPIRE key amount [NX | XX | GT | LT] FIELDS numfields field [field ...]
+/// and its variants. Replies per field with -2 when it does not exist, 0 when
+/// the condition was not met, 1 when the TTL was set and 2 when the field was
+/// deleted because the time already passed.
+fn set_field_expiry(storage: &mut Storage, args: &[Command], name: &str, unit: ExpireUnit, base: ExpireBase) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let amount = arg_int(args, 1)?;
+
+    let condition = match arg_str(args, 2)?.to_uppercase().as_str() {
+        condition @ ("NX" | "XX" | "GT" | "LT") => Some(condition.to_string()),
+        _ => None
+    };
+    let fields = parse_fields(args, if condition.is_some() { 3 } else { 2 })?;
+
+    let millis = match unit {
+        ExpireUnit::Seconds => amount.checked_mul(1000),
+        ExpireUnit::Milliseconds => Some(amount),
+    };
+    let expires_at = match (base, millis) {
+        (_, Some(millis)) if !(0..=HASH_FIELD_MAX_EXPIRE).contains(&millis) => None,
+        (ExpireBase::Relative, Some(millis)) => Some(millis + now_millis()),
+        (ExpireBase::Absolute, millis) => millis,
+        (_, None) => None,
+    };
+    let expires_at = match expires_at {
+        Some(expires_at) if expires_at <= HASH_FIELD_MAX_EXPIRE => expires_at,
+        _ => return Err(CommandError::Other(format!("invalid expire time in '{}' command", name)))
+    };
+
+    let hash = match storage.get_mut(&key) {
+        Some(record) => record.value.as_hash_mut()?,
+        None => return Ok(Command::Array(fields.iter().map(|_| Command::Integer(-2)).collect()))
+    };
+
+    let now = now_millis();
+    let (mut replies, mut updated, mut deleted) = (vec![], vec![], vec![]);
+    for field in &fields {
+        let current = match hash.get(field) {
+            Some(field) => field.expires_at,
+            None => {
+                replies.push(Command::Integer(-2));
+                continue;
+            }
+        };
+
+        // A field without a TTL counts as having an infinite one for GT and LT
+        let allowed = match condition.as_deref() {
+            Some("NX") => current == 0,
+            Some("XX") => current != 0,
+            Some("GT") => current != 0 && expires_at > current,
+            Some("LT") => current == 0 || expires_at < current,
+            _ => true
+        };
+
+        replies.push(Command::Integer(if !allowed {
+            0
+        } else if expires_at <= now {
+            hash.remove(field);
+            deleted.push(Command::BulkString(field.clone()));
+            2
+        } else {
+            hash.set_expiry(field, expires_at);
+            updated.push(Command::BulkString(field.clone()));
+            1
+        }));
+    }
+
+    // As with EXPIRE, replicas get the outcome for the fields that changed
+    if !updated.is_empty() {
+        let mut propagated = vec![
+            Command::bulk("HPEXPIREAT"), Command::BulkString(key.clone()), Command::bulk(expires_at.to_string()),
+            Command::bulk("FIELDS"), Command::bulk(updated.len().to_string()),
+        ];
+        propagated.extend(updated);
+        storage.propagate(propagated);
+    }
+    if !deleted.is_empty() {
+        let mut propagated = vec![Command::bulk("HDEL"), Command::BulkString(key.clone())];
+        propagated.extend(deleted);
+        storage.propagate(propagated);
+    }
+
+    if replies.contains(&Command::Integer(1)) {
+        storage.notify(EventClasses::HASH, "hexpire", &key);
+    }
+    if replies.contains(&Command::Integer(2)) {
+        storage.notify(EventClasses::HASH, "hdel", &key);
+    }
+    storage.remove_if_empty(&key);
+    if storage.contains(&key) {
+        storage.track_field_expiry(&key);
+    }
+
+    Ok(Command::Array(replies))
+}
+
+/// Replies per field with -2 when it does not exist, -1 when it has no TTL
+/// and `convert(expires_at)` otherwise.
+fn field_expiry_info(storage: &mut Storage, args: &[Command], convert: impl Fn(i64) -> i64) -> Result<Command, CommandError> {
+    let fields = parse_fields(args, 1)?;
+
+    let hash = match hash(storage, args)? {
+        Some(hash) => hash,
+        None => return Ok(Command::Array(fields.iter().map(|_| Command::Integer(-2)).collect()))
+    };
+
+    let replies = fields.iter()
+        .map(|field| Command::Integer(match hash.get(field) {
+            None => -2,
+            Some(field) if field.expires_at == 0 => -1,
+            Some(field) => convert(field.expires_at),
+        }))
+        .collect();
+
+    Ok(Command::Array(replies))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<Command> {
+        args.iter().map(|arg| Command::bulk(arg.to_string())).collect()
+    }
+
+    #[test]
+    fn hincrbyfloat_rounds_like_incrbyfloat() {
+        let mut storage = Storage::new();
+
+        assert_eq!(hincrbyfloat_command(&mut storage, &args(&["key", "field", "0.1"])).unwrap(), Command::bulk("0.1"));
+        assert_eq!(hincrbyfloat_command(&mut storage, &args(&["key", "field", "0.2"])).unwrap(), Command::bulk("0.3"));
+        assert_eq!(hincrbyfloat_command(&mut storage, &args(&["key", "field", "1e300"])).unwrap(), Command::bulk("1e+300"));
+    }
+}
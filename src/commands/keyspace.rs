@@ -0,0 +1,438 @@
+use crate::command_handler::Command;
+use crate::commands::{arg_bytes, arg_int, arg_str};
+use crate::error::CommandError;
+use crate::storage::{EventClasses, Storage};
+use crate::util::{glob_match, now_millis};
+
+#[derive(Clone, Copy)]
+enum ExpireUnit {
+    Seconds,
+    Milliseconds,
+}
+
+#[derive(Clone, Copy)]
+enum ExpireBase {
+    /// The argument is relative to now (EXPIRE, PEXPIRE)
+    Relative,
+    /// The argument is a Unix timestamp (EXPIREAT, PEXPIREAT)
+    Absolute,
+}
+
+pub fn expire_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_expiry(storage, args, "expire", ExpireUnit::Seconds, ExpireBase::Relative)
+}
+
+pub fn pexpire_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_expiry(storage, args, "pexpire", ExpireUnit::Milliseconds, ExpireBase::Relative)
+}
+
+pub fn expireat_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_expiry(storage, args, "expireat", ExpireUnit::Seconds, ExpireBase::Absolute)
+}
+
+pub fn pexpireat_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    set_expiry(storage, args, "pexpireat", ExpireUnit::Milliseconds, ExpireBase::Absolute)
+}
+
+pub fn ttl_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    remaining_ttl(storage, args, ExpireUnit::Seconds)
+}
+
+pub fn pttl_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    remaining_ttl(storage, args, ExpireUnit::Milliseconds)
+}
+
+pub fn expiretime_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    absolute_expiry(storage, args, ExpireUnit::Seconds)
+}
+
+pub fn pexpiretime_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    absolute_expiry(storage, args, ExpireUnit::Milliseconds)
+}
+
+pub fn persist_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let persisted = match storage.get(&key) {
+        Some(record) if record.expires_at != 0 => storage.set_expires_at(&key, 0),
+        _ => false
+    };
+    if persisted {
+        storage.notify(EventClasses::GENERIC, "persist", &key);
+    }
+
+    Ok(Command::Integer(persisted as i64))
+}
+
+pub fn del_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let mut deleted = 0;
+
+    for i in 0..args.len() {
+        let key = arg_bytes(args, i)?;
+
+        if storage.contains(&key) {
+            storage.remove(&key);
+            storage.notify(EventClasses::GENERIC, "del", &key);
+            deleted += 1;
+        }
+    }
+
+    Ok(Command::Integer(deleted))
+}
+
+pub fn exists_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let mut existing = 0;
+
+    // Keys given more than once are counted more than once, as in Redis
+    for i in 0..args.len() {
+        if storage.contains(&arg_bytes(args, i)?) {
+            existing += 1;
+        }
+    }
+
+    Ok(Command::Integer(existing))
+}
+
+pub fn type_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let type_name = match storage.get(&arg_bytes(args, 0)?) {
+        Some(record) => record.value.type_name(),
+        None => "none"
+    };
+
+    Ok(Command::SimpleString(type_name.to_string()))
+}
+
+pub fn rename_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    rename(storage, args, false)?;
+
+    Ok(Command::SimpleString("OK".to_string()))
+}
+
+pub fn renamenx_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    Ok(Command::Integer(rename(storage, args, true)? as i64))
+}
+
+/// COPY source destination [DB destination-db] [REPLACE]
+pub fn copy_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let source = arg_bytes(args, 0)?;
+    let destination = arg_bytes(args, 1)?;
+    let mut replace = false;
+
+    let mut i = 2;
+    while i < args.len() {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "REPLACE" => replace = true,
+            // Only database 0 exists
+            "DB" if i + 1 < args.len() => {
+                i += 1;
+                if arg_int(args, i)? != 0 {
+                    return Err(CommandError::Other(String::from("DB index is out of range")));
+                }
+            }
+            _ => return Err(CommandError::Syntax)
+        }
+
+        i += 1;
+    }
+
+    if source == destination {
+        return Err(CommandError::Other(String::from("source and destination objects are the same")));
+    }
+
+    let record = match storage.get(&source) {
+        Some(record) => record.clone(),
+        None => return Ok(Command::Integer(0))
+    };
+
+    if !replace && storage.contains(&destination) {
+        return Ok(Command::Integer(0));
+    }
+
+    storage.insert(destination.clone(), record);
+    storage.notify(EventClasses::GENERIC, "copy_to", &destination);
+    Ok(Command::Integer(1))
+}
+
+/// OBJECT ENCODING | REFCOUNT | HELP
+pub fn object_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let subcommand = arg_str(args, 0)?.to_uppercase();
+
+    if subcommand == "HELP" {
+        return Ok(Command::Array([
+            "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "ENCODING <key>",
+            "    Return the kind of internal representation used in order to store the value",
+            "    associated with a <key>.",
+            "REFCOUNT <key>",
+            "    Return the number of references of the value associated with the specified",
+            "    <key>.",
+        ].into_iter().map(|line| Command::SimpleString(line.to_string())).collect()));
+    }
+
+    if args.len() != 2 || !["ENCODING", "REFCOUNT"].contains(&subcommand.as_str()) {
+        return Err(CommandError::Other(format!("unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.", subcommand)));
+    }
+
+    let record = match storage.get(&arg_bytes(args, 1)?) {
+        Some(record) => record,
+        None => return Ok(Command::NullBulkString)
+    };
+
+    Ok(match subcommand.as_str() {
+        "ENCODING" => Command::bulk(record.value.encoding()),
+        // Values are never shared between keys
+        _ => Command::Integer(1),
+    })
+}
+
+pub fn keys_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let pattern = arg_bytes(args, 0)?;
+
+    let keys = storage.keys()
+        .filter(|(key, _)| glob_match(&pattern, key))
+        .map(|(key, _)| Command::BulkString(key.clone()))
+        .collect();
+
+    Ok(Command::Array(keys))
+}
+
+pub fn dbsize_command(storage: &mut Storage, _args: &[Command]) -> Result<Command, CommandError> {
+    Ok(Command::Integer(storage.len() as i64))
+}
+
+/// FLUSHDB and FLUSHALL; there is a single database so both clear everything.
+pub fn flushall_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    match args.len() {
+        0 => (),
+        1 if ["ASYNC", "SYNC"].contains(&arg_str(args, 0)?.to_uppercase().as_str()) => (),
+        _ => return Err(CommandError::Syntax)
+    }
+
+    storage.clear();
+    Ok(Command::SimpleString("OK".to_string()))
+}
+
+pub fn randomkey_command(storage: &mut Storage, _args: &[Command]) -> Result<Command, CommandError> {
+    Ok(match storage.random_key() {
+        Some(key) => Command::BulkString(key),
+        None => Command::NullBulkString
+    })
+}
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+pub fn scan_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let cursor = arg_cursor(args, 0)?;
+    let options = ScanOptions::parse(args, 1, ScanTarget::Keyspace)?;
+
+    let (next_cursor, keys) = storage.scan(cursor, options.count);
+
+    let keys = keys.into_iter()
+        .filter(|(key, _)| options.matches(key))
+        .filter(|(_, record)| options.type_name.as_ref().is_none_or(|type_name| type_name.eq_ignore_ascii_case(record.value.type_name())))
+        .map(|(key, _)| Command::BulkString(key.clone()))
+        .collect();
+
+    Ok(scan_reply(next_cursor, keys))
+}
+
+/// Options shared by SCAN and the per-type HSCAN/SSCAN/ZSCAN commands.
+pub struct ScanOptions {
+    pub pattern: Option<Vec<u8>>,
+    pub count: usize,
+    pub type_name: Option<String>,
+    pub no_values: bool,
+}
+
+/// What is being scanned, which decides the options accepted.
+#[derive(PartialEq)]
+pub enum ScanTarget {
+    /// SCAN, which also takes TYPE
+    Keyspace,
+    /// HSCAN, which also takes NOVALUES
+    Hash,
+    /// SSCAN and ZSCAN
+    Collection,
+}
+
+impl ScanOptions {
+    pub fn parse(args: &[Command], start: usize, target: ScanTarget) -> Result<Self, CommandError> {
+        let mut options = ScanOptions {
+            pattern: None,
+            count: 10,
+            type_name: None,
+            no_values: false,
+        };
+
+        let mut i = start;
+        while i < args.len() {
+            let option = arg_str(args, i)?.to_uppercase();
+
+            if option == "NOVALUES" && target == ScanTarget::Hash {
+                options.no_values = true;
+                i += 1;
+                continue;
+            }
+
+            if i + 1 >= args.len() {
+                return Err(CommandError::Syntax);
+            }
+
+            match option.as_str() {
+                "MATCH" => options.pattern = Some(arg_bytes(args, i + 1)?.to_vec()),
+                "COUNT" => match arg_int(args, i + 1)? {
+                    count if count >= 1 => options.count = count as usize,
+                    _ => return Err(CommandError::Syntax)
+                },
+                "TYPE" if target == ScanTarget::Keyspace => options.type_name = Some(arg_str(args, i + 1)?),
+                _ => return Err(CommandError::Syntax)
+            }
+
+            i += 2;
+        }
+
+        Ok(options)
+    }
+
+    pub fn matches(&self, key: &[u8]) -> bool {
+        self.pattern.as_ref().is_none_or(|pattern| glob_match(pattern, key))
+    }
+}
+
+pub fn arg_cursor(args: &[Command], index: usize) -> Result<u64, CommandError> {
+    arg_str(args, index)?.parse::<u64>().map_err(|_| CommandError::Other(String::from("invalid cursor")))
+}
+
+pub fn scan_reply(next_cursor: u64, items: Vec<Command>) -> Command {
+    Command::Array(vec![
+        Command::bulk(next_cursor.to_string()),
+        Command::Array(items),
+    ])
+}
+
+/// Moves the value and TTL of `args[0]` to `args[1]`, returning false when
+/// `only_if_missing` is set and the destination exists.
+fn rename(storage: &mut Storage, args: &[Command], only_if_missing: bool) -> Result<bool, CommandError> {
+    let source = arg_bytes(args, 0)?;
+    let destination = arg_bytes(args, 1)?;
+
+    if !storage.contains(&source) {
+        return Err(CommandError::Other(String::from("no such key")));
+    }
+
+    if source == destination {
+        return Ok(!only_if_missing);
+    }
+
+    if only_if_missing && storage.contains(&destination) {
+        return Ok(false);
+    }
+
+    let record = storage.remove(&source).unwrap();
+    storage.notify(EventClasses::GENERIC, "rename_from", &source);
+    storage.insert(destination.clone(), record);
+    storage.notify(EventClasses::GENERIC, "rename_to", &destination);
+    Ok(true)
+}
+
+/// EXPIRE key amount [NX | XX | GT | LT] and its millisecond/absolute variants
+fn set_expiry(storage: &mut Storage, args: &[Command], name: &str, unit: ExpireUnit, base: ExpireBase) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let amount = arg_int(args, 1)?;
+
+    let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+    for i in 2..args.len() {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "GT" => gt = true,
+            "LT" => lt = true,
+            option => return Err(CommandError::Other(format!("Unsupported option {}", option)))
+        }
+    }
+
+    if nx && (xx || gt || lt) {
+        return Err(CommandError::Other(String::from("NX and XX, GT or LT options at the same time are not compatible")));
+    }
+    if gt && lt {
+        return Err(CommandError::Other(String::from("GT and LT options at the same time are not compatible")));
+    }
+
+    let millis = match unit {
+        ExpireUnit::Seconds => amount.checked_mul(1000),
+        ExpireUnit::Milliseconds => Some(amount),
+    };
+    let expires_at = match base {
+        ExpireBase::Relative => millis.and_then(|millis| millis.checked_add(now_millis())),
+        ExpireBase::Absolute => millis,
+    };
+    let expires_at = match expires_at {
+        Some(expires_at) => expires_at,
+        None => return Err(CommandError::Other(format!("invalid expire time in '{}' command", name)))
+    };
+
+    let current = match storage.get(&key) {
+        Some(record) => record.expires_at,
+        None => return Ok(Command::Integer(0))
+    };
+
+    // A key without a TTL counts as having an infinite one for GT and LT
+    let allowed = if nx {
+        current == 0
+    } else if xx && current == 0 {
+        false
+    } else if gt {
+        current != 0 && expires_at > current
+    } else if lt {
+        current == 0 || expires_at < current
+    } else {
+        true
+    };
+
+    if !allowed {
+        return Ok(Command::Integer(0));
+    }
+
+    // A deadline that has already passed deletes the key outright. Replicas
+    // are told the outcome, as their clocks and a relative TTL would differ.
+    if expires_at <= now_millis() {
+        storage.propagate(vec![Command::bulk("DEL"), Command::BulkString(key.clone())]);
+        storage.remove(&key);
+        storage.notify(EventClasses::GENERIC, "del", &key);
+    } else {
+        storage.propagate(vec![Command::bulk("PEXPIREAT"), Command::BulkString(key.clone()), Command::bulk(expires_at.to_string())]);
+        storage.set_expires_at(&key, expires_at);
+        storage.notify(EventClasses::GENERIC, "expire", &key);
+    }
+
+    Ok(Command::Integer(1))
+}
+
+fn remaining_ttl(storage: &mut Storage, args: &[Command], unit: ExpireUnit) -> Result<Command, CommandError> {
+    let expires_at = match storage.get(&arg_bytes(args, 0)?) {
+        Some(record) => record.expires_at,
+        None => return Ok(Command::Integer(-2))
+    };
+
+    if expires_at == 0 {
+        return Ok(Command::Integer(-1));
+    }
+
+    let remaining = (expires_at - now_millis()).max(0);
+    Ok(Command::Integer(match unit {
+        ExpireUnit::Seconds => (remaining + 500) / 1000,
+        ExpireUnit::Milliseconds => remaining,
+    }))
+}
+
+fn absolute_expiry(storage: &mut Storage, args: &[Command], unit: ExpireUnit) -> Result<Command, CommandError> {
+    let expires_at = match storage.get(&arg_bytes(args, 0)?) {
+        Some(record) => record.expires_at,
+        None => return Ok(Command::Integer(-2))
+    };
+
+    Ok(Command::Integer(match (expires_at, unit) {
+        (0, _) => -1,
+        (expires_at, ExpireUnit::Seconds) => expires_at / 1000,
+        (expires_at, ExpireUnit::Milliseconds) => expires_at,
+    }))
+}
@@ -0,0 +1,457 @@
+use crate::command_handler::Command;
+use crate::commands::{arg_bytes, arg_int, arg_str, arg_timeout, block_on, reply, CommandResult};
+use crate::error::CommandError;
+use crate::session::Session;
+use crate::storage::{BlockedOp, EventClasses, Storage, Value};
+use crate::util::normalize_range;
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy)]
+pub enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    fn parse(args: &[Command], index: usize) -> Result<Self, CommandError> {
+        match arg_str(args, index)?.to_uppercase().as_str() {
+            "LEFT" => Ok(End::Left),
+            "RIGHT" => Ok(End::Right),
+            _ => Err(CommandError::Syntax)
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            End::Left => "LEFT",
+            End::Right => "RIGHT",
+        }
+    }
+
+    fn push_event(&self) -> &'static str {
+        match self {
+            End::Left => "lpush",
+            End::Right => "rpush",
+        }
+    }
+
+    fn pop_event(&self) -> &'static str {
+        match self {
+            End::Left => "lpop",
+            End::Right => "rpop",
+        }
+    }
+}
+
+pub fn lpush_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    push(storage, args, End::Left, false)
+}
+
+pub fn rpush_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    push(storage, args, End::Right, false)
+}
+
+pub fn lpushx_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    push(storage, args, End::Left, true)
+}
+
+pub fn rpushx_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    push(storage, args, End::Right, true)
+}
+
+pub fn lpop_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    pop(storage, args, End::Left)
+}
+
+pub fn rpop_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    pop(storage, args, End::Right)
+}
+
+pub fn llen_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let len = match storage.get(&arg_bytes(args, 0)?) {
+        Some(record) => record.value.as_list()?.len(),
+        None => 0
+    };
+
+    Ok(Command::Integer(len as i64))
+}
+
+pub fn lrange_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let (start, stop) = (arg_int(args, 1)?, arg_int(args, 2)?);
+
+    let list = match storage.get(&key) {
+        Some(record) => record.value.as_list()?,
+        None => return Ok(Command::Array(vec![]))
+    };
+
+    let elements = match normalize_range(start, stop, list.len()) {
+        Some((start, stop)) => list.range(start..=stop).cloned().map(Command::BulkString).collect(),
+        None => vec![]
+    };
+
+    Ok(Command::Array(elements))
+}
+
+pub fn lindex_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let index = arg_int(args, 1)?;
+
+    let list = match storage.get(&key) {
+        Some(record) => record.value.as_list()?,
+        None => return Ok(Command::NullBulkString)
+    };
+
+    Ok(match resolve_index(index, list.len()).and_then(|index| list.get(index)) {
+        Some(element) => Command::BulkString(element.clone()),
+        None => Command::NullBulkString
+    })
+}
+
+pub fn lset_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let index = arg_int(args, 1)?;
+    let element = arg_bytes(args, 2)?;
+
+    let list = match storage.get_mut(&key) {
+        Some(record) => record.value.as_list_mut()?,
+        None => return Err(CommandError::Other(String::from("no such key")))
+    };
+
+    match resolve_index(index, list.len()) {
+        Some(index) => list[index] = element,
+        None => return Err(CommandError::Other(String::from("index out of range")))
+    }
+    storage.notify(EventClasses::LIST, "lset", &key);
+
+    Ok(Command::SimpleString("OK".to_string()))
+}
+
+/// LREM key count element: removes up to `count` occurrences scanning from
+/// the head (`count > 0`), the tail (`count < 0`), or all of them (`0`).
+pub fn lrem_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let count = arg_int(args, 1)?;
+    let element = arg_bytes(args, 2)?;
+
+    let list = match storage.get_mut(&key) {
+        Some(record) => record.value.as_list_mut()?,
+        None => return Ok(Command::Integer(0))
+    };
+
+    let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() as usize };
+    let mut removed = 0;
+    let mut kept = VecDeque::with_capacity(list.len());
+
+    if count >= 0 {
+        for item in list.drain(..) {
+            if removed < limit && item == element {
+                removed += 1;
+            } else {
+                kept.push_back(item);
+            }
+        }
+    } else {
+        for item in list.drain(..).rev() {
+            if removed < limit && item == element {
+                removed += 1;
+            } else {
+                kept.push_front(item);
+            }
+        }
+    }
+
+    *list = kept;
+    if removed > 0 {
+        storage.notify(EventClasses::LIST, "lrem", &key);
+    }
+    storage.remove_if_empty(&key);
+
+    Ok(Command::Integer(removed as i64))
+}
+
+pub fn ltrim_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let (start, stop) = (arg_int(args, 1)?, arg_int(args, 2)?);
+
+    let list = match storage.get_mut(&key) {
+        Some(record) => record.value.as_list_mut()?,
+        None => return Ok(Command::SimpleString("OK".to_string()))
+    };
+
+    match normalize_range(start, stop, list.len()) {
+        Some((start, stop)) => {
+            list.truncate(stop + 1);
+            list.drain(..start);
+        }
+        None => list.clear()
+    }
+    storage.notify(EventClasses::LIST, "ltrim", &key);
+    storage.remove_if_empty(&key);
+
+    Ok(Command::SimpleString("OK".to_string()))
+}
+
+/// LINSERT key BEFORE | AFTER pivot element
+pub fn linsert_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let after = match arg_str(args, 1)?.to_uppercase().as_str() {
+        "BEFORE" => false,
+        "AFTER" => true,
+        _ => return Err(CommandError::Syntax)
+    };
+    let pivot = arg_bytes(args, 2)?;
+    let element = arg_bytes(args, 3)?;
+
+    let list = match storage.get_mut(&key) {
+        Some(record) => record.value.as_list_mut()?,
+        None => return Ok(Command::Integer(0))
+    };
+
+    match list.iter().position(|item| *item == pivot) {
+        Some(position) => {
+            list.insert(if after { position + 1 } else { position }, element);
+            let len = list.len();
+            storage.notify(EventClasses::LIST, "linsert", &key);
+            Ok(Command::Integer(len as i64))
+        }
+        None => Ok(Command::Integer(-1))
+    }
+}
+
+/// LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]
+pub fn lpos_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let element = arg_bytes(args, 1)?;
+    let (mut rank, mut count, mut maxlen) = (1i64, None, 0i64);
+
+    let mut i = 2;
+    while i < args.len() {
+        let option = arg_str(args, i)?.to_uppercase();
+        if i + 1 >= args.len() {
+            return Err(CommandError::Syntax);
+        }
+        let value = arg_int(args, i + 1)?;
+
+        match option.as_str() {
+            "RANK" if value == 0 => return Err(CommandError::Other(String::from("RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list"))),
+            "RANK" if value == i64::MIN => return Err(CommandError::Other(String::from("value is out of range"))),
+            "RANK" => rank = value,
+            "COUNT" if value < 0 => return Err(CommandError::Other(String::from("COUNT can't be negative"))),
+            "COUNT" => count = Some(value as usize),
+            "MAXLEN" if value < 0 => return Err(CommandError::Other(String::from("MAXLEN can't be negative"))),
+            "MAXLEN" => maxlen = value,
+            _ => return Err(CommandError::Syntax)
+        }
+
+        i += 2;
+    }
+
+    let list = match storage.get(&key) {
+        Some(record) => record.value.as_list()?,
+        None => return Ok(if count.is_some() { Command::Array(vec![]) } else { Command::NullBulkString })
+    };
+
+    let scanned = if maxlen == 0 { list.len() } else { (maxlen as usize).min(list.len()) };
+    let positions: Box<dyn Iterator<Item=usize>> = if rank > 0 {
+        Box::new(0..scanned)
+    } else {
+        Box::new((list.len() - scanned..list.len()).rev())
+    };
+
+    let wanted = match count {
+        Some(0) => usize::MAX,
+        Some(count) => count,
+        None => 1
+    };
+    let matches: Vec<usize> = positions
+        .filter(|position| list[*position] == element)
+        .skip(rank.unsigned_abs() as usize - 1)
+        .take(wanted)
+        .collect();
+
+    Ok(match count {
+        Some(_) => Command::Array(matches.into_iter().map(|position| Command::Integer(position as i64)).collect()),
+        None => matches.first().map_or(Command::NullBulkString, |position| Command::Integer(*position as i64))
+    })
+}
+
+/// LMOVE source destination LEFT | RIGHT LEFT | RIGHT
+pub fn lmove_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let (source, destination) = (arg_bytes(args, 0)?, arg_bytes(args, 1)?);
+    let (from, to) = (End::parse(args, 2)?, End::parse(args, 3)?);
+
+    Ok(move_element(storage, &source, &destination, from, to)?.map_or(Command::NullBulkString, Command::BulkString))
+}
+
+pub fn rpoplpush_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let (source, destination) = (arg_bytes(args, 0)?, arg_bytes(args, 1)?);
+
+    Ok(move_element(storage, &source, &destination, End::Right, End::Left)?.map_or(Command::NullBulkString, Command::BulkString))
+}
+
+pub async fn blpop_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    blocking_pop(session, args, End::Left).await
+}
+
+pub async fn brpop_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    blocking_pop(session, args, End::Right).await
+}
+
+/// BLMOVE source destination LEFT | RIGHT LEFT | RIGHT timeout
+pub async fn blmove_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let (source, destination) = (arg_bytes(args, 0)?, arg_bytes(args, 1)?);
+    let (from, to) = (End::parse(args, 2)?, End::parse(args, 3)?);
+    let timeout = arg_timeout(args, 4)?;
+
+    blocking_move(session, source, destination, from, to, timeout).await
+}
+
+pub async fn brpoplpush_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let (source, destination) = (arg_bytes(args, 0)?, arg_bytes(args, 1)?);
+    let timeout = arg_timeout(args, 2)?;
+
+    blocking_move(session, source, destination, End::Right, End::Left, timeout).await
+}
+
+/// BLPOP/BRPOP key [key ...] timeout: pops from the first non-empty list, or
+/// waits for one of them to be pushed to.
+async fn blocking_pop(session: &mut Session, args: &[Command], end: End) -> CommandResult {
+    let timeout = arg_timeout(args, args.len() - 1)?;
+    let mut keys = vec![];
+    for i in 0..args.len() - 1 {
+        keys.push(arg_bytes(args, i)?);
+    }
+
+    let op_keys = keys.clone();
+    let op: BlockedOp = Box::new(move |storage| {
+        for key in op_keys.iter() {
+            if let Some(mut elements) = pop_elements(storage, key, end, 1)? {
+                storage.propagate(vec![Command::bulk(end.pop_event().to_uppercase()), Command::BulkString(key.clone())]);
+                return Ok(Some(Command::Array(vec![Command::BulkString(key.clone()), Command::BulkString(elements.remove(0))])));
+            }
+        }
+
+        Ok(None)
+    });
+
+    let command = block_on(session, keys, timeout, op, Command::NullArray).await?;
+    reply(session, command).await
+}
+
+async fn blocking_move(session: &mut Session, source: Bytes, destination: Bytes, from: End, to: End, timeout: Option<std::time::Duration>) -> CommandResult {
+    let op_source = source.clone();
+    let op: BlockedOp = Box::new(move |storage| {
+        let element = move_element(storage, &op_source, &destination, from, to)?;
+        if element.is_some() {
+            storage.propagate(vec![
+                Command::bulk("LMOVE"), Command::BulkString(op_source.clone()), Command::BulkString(destination.clone()),
+                Command::bulk(from.name()), Command::bulk(to.name()),
+            ]);
+        }
+
+        Ok(element.map(Command::BulkString))
+    });
+
+    let command = block_on(session, vec![source], timeout, op, Command::NullBulkString).await?;
+    reply(session, command).await
+}
+
+fn push(storage: &mut Storage, args: &[Command], end: End, only_if_exists: bool) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    if only_if_exists && !storage.contains(&key) {
+        return Ok(Command::Integer(0));
+    }
+
+    let list = storage.get_or_insert(&key, || Value::List(VecDeque::new())).as_list_mut()?;
+    for i in 1..args.len() {
+        push_element(list, end, arg_bytes(args, i)?);
+    }
+    let len = list.len();
+
+    storage.notify(EventClasses::LIST, end.push_event(), &key);
+    storage.signal_ready(&key);
+    Ok(Command::Integer(len as i64))
+}
+
+/// LPOP/RPOP key [count]
+fn pop(storage: &mut Storage, args: &[Command], end: End) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let count = match args.len() {
+        1 => None,
+        2 => match arg_int(args, 1)? {
+            count if count >= 0 => Some(count as usize),
+            _ => return Err(CommandError::Other(String::from("value is out of range, must be positive")))
+        },
+        _ => return Err(CommandError::Syntax)
+    };
+
+    let elements = pop_elements(storage, &key, end, count.unwrap_or(1))?;
+
+    Ok(match (elements, count) {
+        (None, Some(_)) => Command::NullArray,
+        (None, None) => Command::NullBulkString,
+        (Some(mut elements), None) => Command::BulkString(elements.remove(0)),
+        (Some(elements), Some(_)) => Command::Array(elements.into_iter().map(Command::BulkString).collect()),
+    })
+}
+
+/// Pops up to `count` elements, or `None` if the key does not exist.
+fn pop_elements(storage: &mut Storage, key: &Bytes, end: End, count: usize) -> Result<Option<Vec<Bytes>>, CommandError> {
+    let list = match storage.get_mut(key) {
+        Some(record) => record.value.as_list_mut()?,
+        None => return Ok(None)
+    };
+
+    let count = count.min(list.len());
+    let elements = match end {
+        End::Left => list.drain(..count).collect(),
+        End::Right => list.drain(list.len() - count..).rev().collect(),
+    };
+    if count > 0 {
+        storage.notify(EventClasses::LIST, end.pop_event(), key);
+    }
+    storage.remove_if_empty(key);
+
+    Ok(Some(elements))
+}
+
+/// Atomically pops from `source` and pushes onto `destination`, returning the
+/// moved element, or `None` if `source` does not exist.
+fn move_element(storage: &mut Storage, source: &Bytes, destination: &Bytes, from: End, to: End) -> Result<Option<Bytes>, CommandError> {
+    match storage.get(source) {
+        Some(record) => record.value.as_list()?,
+        None => return Ok(None)
+    };
+    if let Some(record) = storage.get(destination) {
+        record.value.as_list()?;
+    }
+
+    let element = match pop_elements(storage, source, from, 1)? {
+        Some(mut elements) => elements.remove(0),
+        None => return Ok(None)
+    };
+
+    let list = storage.get_or_insert(destination, || Value::List(VecDeque::new())).as_list_mut()?;
+    push_element(list, to, element.clone());
+    storage.notify(EventClasses::LIST, to.push_event(), destination);
+    storage.signal_ready(destination);
+
+    Ok(Some(element))
+}
+
+fn push_element(list: &mut VecDeque<Bytes>, end: End, element: Bytes) {
+    match end {
+        End::Left => list.push_front(element),
+        End::Right => list.push_back(element),
+    }
+}
+
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
@@ -0,0 +1,152 @@
+use crate::command_handler::Command;
+use crate::commands::{arg_bytes, arg_str, reply, CommandResult};
+use crate::error::CommandError;
+use crate::session::Session;
+use crate::storage::{Storage, SubscriptionKind};
+use crate::util::glob_match;
+use bytes::Bytes;
+
+pub async fn subscribe_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    subscribe(session, args, SubscriptionKind::Channel).await
+}
+
+pub async fn psubscribe_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    subscribe(session, args, SubscriptionKind::Pattern).await
+}
+
+pub async fn ssubscribe_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    subscribe(session, args, SubscriptionKind::ShardChannel).await
+}
+
+pub async fn unsubscribe_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    unsubscribe(session, args, SubscriptionKind::Channel).await
+}
+
+pub async fn punsubscribe_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    unsubscribe(session, args, SubscriptionKind::Pattern).await
+}
+
+pub async fn sunsubscribe_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    unsubscribe(session, args, SubscriptionKind::ShardChannel).await
+}
+
+/// PUBLISH channel message
+pub fn publish_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let receivers = storage.pubsub.publish(&arg_bytes(args, 0)?, &arg_bytes(args, 1)?);
+
+    Ok(Command::Integer(receivers as i64))
+}
+
+/// SPUBLISH shardchannel message
+pub fn spublish_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let receivers = storage.pubsub.spublish(&arg_bytes(args, 0)?, &arg_bytes(args, 1)?);
+
+    Ok(Command::Integer(receivers as i64))
+}
+
+/// PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT |
+/// SHARDCHANNELS [pattern] | SHARDNUMSUB [shardchannel ...] | HELP
+pub fn pubsub_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let subcommand = arg_str(args, 0)?.to_uppercase();
+
+    match subcommand.as_str() {
+        "CHANNELS" | "SHARDCHANNELS" if args.len() <= 2 => {
+            let kind = if subcommand == "CHANNELS" { SubscriptionKind::Channel } else { SubscriptionKind::ShardChannel };
+            let pattern = args.get(1).map(|_| arg_bytes(args, 1)).transpose()?;
+
+            let channels = storage.pubsub.channels(kind)
+                .filter(|channel| pattern.as_ref().is_none_or(|pattern| glob_match(pattern, channel)))
+                .map(|channel| Command::BulkString(channel.clone()))
+                .collect();
+
+            Ok(Command::Array(channels))
+        }
+        "NUMSUB" | "SHARDNUMSUB" => {
+            let kind = if subcommand == "NUMSUB" { SubscriptionKind::Channel } else { SubscriptionKind::ShardChannel };
+
+            let mut counts = vec![];
+            for i in 1..args.len() {
+                let channel = arg_bytes(args, i)?;
+                let count = storage.pubsub.subscriber_count(kind, &channel);
+                counts.push((Command::BulkString(channel), Command::Integer(count as i64)));
+            }
+
+            Ok(Command::Map(counts))
+        }
+        "NUMPAT" if args.len() == 1 => Ok(Command::Integer(storage.pubsub.channels(SubscriptionKind::Pattern).count() as i64)),
+        "HELP" if args.len() == 1 => Ok(Command::Array([
+            "PUBSUB <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "CHANNELS [<pattern>]",
+            "    Return the currently active channels matching a <pattern> (default: '*').",
+            "NUMPAT",
+            "    Return number of subscriptions to patterns.",
+            "NUMSUB [<channel> ...]",
+            "    Return the number of subscribers for the specified channels, excluding",
+            "    pattern subscriptions(default: no channels).",
+            "SHARDCHANNELS [<pattern>]",
+            "    Return the currently active shard level channels matching a <pattern> (default: '*').",
+            "SHARDNUMSUB [<shardchannel> ...]",
+            "    Return the number of subscribers for the specified shard level channel(s)",
+            "HELP",
+            "    Print this help.",
+        ].iter().map(|line| Command::SimpleString(line.to_string())).collect())),
+        "CHANNELS" | "SHARDCHANNELS" | "NUMPAT" | "HELP" => Err(CommandError::Other(format!(
+            "wrong number of arguments for 'pubsub|{}' command", subcommand.to_lowercase()
+        ))),
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'. Try PUBSUB HELP.", arg_str(args, 0)?)))
+    }
+}
+
+/// Subscribes to each channel or pattern in `args`, confirming each one with
+/// the connection's subscription count so far.
+async fn subscribe(session: &mut Session, args: &[Command], kind: SubscriptionKind) -> CommandResult {
+    for i in 0..args.len() {
+        let name = arg_bytes(args, i)?;
+
+        if session.subscriptions.insert((kind, name.clone())) {
+            let (id, subscriber) = (session.command_handler.id, session.subscriber.clone());
+            session.lock_storage().await.pubsub.subscribe(kind, name.clone(), id, subscriber);
+        }
+
+        let count = session.subscription_count(kind);
+        reply(session, confirmation(kind, true, Command::BulkString(name), count)).await?;
+    }
+
+    Ok(())
+}
+
+/// Unsubscribes from each channel or pattern in `args`, or from all of those
+/// of `kind` when none are given.
+async fn unsubscribe(session: &mut Session, args: &[Command], kind: SubscriptionKind) -> CommandResult {
+    let names = if args.is_empty() {
+        let mut names: Vec<Bytes> = session.subscriptions.iter()
+            .filter(|(subscribed, _)| *subscribed == kind)
+            .map(|(_, name)| name.clone())
+            .collect();
+        names.sort();
+        names
+    } else {
+        (0..args.len()).map(|i| arg_bytes(args, i)).collect::<Result<_, _>>()?
+    };
+
+    if names.is_empty() {
+        let count = session.subscription_count(kind);
+        return reply(session, confirmation(kind, false, Command::NullBulkString, count)).await;
+    }
+
+    for name in names {
+        if session.subscriptions.remove(&(kind, name.clone())) {
+            let id = session.command_handler.id;
+            session.lock_storage().await.pubsub.unsubscribe(kind, &name, id);
+        }
+
+        let count = session.subscription_count(kind);
+        reply(session, confirmation(kind, false, Command::BulkString(name), count)).await?;
+    }
+
+    Ok(())
+}
+
+fn confirmation(kind: SubscriptionKind, subscribe: bool, name: Command, count: usize) -> Command {
+    Command::Push(vec![Command::bulk(kind.confirmation(subscribe)), name, Command::Integer(count as i64)])
+}
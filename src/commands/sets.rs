@@ -0,0 +1,345 @@
+use crate::command_handler::Command;
+use crate::commands::keyspace::{arg_cursor, scan_reply, ScanOptions, ScanTarget};
+use crate::commands::{arg_bytes, arg_int, arg_str};
+use crate::error::CommandError;
+use crate::storage::{EventClasses, SetValue, Storage, StorageRecord, Value};
+use bytes::Bytes;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy)]
+enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+impl SetOp {
+    fn store_event(&self) -> &'static str {
+        match self {
+            SetOp::Inter => "sinterstore",
+            SetOp::Union => "sunionstore",
+            SetOp::Diff => "sdiffstore",
+        }
+    }
+}
+
+/// SADD key member [member ...]
+pub fn sadd_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let members = (1..args.len()).map(|i| arg_bytes(args, i)).collect::<Result<Vec<_>, _>>()?;
+
+    let set = storage.get_or_insert(&key, || Value::Set(SetValue::new())).as_set_mut()?;
+    let added = members.into_iter().filter(|member| set.insert(member.clone())).count();
+
+    if added > 0 {
+        storage.notify(EventClasses::SET, "sadd", &key);
+    }
+    Ok(Command::Integer(added as i64))
+}
+
+pub fn srem_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let set = match storage.get_mut(&key) {
+        Some(record) => record.value.as_set_mut()?,
+        None => return Ok(Command::Integer(0))
+    };
+
+    let mut removed = 0;
+    for i in 1..args.len() {
+        if set.remove(&arg_bytes(args, i)?) {
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        storage.notify(EventClasses::SET, "srem", &key);
+    }
+    storage.remove_if_empty(&key);
+    Ok(Command::Integer(removed))
+}
+
+pub fn smembers_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let members = match set(storage, &arg_bytes(args, 0)?)? {
+        Some(set) => set.members(),
+        None => vec![]
+    };
+
+    Ok(Command::Set(members.into_iter().map(Command::BulkString).collect()))
+}
+
+pub fn sismember_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let member = arg_bytes(args, 1)?;
+    let is_member = set(storage, &arg_bytes(args, 0)?)?.is_some_and(|set| set.contains(&member));
+
+    Ok(Command::Integer(is_member as i64))
+}
+
+pub fn smismember_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let set = set(storage, &arg_bytes(args, 0)?)?;
+
+    let mut replies = vec![];
+    for i in 1..args.len() {
+        let member = arg_bytes(args, i)?;
+        replies.push(Command::Integer(set.is_some_and(|set| set.contains(&member)) as i64));
+    }
+
+    Ok(Command::Array(replies))
+}
+
+pub fn scard_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let len = set(storage, &arg_bytes(args, 0)?)?.map_or(0, |set| set.len());
+
+    Ok(Command::Integer(len as i64))
+}
+
+/// SPOP key [count]
+pub fn spop_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let count = match args.get(1) {
+        Some(_) => match arg_int(args, 1)? {
+            count if count >= 0 => Some(count as usize),
+            _ => return Err(CommandError::Other(String::from("value is out of range, must be positive")))
+        },
+        None => None
+    };
+    if args.len() > 2 {
+        return Err(CommandError::Syntax);
+    }
+
+    let set = match storage.get_mut(&key) {
+        Some(record) => record.value.as_set_mut()?,
+        None => return Ok(match count {
+            Some(_) => Command::Set(vec![]),
+            None => Command::NullBulkString
+        })
+    };
+
+    let members = set.sample(count.unwrap_or(1));
+    for member in &members {
+        set.remove(member);
+    }
+
+    if !members.is_empty() {
+        storage.notify(EventClasses::SET, "spop", &key);
+
+        // Replicas must drop the same members rather than pick their own
+        let mut propagated = vec![Command::bulk("SREM"), Command::BulkString(key.clone())];
+        propagated.extend(members.iter().cloned().map(Command::BulkString));
+        storage.propagate(propagated);
+    }
+    storage.remove_if_empty(&key);
+
+    let mut members = members.into_iter().map(Command::BulkString);
+    Ok(match count {
+        Some(_) => Command::Set(members.collect()),
+        None => members.next().unwrap_or(Command::NullBulkString)
+    })
+}
+
+/// SRANDMEMBER key [count]: a positive count returns distinct members, a
+/// negative one may repeat them.
+pub fn srandmember_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let count = match args.get(1) {
+        Some(_) => Some(arg_int(args, 1)?),
+        None => None
+    };
+    if args.len() > 2 {
+        return Err(CommandError::Syntax);
+    }
+
+    let set = match set(storage, &arg_bytes(args, 0)?)? {
+        Some(set) => set,
+        None => return Ok(match count {
+            Some(_) => Command::Array(vec![]),
+            None => Command::NullBulkString
+        })
+    };
+
+    let picked: Vec<Bytes> = match count {
+        None => return Ok(Command::BulkString(set.random().unwrap())),
+        Some(count) if count >= 0 => set.sample(count as usize),
+        Some(count) => (0..count.unsigned_abs()).filter_map(|_| set.random()).collect(),
+    };
+
+    Ok(Command::Array(picked.into_iter().map(Command::BulkString).collect()))
+}
+
+/// SMOVE source destination member
+pub fn smove_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let source = arg_bytes(args, 0)?;
+    let destination = arg_bytes(args, 1)?;
+    let member = arg_bytes(args, 2)?;
+
+    let is_member = match set(storage, &source)? {
+        Some(set) => set.contains(&member),
+        None => return Ok(Command::Integer(0))
+    };
+    set(storage, &destination)?;
+
+    if !is_member {
+        return Ok(Command::Integer(0));
+    }
+    if source == destination {
+        return Ok(Command::Integer(1));
+    }
+
+    storage.get_mut(&source).unwrap().value.as_set_mut()?.remove(&member);
+    storage.notify(EventClasses::SET, "srem", &source);
+    storage.remove_if_empty(&source);
+    storage.get_or_insert(&destination, || Value::Set(SetValue::new())).as_set_mut()?.insert(member);
+    storage.notify(EventClasses::SET, "sadd", &destination);
+
+    Ok(Command::Integer(1))
+}
+
+pub fn sinter_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    combine_reply(storage, args, SetOp::Inter)
+}
+
+pub fn sunion_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    combine_reply(storage, args, SetOp::Union)
+}
+
+pub fn sdiff_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    combine_reply(storage, args, SetOp::Diff)
+}
+
+pub fn sinterstore_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    combine_store(storage, args, SetOp::Inter)
+}
+
+pub fn sunionstore_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    combine_store(storage, args, SetOp::Union)
+}
+
+pub fn sdiffstore_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    combine_store(storage, args, SetOp::Diff)
+}
+
+/// SINTERCARD numkeys key [key ...] [LIMIT limit]
+pub fn sintercard_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let numkeys = match arg_int(args, 0) {
+        Ok(numkeys) if numkeys > 0 => numkeys as usize,
+        _ => return Err(CommandError::Other(String::from("numkeys should be greater than 0")))
+    };
+    if numkeys > args.len() - 1 {
+        return Err(CommandError::Other(String::from("Number of keys can't be greater than number of args")));
+    }
+
+    let keys = (1..=numkeys).map(|i| arg_bytes(args, i)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut limit = usize::MAX;
+    let mut i = numkeys + 1;
+    while i < args.len() {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "LIMIT" if i + 1 < args.len() => match arg_int(args, i + 1) {
+                Ok(0) => limit = usize::MAX,
+                Ok(value) if value > 0 => limit = value as usize,
+                _ => return Err(CommandError::Other(String::from("LIMIT can't be negative")))
+            },
+            _ => return Err(CommandError::Syntax)
+        }
+        i += 2;
+    }
+
+    let count = combine(storage, &keys, SetOp::Inter, limit)?.len();
+    Ok(Command::Integer(count as i64))
+}
+
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+pub fn sscan_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let cursor = arg_cursor(args, 1)?;
+    let options = ScanOptions::parse(args, 2, ScanTarget::Collection)?;
+
+    let (next_cursor, members) = match set(storage, &arg_bytes(args, 0)?)? {
+        Some(set) => set.scan(cursor, options.count),
+        None => return Ok(scan_reply(0, vec![]))
+    };
+
+    let members = members.into_iter()
+        .filter(|member| options.matches(member))
+        .map(Command::BulkString)
+        .collect();
+
+    Ok(scan_reply(next_cursor, members))
+}
+
+/// The set at `key`, or WRONGTYPE if the key holds another kind of value.
+fn set<'a>(storage: &'a mut Storage, key: &[u8]) -> Result<Option<&'a SetValue>, CommandError> {
+    match storage.get(key) {
+        Some(record) => Ok(Some(record.value.as_set()?)),
+        None => Ok(None)
+    }
+}
+
+/// Applies `op` across the sets at `keys`, a missing key counting as the empty
+/// set. Stops once `limit` members were found.
+fn combine(storage: &mut Storage, keys: &[Bytes], op: SetOp, limit: usize) -> Result<Vec<Bytes>, CommandError> {
+    // Evict expired keys and check types up front, so the sets can be
+    // borrowed together below
+    for key in keys {
+        set(storage, key)?;
+    }
+    let sets: Vec<Option<&SetValue>> = keys.iter()
+        .map(|key| storage.peek(key).map(|record| record.value.as_set()).transpose())
+        .collect::<Result<_, _>>()?;
+
+    let result = match op {
+        SetOp::Inter => {
+            let mut sets = match sets.into_iter().collect::<Option<Vec<_>>>() {
+                Some(sets) => sets,
+                None => return Ok(vec![])
+            };
+            sets.sort_by_key(|set| set.len());
+
+            let (smallest, rest) = sets.split_first().unwrap();
+            smallest.members().into_iter()
+                .filter(|member| rest.iter().all(|set| set.contains(member)))
+                .take(limit)
+                .collect()
+        }
+        SetOp::Union => {
+            let mut union = HashSet::new();
+            for set in sets.into_iter().flatten() {
+                union.extend(set.members());
+            }
+            union.into_iter().collect()
+        }
+        SetOp::Diff => match sets.split_first() {
+            Some((Some(first), rest)) => first.members().into_iter()
+                .filter(|member| !rest.iter().flatten().any(|set| set.contains(member)))
+                .collect(),
+            _ => vec![]
+        },
+    };
+
+    Ok(result)
+}
+
+fn combine_reply(storage: &mut Storage, args: &[Command], op: SetOp) -> Result<Command, CommandError> {
+    let keys = (0..args.len()).map(|i| arg_bytes(args, i)).collect::<Result<Vec<_>, _>>()?;
+    let members = combine(storage, &keys, op, usize::MAX)?;
+
+    Ok(Command::Set(members.into_iter().map(Command::BulkString).collect()))
+}
+
+/// The STORE variants write the result to `args[0]`, replacing whatever was
+/// there, and delete it when the result is empty.
+fn combine_store(storage: &mut Storage, args: &[Command], op: SetOp) -> Result<Command, CommandError> {
+    let destination = arg_bytes(args, 0)?;
+    let keys = (1..args.len()).map(|i| arg_bytes(args, i)).collect::<Result<Vec<_>, _>>()?;
+    let members = combine(storage, &keys, op, usize::MAX)?;
+
+    let len = members.len();
+    if len == 0 {
+        if storage.remove(&destination).is_some() {
+            storage.notify(EventClasses::GENERIC, "del", &destination);
+        }
+    } else {
+        storage.insert(destination.clone(), StorageRecord::new(Value::Set(SetValue::from_members(members)), 0));
+        storage.notify(EventClasses::SET, op.store_event(), &destination);
+    }
+
+    Ok(Command::Integer(len as i64))
+}
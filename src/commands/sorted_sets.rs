@@ -0,0 +1,724 @@
+use crate::command_handler::{format_double, Command};
+use crate::commands::keyspace::{arg_cursor, scan_reply, ScanOptions, ScanTarget};
+use crate::commands::strings::parse_f64;
+use crate::commands::{arg_bytes, arg_int, arg_str, arg_timeout, block_on, reply, CommandResult};
+use crate::error::CommandError;
+use crate::session::Session;
+use crate::storage::{BlockedOp, EventClasses, LexBound, ScoreBound, SortedSet, Storage, StorageRecord, Value};
+use crate::util::normalize_range;
+use bytes::Bytes;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq)]
+enum RangeBy {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// The options following `min max` in ZRANGE and ZRANGESTORE.
+struct RangeOptions {
+    by: RangeBy,
+    reverse: bool,
+    /// `None` when the LIMIT offset was negative, which selects nothing
+    offset: Option<usize>,
+    count: usize,
+    with_scores: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Combine {
+    Union,
+    Inter,
+}
+
+/// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+pub fn zadd_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) = (false, false, false, false, false, false);
+    let mut i = 1;
+    while i < args.len() {
+        match arg_str(args, i).unwrap_or_default().to_uppercase().as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "GT" => gt = true,
+            "LT" => lt = true,
+            "CH" => ch = true,
+            "INCR" => incr = true,
+            _ => break
+        }
+        i += 1;
+    }
+
+    if nx && xx {
+        return Err(CommandError::Other(String::from("XX and NX options at the same time are not compatible")));
+    }
+    if (gt && lt) || (nx && (gt || lt)) {
+        return Err(CommandError::Other(String::from("GT, LT, and/or NX options at the same time are not compatible")));
+    }
+
+    let elements = &args[i..];
+    if elements.is_empty() || !elements.len().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+    if incr && elements.len() > 2 {
+        return Err(CommandError::Other(String::from("INCR option supports a single increment-element pair")));
+    }
+
+    let mut pairs = vec![];
+    for j in (0..elements.len()).step_by(2) {
+        pairs.push((parse_f64(&arg_bytes(elements, j)?)?, arg_bytes(elements, j + 1)?));
+    }
+
+    // XX never adds members, so it leaves a missing key alone; without it
+    // every member is added to a new key, so it never ends up empty
+    if xx && !storage.contains(&key) {
+        return Ok(if incr { Command::NullBulkString } else { Command::Integer(0) });
+    }
+
+    let zset = storage.get_or_insert(&key, || Value::SortedSet(SortedSet::new())).as_sorted_set_mut()?;
+    let (mut added, mut changed) = (0, 0);
+    let mut incr_result = None;
+
+    for (score, member) in pairs {
+        let score = match zset.score(&member) {
+            None if xx => continue,
+            None => {
+                zset.insert(member, score);
+                added += 1;
+                score
+            }
+            Some(_) if nx => continue,
+            Some(current) => {
+                let score = if incr { current + score } else { score };
+                if score.is_nan() {
+                    return Err(CommandError::Other(String::from("resulting score is not a number (NaN)")));
+                }
+                if (gt && score <= current) || (lt && score >= current) {
+                    continue;
+                }
+                if score != current {
+                    zset.insert(member, score);
+                    changed += 1;
+                }
+                score
+            }
+        };
+        incr_result = Some(score);
+    }
+
+    if added + changed > 0 {
+        storage.notify(EventClasses::ZSET, if incr { "zincr" } else { "zadd" }, &key);
+    }
+    if added > 0 {
+        storage.signal_ready(&key);
+    }
+
+    Ok(if incr {
+        incr_result.map_or(Command::NullBulkString, Command::Double)
+    } else {
+        Command::Integer(added + if ch { changed } else { 0 })
+    })
+}
+
+/// ZINCRBY key increment member
+pub fn zincrby_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let increment = parse_f64(&arg_bytes(args, 1)?)?;
+    let member = arg_bytes(args, 2)?;
+
+    let zset = storage.get_or_insert(&key, || Value::SortedSet(SortedSet::new())).as_sorted_set_mut()?;
+    let score = zset.score(&member).unwrap_or(0.0) + increment;
+    if score.is_nan() {
+        storage.remove_if_empty(&key);
+        return Err(CommandError::Other(String::from("resulting score is not a number (NaN)")));
+    }
+
+    zset.insert(member, score);
+    storage.notify(EventClasses::ZSET, "zincr", &key);
+    storage.signal_ready(&key);
+
+    Ok(Command::Double(score))
+}
+
+pub fn zrem_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let zset = match storage.get_mut(&key) {
+        Some(record) => record.value.as_sorted_set_mut()?,
+        None => return Ok(Command::Integer(0))
+    };
+
+    let mut removed = 0;
+    for i in 1..args.len() {
+        if zset.remove(&arg_bytes(args, i)?) {
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        storage.notify(EventClasses::ZSET, "zrem", &key);
+    }
+    storage.remove_if_empty(&key);
+    Ok(Command::Integer(removed))
+}
+
+pub fn zcard_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let len = sorted_set(storage, &arg_bytes(args, 0)?)?.map_or(0, |zset| zset.len());
+
+    Ok(Command::Integer(len as i64))
+}
+
+pub fn zscore_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let member = arg_bytes(args, 1)?;
+
+    Ok(match sorted_set(storage, &arg_bytes(args, 0)?)?.and_then(|zset| zset.score(&member)) {
+        Some(score) => Command::Double(score),
+        None => Command::NullBulkString
+    })
+}
+
+pub fn zmscore_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let zset = sorted_set(storage, &arg_bytes(args, 0)?)?;
+
+    let mut scores = vec![];
+    for i in 1..args.len() {
+        let member = arg_bytes(args, i)?;
+        scores.push(match zset.and_then(|zset| zset.score(&member)) {
+            Some(score) => Command::Double(score),
+            None => Command::NullBulkString
+        });
+    }
+
+    Ok(Command::Array(scores))
+}
+
+pub fn zrank_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    rank(storage, args, false)
+}
+
+pub fn zrevrank_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    rank(storage, args, true)
+}
+
+/// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+pub fn zrange_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let options = RangeOptions::parse(args, 3, true)?;
+    let elements = range(storage, args, 0, &options)?;
+
+    let mut items = vec![];
+    for (member, score) in elements {
+        items.push(Command::BulkString(member));
+        if options.with_scores {
+            items.push(Command::Double(score));
+        }
+    }
+
+    Ok(Command::Array(items))
+}
+
+/// ZRANGESTORE dst src min max [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+pub fn zrangestore_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let destination = arg_bytes(args, 0)?;
+    let options = RangeOptions::parse(args, 4, false)?;
+    let elements = range(storage, args, 1, &options)?;
+
+    Ok(Command::Integer(store(storage, destination, elements, "zrangestore") as i64))
+}
+
+/// ZCOUNT key min max
+pub fn zcount_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let (min, max) = (parse_score_bound(args, 1)?, parse_score_bound(args, 2)?);
+    let count = sorted_set(storage, &arg_bytes(args, 0)?)?.map_or(0, |zset| zset.count_by_score(&min, &max));
+
+    Ok(Command::Integer(count as i64))
+}
+
+/// ZLEXCOUNT key min max
+pub fn zlexcount_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let (min, max) = (parse_lex_bound(args, 1)?, parse_lex_bound(args, 2)?);
+    let count = sorted_set(storage, &arg_bytes(args, 0)?)?.map_or(0, |zset| zset.count_by_lex(&min, &max));
+
+    Ok(Command::Integer(count as i64))
+}
+
+pub fn zpopmin_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    pop(storage, args, false)
+}
+
+pub fn zpopmax_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    pop(storage, args, true)
+}
+
+pub async fn bzpopmin_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    blocking_pop(session, args, false).await
+}
+
+pub async fn bzpopmax_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    blocking_pop(session, args, true).await
+}
+
+pub fn zunionstore_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    combine_store(storage, args, Combine::Union, "zunionstore")
+}
+
+pub fn zinterstore_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    combine_store(storage, args, Combine::Inter, "zinterstore")
+}
+
+/// ZSCAN key cursor [MATCH pattern] [COUNT count]
+pub fn zscan_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let cursor = arg_cursor(args, 1)?;
+    let options = ScanOptions::parse(args, 2, ScanTarget::Collection)?;
+
+    let zset = match sorted_set(storage, &arg_bytes(args, 0)?)? {
+        Some(zset) => zset,
+        None => return Ok(scan_reply(0, vec![]))
+    };
+
+    let (next_cursor, elements) = zset.scan(cursor, options.count);
+
+    let mut items = vec![];
+    for (member, score) in elements.into_iter().filter(|(member, _)| options.matches(member)) {
+        items.push(Command::BulkString(member.clone()));
+        items.push(Command::bulk(format_double(score)));
+    }
+
+    Ok(scan_reply(next_cursor, items))
+}
+
+impl RangeOptions {
+    fn parse(args: &[Command], start: usize, allow_with_scores: bool) -> Result<Self, CommandError> {
+        let mut options = RangeOptions {
+            by: RangeBy::Rank,
+            reverse: false,
+            offset: Some(0),
+            count: usize::MAX,
+            with_scores: false,
+        };
+        let mut limit = false;
+
+        let mut i = start;
+        while i < args.len() {
+            match arg_str(args, i)?.to_uppercase().as_str() {
+                "BYSCORE" => options.by = RangeBy::Score,
+                "BYLEX" => options.by = RangeBy::Lex,
+                "REV" => options.reverse = true,
+                "WITHSCORES" if allow_with_scores => options.with_scores = true,
+                "LIMIT" if i + 2 < args.len() => {
+                    let (offset, count) = (arg_int(args, i + 1)?, arg_int(args, i + 2)?);
+                    options.offset = usize::try_from(offset).ok();
+                    options.count = usize::try_from(count).unwrap_or(usize::MAX);
+                    limit = true;
+                    i += 2;
+                }
+                _ => return Err(CommandError::Syntax)
+            }
+            i += 1;
+        }
+
+        if limit && options.by == RangeBy::Rank {
+            return Err(CommandError::Other(String::from("syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX")));
+        }
+        if options.with_scores && options.by == RangeBy::Lex {
+            return Err(CommandError::Other(String::from("syntax error, WITHSCORES not supported in combination with BYLEX")));
+        }
+
+        Ok(options)
+    }
+}
+
+/// The elements of the sorted set at `args[index]` selected by the range in
+/// the two arguments after it. REV ranges by score or member take the
+/// maximum first.
+fn range(storage: &mut Storage, args: &[Command], index: usize, options: &RangeOptions) -> Result<Vec<(Bytes, f64)>, CommandError> {
+    let (from, to) = if options.reverse && options.by != RangeBy::Rank { (index + 2, index + 1) } else { (index + 1, index + 2) };
+
+    enum Bounds {
+        Rank(i64, i64),
+        Score(ScoreBound, ScoreBound),
+        Lex(LexBound, LexBound),
+    }
+    let bounds = match options.by {
+        RangeBy::Rank => Bounds::Rank(arg_int(args, from)?, arg_int(args, to)?),
+        RangeBy::Score => Bounds::Score(parse_score_bound(args, from)?, parse_score_bound(args, to)?),
+        RangeBy::Lex => Bounds::Lex(parse_lex_bound(args, from)?, parse_lex_bound(args, to)?),
+    };
+
+    let zset = match sorted_set(storage, &arg_bytes(args, index)?)? {
+        Some(zset) => zset,
+        None => return Ok(vec![])
+    };
+    let offset = match options.offset {
+        Some(offset) => offset,
+        None => return Ok(vec![])
+    };
+
+    Ok(match bounds {
+        Bounds::Rank(start, stop) => match normalize_range(start, stop, zset.len()) {
+            Some((start, stop)) => zset.range_by_rank(start, stop, options.reverse),
+            None => vec![]
+        },
+        Bounds::Score(min, max) => zset.range_by_score(&min, &max, options.reverse, offset, options.count),
+        Bounds::Lex(min, max) => zset.range_by_lex(&min, &max, options.reverse, offset, options.count),
+    })
+}
+
+/// ZRANK/ZREVRANK key member [WITHSCORE]
+fn rank(storage: &mut Storage, args: &[Command], reverse: bool) -> Result<Command, CommandError> {
+    let member = arg_bytes(args, 1)?;
+    let with_score = match args.get(2) {
+        Some(_) if arg_str(args, 2)?.eq_ignore_ascii_case("WITHSCORE") && args.len() == 3 => true,
+        Some(_) => return Err(CommandError::Syntax),
+        None => false
+    };
+
+    let zset = sorted_set(storage, &arg_bytes(args, 0)?)?;
+    let found = zset.and_then(|zset| Some((zset.rank(&member, reverse)?, zset.score(&member)?)));
+
+    Ok(match (found, with_score) {
+        (Some((rank, _)), false) => Command::Integer(rank as i64),
+        (Some((rank, score)), true) => Command::Array(vec![Command::Integer(rank as i64), Command::Double(score)]),
+        (None, false) => Command::NullBulkString,
+        (None, true) => Command::NullArray,
+    })
+}
+
+/// ZPOPMIN/ZPOPMAX key [count]
+fn pop(storage: &mut Storage, args: &[Command], reverse: bool) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let count = match args.get(1) {
+        Some(_) => match arg_int(args, 1)? {
+            count if count >= 0 => count as usize,
+            _ => return Err(CommandError::Other(String::from("value is out of range, must be positive")))
+        },
+        None => 1
+    };
+    if args.len() > 2 {
+        return Err(CommandError::Syntax);
+    }
+
+    let popped = pop_elements(storage, &key, count, reverse)?.unwrap_or_default();
+
+    let mut items = vec![];
+    for (member, score) in popped {
+        items.push(Command::BulkString(member));
+        items.push(Command::Double(score));
+    }
+
+    Ok(Command::Array(items))
+}
+
+/// Pops up to `count` elements, deleting the key once it is empty. Returns
+/// `None` when there is no sorted set at `key`.
+fn pop_elements(storage: &mut Storage, key: &Bytes, count: usize, reverse: bool) -> Result<Option<Vec<(Bytes, f64)>>, CommandError> {
+    let zset = match storage.get_mut(key) {
+        Some(record) => record.value.as_sorted_set_mut()?,
+        None => return Ok(None)
+    };
+
+    let popped = zset.pop(count, reverse);
+    if !popped.is_empty() {
+        storage.notify(EventClasses::ZSET, if reverse { "zpopmax" } else { "zpopmin" }, key);
+    }
+    storage.remove_if_empty(key);
+
+    Ok(Some(popped))
+}
+
+/// BZPOPMIN/BZPOPMAX key [key ...] timeout: pops from the first non-empty
+/// sorted set, or waits for one of them to be added to.
+async fn blocking_pop(session: &mut Session, args: &[Command], reverse: bool) -> CommandResult {
+    let timeout = arg_timeout(args, args.len() - 1)?;
+    let mut keys = vec![];
+    for i in 0..args.len() - 1 {
+        keys.push(arg_bytes(args, i)?);
+    }
+
+    let op_keys = keys.clone();
+    let op: BlockedOp = Box::new(move |storage| {
+        for key in op_keys.iter() {
+            if let Some((member, score)) = pop_elements(storage, key, 1, reverse)?.and_then(|popped| popped.into_iter().next()) {
+                storage.propagate(vec![Command::bulk(if reverse { "ZPOPMAX" } else { "ZPOPMIN" }), Command::BulkString(key.clone())]);
+                return Ok(Some(Command::Array(vec![
+                    Command::BulkString(key.clone()),
+                    Command::BulkString(member),
+                    Command::Double(score),
+                ])));
+            }
+        }
+
+        Ok(None)
+    });
+
+    let command = block_on(session, keys, timeout, op, Command::NullArray).await?;
+    reply(session, command).await
+}
+
+/// ZUNIONSTORE/ZINTERSTORE destination numkeys key [key ...]
+/// [WEIGHTS weight [weight ...]] [AGGREGATE SUM | MIN | MAX]
+fn combine_store(storage: &mut Storage, args: &[Command], combine: Combine, name: &str) -> Result<Command, CommandError> {
+    let destination = arg_bytes(args, 0)?;
+    let numkeys = match arg_int(args, 1)? {
+        numkeys if numkeys > 0 => numkeys as usize,
+        _ => return Err(CommandError::Other(format!("at least 1 input key is needed for '{}' command", name)))
+    };
+    if numkeys > args.len() - 2 {
+        return Err(CommandError::Syntax);
+    }
+
+    let keys = (2..2 + numkeys).map(|i| arg_bytes(args, i)).collect::<Result<Vec<_>, _>>()?;
+    let mut weights = vec![1.0; numkeys];
+    let mut aggregate = Aggregate::Sum;
+
+    let mut i = 2 + numkeys;
+    while i < args.len() {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "WEIGHTS" if i + numkeys < args.len() => {
+                for (j, weight) in weights.iter_mut().enumerate() {
+                    *weight = parse_f64(&arg_bytes(args, i + 1 + j)?)
+                        .map_err(|_| CommandError::Other(String::from("weight value is not a float")))?;
+                }
+                i += numkeys;
+            }
+            "AGGREGATE" if i + 1 < args.len() => {
+                aggregate = match arg_str(args, i + 1)?.to_uppercase().as_str() {
+                    "SUM" => Aggregate::Sum,
+                    "MIN" => Aggregate::Min,
+                    "MAX" => Aggregate::Max,
+                    _ => return Err(CommandError::Syntax)
+                };
+                i += 1;
+            }
+            _ => return Err(CommandError::Syntax)
+        }
+        i += 1;
+    }
+
+    let mut inputs = vec![];
+    for key in &keys {
+        inputs.push(input_scores(storage, key)?);
+    }
+
+    let mut result: HashMap<Bytes, f64> = HashMap::new();
+    for (input, weight) in inputs.iter().zip(&weights) {
+        for (member, score) in input {
+            if combine == Combine::Inter && !inputs.iter().all(|other| other.contains_key(member)) {
+                continue;
+            }
+
+            let score = weighted(*score, *weight);
+            match result.get_mut(member) {
+                Some(current) => *current = aggregated(*current, score, aggregate),
+                None => {
+                    result.insert(member.clone(), score);
+                }
+            }
+        }
+    }
+
+    Ok(Command::Integer(store(storage, destination, result.into_iter().collect(), name) as i64))
+}
+
+/// Members and scores of the sorted set or plain set at `key`, the members
+/// of a plain set scoring 1.
+fn input_scores(storage: &mut Storage, key: &[u8]) -> Result<HashMap<Bytes, f64>, CommandError> {
+    Ok(match storage.get(key).map(|record| &record.value) {
+        Some(Value::SortedSet(zset)) => zset.iter().map(|(member, score)| (member.clone(), score)).collect(),
+        Some(Value::Set(set)) => set.members().into_iter().map(|member| (member, 1.0)).collect(),
+        Some(_) => return Err(CommandError::WrongType),
+        None => HashMap::new()
+    })
+}
+
+fn weighted(score: f64, weight: f64) -> f64 {
+    let score = score * weight;
+    // 0 * inf is NaN, which Redis turns into 0
+    if score.is_nan() { 0.0 } else { score }
+}
+
+fn aggregated(current: f64, score: f64, aggregate: Aggregate) -> f64 {
+    match aggregate {
+        Aggregate::Sum => {
+            let sum = current + score;
+            if sum.is_nan() { 0.0 } else { sum }
+        }
+        Aggregate::Min => current.min(score),
+        Aggregate::Max => current.max(score),
+    }
+}
+
+/// Replaces `destination` with a sorted set of `elements`, deleting it when
+/// there are none. Returns the new cardinality.
+fn store(storage: &mut Storage, destination: Bytes, elements: Vec<(Bytes, f64)>, event: &str) -> usize {
+    if elements.is_empty() {
+        if storage.remove(&destination).is_some() {
+            storage.notify(EventClasses::GENERIC, "del", &destination);
+        }
+        return 0;
+    }
+
+    let mut zset = SortedSet::new();
+    for (member, score) in elements {
+        zset.insert(member, score);
+    }
+
+    let len = zset.len();
+    storage.insert(destination.clone(), StorageRecord::new(Value::SortedSet(zset), 0));
+    storage.notify(EventClasses::ZSET, event, &destination);
+    storage.signal_ready(&destination);
+
+    len
+}
+
+/// The sorted set at `key`, or WRONGTYPE if the key holds another kind of value.
+fn sorted_set<'a>(storage: &'a mut Storage, key: &[u8]) -> Result<Option<&'a SortedSet>, CommandError> {
+    match storage.get(key) {
+        Some(record) => Ok(Some(record.value.as_sorted_set()?)),
+        None => Ok(None)
+    }
+}
+
+/// A score bound such as `1.5`, `(1.5` (exclusive), `-inf` or `+inf`.
+fn parse_score_bound(args: &[Command], index: usize) -> Result<ScoreBound, CommandError> {
+    let arg = arg_bytes(args, index)?;
+    let (value, exclusive) = match arg.strip_prefix(b"(") {
+        Some(value) => (value, true),
+        None => (arg.as_ref(), false)
+    };
+
+    match parse_f64(value) {
+        Ok(value) => Ok(ScoreBound { value, exclusive }),
+        Err(_) => Err(CommandError::Other(String::from("min or max is not a float")))
+    }
+}
+
+/// A lex bound: `-`, `+`, `[member` (inclusive) or `(member` (exclusive).
+fn parse_lex_bound(args: &[Command], index: usize) -> Result<LexBound, CommandError> {
+    let arg = arg_bytes(args, index)?;
+
+    match arg.first() {
+        Some(b'-') if arg.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if arg.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(arg.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(arg.slice(1..))),
+        _ => Err(CommandError::Other(String::from("min or max not valid string range item")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<Command> {
+        args.iter().map(|arg| Command::bulk(arg.to_string())).collect()
+    }
+
+    fn members(members: &[&str]) -> Command {
+        Command::Array(members.iter().map(|member| Command::bulk(member.to_string())).collect())
+    }
+
+    /// The members of the sorted set at `key` with their scores, in order.
+    fn scores(storage: &mut Storage, key: &str) -> Vec<(String, f64)> {
+        let zset = sorted_set(storage, key.as_bytes()).unwrap().unwrap();
+        zset.range_by_rank(0, zset.len() - 1, false).into_iter()
+            .map(|(member, score)| (String::from_utf8(member.to_vec()).unwrap(), score))
+            .collect()
+    }
+
+    fn scored(pairs: &[(&str, f64)]) -> Vec<(String, f64)> {
+        pairs.iter().map(|(member, score)| (member.to_string(), *score)).collect()
+    }
+
+    #[test]
+    fn zadd_flags_decide_what_gets_added_updated_and_counted() {
+        let mut storage = Storage::new();
+        let mut zadd = |arguments: &[&str]| zadd_command(&mut storage, &args(arguments));
+
+        assert_eq!(zadd(&["key", "1", "a", "2", "b"]).unwrap(), Command::Integer(2));
+        assert_eq!(zadd(&["key", "NX", "5", "a", "3", "c"]).unwrap(), Command::Integer(1));
+        assert_eq!(zadd(&["key", "XX", "CH", "5", "a", "4", "d"]).unwrap(), Command::Integer(1));
+        assert_eq!(zadd(&["key", "GT", "CH", "1", "a", "6", "b", "7", "e"]).unwrap(), Command::Integer(2));
+        assert_eq!(zadd(&["key", "LT", "CH", "4", "a", "9", "b"]).unwrap(), Command::Integer(1));
+        assert_eq!(zadd(&["key", "XX", "GT", "CH", "5", "a", "5", "f"]).unwrap(), Command::Integer(1));
+        assert_eq!(zadd(&["key", "CH", "5", "a", "3", "c"]).unwrap(), Command::Integer(0));
+
+        assert_eq!(zadd(&["key", "INCR", "2", "a"]).unwrap(), Command::Double(7.0));
+        assert_eq!(zadd(&["key", "INCR", "NX", "2", "a"]).unwrap(), Command::NullBulkString);
+        assert_eq!(zadd(&["key", "INCR", "XX", "1", "g"]).unwrap(), Command::NullBulkString);
+        assert_eq!(zadd(&["key", "INCR", "LT", "1", "a"]).unwrap(), Command::NullBulkString);
+        assert_eq!(zadd(&["key", "INCR", "GT", "-1", "a"]).unwrap(), Command::NullBulkString);
+        assert_eq!(zadd(&["key", "INCR", "GT", "1", "h"]).unwrap(), Command::Double(1.0));
+
+        assert!(zadd(&["key", "NX", "XX", "1", "a"]).is_err());
+        assert!(zadd(&["key", "GT", "LT", "1", "a"]).is_err());
+        assert!(zadd(&["key", "NX", "GT", "1", "a"]).is_err());
+        assert!(zadd(&["key", "INCR", "1", "a", "2", "b"]).is_err());
+
+        assert_eq!(scores(&mut storage, "key"), scored(&[("h", 1.0), ("c", 3.0), ("b", 6.0), ("a", 7.0), ("e", 7.0)]));
+    }
+
+    #[test]
+    fn zrange_reverses_and_limits_each_kind_of_range() {
+        let mut storage = Storage::new();
+        zadd_command(&mut storage, &args(&["key", "1", "a", "2", "b", "3", "c", "4", "d", "5", "e"])).unwrap();
+        zadd_command(&mut storage, &args(&["lex", "0", "a", "0", "b", "0", "c", "0", "d", "0", "e"])).unwrap();
+        let mut zrange = |arguments: &[&str]| zrange_command(&mut storage, &args(arguments)).unwrap();
+
+        assert_eq!(zrange(&["key", "1", "-2"]), members(&["b", "c", "d"]));
+        assert_eq!(zrange(&["key", "0", "1", "REV"]), members(&["e", "d"]));
+        assert_eq!(zrange(&["key", "(1", "4", "BYSCORE", "LIMIT", "1", "2"]), members(&["c", "d"]));
+        assert_eq!(zrange(&["key", "4", "(1", "BYSCORE", "REV", "LIMIT", "1", "-1"]), members(&["c", "b"]));
+        assert_eq!(zrange(&["key", "+inf", "-inf", "BYSCORE", "REV", "LIMIT", "0", "1", "WITHSCORES"]),
+                   Command::Array(vec![Command::bulk("e"), Command::Double(5.0)]));
+        assert_eq!(zrange(&["lex", "[b", "(e", "BYLEX", "LIMIT", "1", "5"]), members(&["c", "d"]));
+        assert_eq!(zrange(&["lex", "+", "(b", "BYLEX", "REV", "LIMIT", "0", "2"]), members(&["e", "d"]));
+        assert_eq!(zrange(&["key", "5", "1", "BYSCORE"]), members(&[]));
+    }
+
+    #[test]
+    fn zunionstore_and_zinterstore_weigh_and_aggregate_scores() {
+        let mut storage = Storage::new();
+        zadd_command(&mut storage, &args(&["first", "1", "a", "2", "b", "3", "c"])).unwrap();
+        zadd_command(&mut storage, &args(&["second", "10", "b", "20", "c", "30", "d"])).unwrap();
+
+        let reply = zunionstore_command(&mut storage, &args(&["out", "2", "first", "second", "WEIGHTS", "2", "0.5"])).unwrap();
+        assert_eq!(reply, Command::Integer(4));
+        assert_eq!(scores(&mut storage, "out"), scored(&[("a", 2.0), ("b", 9.0), ("d", 15.0), ("c", 16.0)]));
+
+        zunionstore_command(&mut storage, &args(&["out", "2", "first", "second", "AGGREGATE", "MIN"])).unwrap();
+        assert_eq!(scores(&mut storage, "out"), scored(&[("a", 1.0), ("b", 2.0), ("c", 3.0), ("d", 30.0)]));
+
+        let reply = zinterstore_command(&mut storage, &args(&["out", "2", "first", "second", "WEIGHTS", "-1", "1", "AGGREGATE", "MAX"])).unwrap();
+        assert_eq!(reply, Command::Integer(2));
+        assert_eq!(scores(&mut storage, "out"), scored(&[("b", 10.0), ("c", 20.0)]));
+
+        zinterstore_command(&mut storage, &args(&["out", "2", "first", "second", "WEIGHTS", "3", "1"])).unwrap();
+        assert_eq!(scores(&mut storage, "out"), scored(&[("b", 16.0), ("c", 29.0)]));
+
+        let reply = zinterstore_command(&mut storage, &args(&["out", "2", "first", "missing"])).unwrap();
+        assert_eq!(reply, Command::Integer(0));
+        assert!(!storage.contains(b"out".as_slice()));
+
+        assert!(zunionstore_command(&mut storage, &args(&["out", "2", "first", "second", "WEIGHTS", "1"])).is_err());
+        assert!(zunionstore_command(&mut storage, &args(&["out", "2", "first", "second", "AGGREGATE", "AVG"])).is_err());
+    }
+
+    #[test]
+    fn zadd_xx_leaves_a_missing_key_alone() {
+        let mut storage = Storage::new();
+
+        assert_eq!(zadd_command(&mut storage, &args(&["key", "XX", "1", "member"])).unwrap(), Command::Integer(0));
+        assert_eq!(zadd_command(&mut storage, &args(&["key", "XX", "INCR", "1", "member"])).unwrap(), Command::NullBulkString);
+        assert_eq!(storage.len(), 0);
+        assert_eq!(storage.dirty(), 0);
+    }
+}
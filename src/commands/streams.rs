@@ -0,0 +1,1156 @@
+use crate::command_handler::Command;
+use crate::commands::{arg_bytes, arg_int, arg_str, block_on, reply, CommandResult};
+use crate::error::CommandError;
+use crate::session::Session;
+use crate::storage::{BlockedOp, ConsumerGroup, EventClasses, PendingEntry, Storage, Stream, StreamFields, StreamId, Value};
+use crate::util::now_millis;
+use bytes::Bytes;
+use std::time::Duration;
+
+/// Entries examined per entry claimed by XAUTOCLAIM, as in Redis.
+const XAUTOCLAIM_ATTEMPTS_FACTOR: usize = 10;
+
+#[derive(Clone, Copy)]
+enum Trim {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// MAXLEN | MINID [= | ~] threshold [LIMIT count], shared by XADD and XTRIM.
+/// Approximate trimming is done exactly, which never keeps fewer entries
+/// than asked for.
+struct TrimOptions {
+    trim: Trim,
+    limit: usize,
+}
+
+/// The ID given for a stream in XREAD and XREADGROUP.
+#[derive(Clone, Copy)]
+enum ReadFrom {
+    /// An explicit ID: entries after it, or history for XREADGROUP
+    Id(StreamId),
+    /// `$`: only entries added after the call
+    Last,
+    /// `>`: entries never delivered to the group
+    Undelivered,
+}
+
+/// XADD key [NOMKSTREAM] [MAXLEN | MINID [= | ~] threshold [LIMIT count]]
+/// * | id field value [field value ...]
+pub fn xadd_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let mut no_mkstream = false;
+    let mut trim = None;
+    let mut i = 1;
+    while i < args.len() {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "NOMKSTREAM" => no_mkstream = true,
+            "MAXLEN" | "MINID" => {
+                let (options, next) = TrimOptions::parse(args, i)?;
+                trim = Some(options);
+                i = next;
+                continue;
+            }
+            _ => break
+        }
+        i += 1;
+    }
+
+    let fields = &args[(i + 1).min(args.len())..];
+    if fields.is_empty() || !fields.len().is_multiple_of(2) {
+        return Err(CommandError::wrong_number_of_arguments("xadd"));
+    }
+    let fields = (0..fields.len()).step_by(2)
+        .map(|j| Ok((arg_bytes(fields, j)?, arg_bytes(fields, j + 1)?)))
+        .collect::<Result<StreamFields, CommandError>>()?;
+
+    let empty = Stream::new();
+    let id = match stream(storage, &key)? {
+        Some(stream) => next_id(stream, &arg_bytes(args, i)?)?,
+        None if no_mkstream => return Ok(Command::NullBulkString),
+        None => next_id(&empty, &arg_bytes(args, i)?)?,
+    };
+
+    let stream = storage.get_or_insert(&key, || Value::Stream(Stream::new())).as_stream_mut()?;
+    stream.append(id, fields);
+    let trimmed = trim.map_or(0, |options| options.apply(stream));
+
+    // Replicas get the ID that was picked rather than generating their own
+    let mut propagated = vec![Command::bulk("XADD")];
+    propagated.extend_from_slice(args);
+    propagated[i + 1] = Command::bulk(id.to_string());
+    storage.propagate(propagated);
+
+    storage.notify(EventClasses::STREAM, "xadd", &key);
+    if trimmed > 0 {
+        storage.notify(EventClasses::STREAM, "xtrim", &key);
+    }
+    storage.signal_ready(&key);
+    Ok(Command::bulk(id.to_string()))
+}
+
+/// XTRIM key MAXLEN | MINID [= | ~] threshold [LIMIT count]
+pub fn xtrim_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let (options, next) = TrimOptions::parse(args, 1)?;
+    if next != args.len() {
+        return Err(CommandError::Syntax);
+    }
+
+    let key = arg_bytes(args, 0)?;
+    let removed = match stream_mut(storage, &key)? {
+        Some(stream) => options.apply(stream),
+        None => 0
+    };
+    if removed > 0 {
+        storage.notify(EventClasses::STREAM, "xtrim", &key);
+    }
+
+    Ok(Command::Integer(removed as i64))
+}
+
+pub fn xlen_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let len = stream(storage, &arg_bytes(args, 0)?)?.map_or(0, |stream| stream.len());
+
+    Ok(Command::Integer(len as i64))
+}
+
+/// XRANGE key start end [COUNT count]
+pub fn xrange_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    range(storage, args, false)
+}
+
+/// XREVRANGE key end start [COUNT count]
+pub fn xrevrange_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    range(storage, args, true)
+}
+
+pub fn xdel_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let ids = (1..args.len()).map(|i| parse_id(args, i, 0)).collect::<Result<Vec<_>, _>>()?;
+
+    let key = arg_bytes(args, 0)?;
+    let deleted = match stream_mut(storage, &key)? {
+        Some(stream) => ids.iter().filter(|id| stream.delete(id)).count(),
+        None => 0
+    };
+    if deleted > 0 {
+        storage.notify(EventClasses::STREAM, "xdel", &key);
+    }
+
+    Ok(Command::Integer(deleted as i64))
+}
+
+/// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+pub async fn xread_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    let mut count = usize::MAX;
+    let mut timeout = None;
+    let mut i = 0;
+    loop {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "COUNT" => count = parse_count(args, i + 1)?,
+            "BLOCK" => timeout = Some(parse_block(args, i + 1)?),
+            "STREAMS" => break,
+            _ => return Err(CommandError::Syntax)
+        }
+        i += 2;
+    }
+
+    let (keys, ids) = parse_streams(args, i + 1, "xread", false)?;
+
+    let op_keys = keys.clone();
+    let mut resolved: Option<Vec<StreamId>> = None;
+    let op: BlockedOp = Box::new(move |storage| {
+        // `$` means the last ID as of the first attempt, not of later retries
+        let after = match &resolved {
+            Some(after) => after.clone(),
+            None => {
+                let mut after = vec![];
+                for (key, id) in op_keys.iter().zip(&ids) {
+                    after.push(match id {
+                        ReadFrom::Id(id) => *id,
+                        _ => stream(storage, key)?.map_or(StreamId::MIN, |stream| stream.last_id),
+                    });
+                }
+                resolved = Some(after.clone());
+                after
+            }
+        };
+
+        let mut replies = vec![];
+        for (key, after) in op_keys.iter().zip(after) {
+            if let Some(stream) = stream(storage, key)? {
+                let entries = stream.after(after, count);
+                if !entries.is_empty() {
+                    replies.push(Command::Array(vec![Command::BulkString(key.clone()), entries_reply(entries)]));
+                }
+            }
+        }
+
+        Ok(if replies.is_empty() { None } else { Some(Command::Array(replies)) })
+    });
+
+    let command = read(session, keys, timeout, op).await?;
+    reply(session, command).await
+}
+
+/// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds]
+/// [NOACK] STREAMS key [key ...] id [id ...]
+pub async fn xreadgroup_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    if !arg_str(args, 0)?.eq_ignore_ascii_case("GROUP") || args.len() < 3 {
+        return Err(CommandError::Syntax);
+    }
+    let group_name = arg_bytes(args, 1)?;
+    let consumer = arg_bytes(args, 2)?;
+
+    let mut count = usize::MAX;
+    let mut timeout = None;
+    let mut no_ack = false;
+    let mut i = 3;
+    loop {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "COUNT" => count = parse_count(args, i + 1)?,
+            "BLOCK" => timeout = Some(parse_block(args, i + 1)?),
+            "NOACK" => {
+                no_ack = true;
+                i += 1;
+                continue;
+            }
+            "STREAMS" => break,
+            _ => return Err(CommandError::Syntax)
+        }
+        i += 2;
+    }
+
+    let (keys, ids) = parse_streams(args, i + 1, "xreadgroup", true)?;
+
+    // Only reads of new entries block; history is always answered right away
+    if ids.iter().any(|id| !matches!(id, ReadFrom::Undelivered)) {
+        timeout = None;
+    }
+
+    let op_keys = keys.clone();
+    let op: BlockedOp = Box::new(move |storage| {
+        let now = now_millis();
+        let mut replies = vec![];
+        let mut found = false;
+        let mut delivered = vec![];
+
+        // Every group must exist before anything is delivered from any of them
+        for key in &op_keys {
+            if !stream(storage, key)?.is_some_and(|stream| stream.groups.contains_key(&group_name)) {
+                return Err(CommandError::Custom(format!(
+                    "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                    String::from_utf8_lossy(key), String::from_utf8_lossy(&group_name)
+                )));
+            }
+        }
+
+        for (key, id) in op_keys.iter().zip(&ids) {
+            let stream = stream_mut(storage, key)?.unwrap();
+            let group = stream.groups.get_mut(&group_name).unwrap();
+            group.consumer(&consumer, now).seen_time = now;
+
+            let entries = match id {
+                ReadFrom::Undelivered => {
+                    let entries: Vec<(StreamId, StreamFields)> = stream.after(stream.groups[&group_name].last_delivered, count)
+                        .into_iter()
+                        .map(|(id, fields)| (id, fields.clone()))
+                        .collect();
+                    if entries.is_empty() {
+                        continue;
+                    }
+
+                    for (id, _) in &entries {
+                        deliver(stream, &group_name, &consumer, *id, no_ack, now);
+                    }
+                    delivered.push((key.clone(), entries.len()));
+                    found = true;
+                    entries.into_iter().map(|(id, fields)| entry_reply(id, Some(&fields))).collect()
+                }
+                ReadFrom::Id(after) => {
+                    found = true;
+                    let pending: Vec<StreamId> = group.consumers[&consumer].pending
+                        .range(after.next().unwrap_or(StreamId::MAX)..)
+                        .take(count)
+                        .copied()
+                        .collect();
+
+                    // Entries deleted since delivery are reported with nil fields
+                    pending.into_iter().map(|id| entry_reply(id, stream.entries.get(&id))).collect()
+                }
+                ReadFrom::Last => unreachable!(),
+            };
+
+            replies.push(Command::Array(vec![Command::BulkString(key.clone()), Command::Array(entries)]));
+        }
+
+        // Replicas hand the same entries to the consumer, without blocking
+        for (key, count) in delivered {
+            let mut propagated = vec![
+                Command::bulk("XREADGROUP"), Command::bulk("GROUP"), Command::BulkString(group_name.clone()),
+                Command::BulkString(consumer.clone()), Command::bulk("COUNT"), Command::bulk(count.to_string()),
+            ];
+            if no_ack {
+                propagated.push(Command::bulk("NOACK"));
+            }
+            propagated.extend([Command::bulk("STREAMS"), Command::BulkString(key), Command::bulk(">")]);
+            storage.propagate(propagated);
+        }
+
+        Ok(if found { Some(Command::Array(replies)) } else { None })
+    });
+
+    let command = read(session, keys, timeout, op).await?;
+    reply(session, command).await
+}
+
+/// XACK key group id [id ...]
+pub fn xack_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let group_name = arg_bytes(args, 1)?;
+    let ids = (2..args.len()).map(|i| parse_id(args, i, 0)).collect::<Result<Vec<_>, _>>()?;
+
+    let group = match stream_mut(storage, &arg_bytes(args, 0)?)?.and_then(|stream| stream.groups.get_mut(&group_name)) {
+        Some(group) => group,
+        None => return Ok(Command::Integer(0))
+    };
+
+    let acknowledged = ids.iter().filter(|id| group.acknowledge(id)).count();
+    if acknowledged > 0 {
+        storage.mark_dirty();
+    }
+    Ok(Command::Integer(acknowledged as i64))
+}
+
+/// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+pub fn xpending_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let group_name = arg_bytes(args, 1)?;
+
+    let mut min_idle = 0;
+    let mut i = 2;
+    if args.len() > 2 && arg_str(args, 2)?.eq_ignore_ascii_case("IDLE") {
+        min_idle = arg_int(args, 3)?;
+        i = 4;
+    }
+
+    let extended = match args.len() - i {
+        0 if i == 2 => None,
+        3 | 4 => Some((
+            parse_range_start(args, i)?,
+            parse_range_end(args, i + 1)?,
+            arg_int(args, i + 2)?.max(0) as usize,
+            args.get(i + 3).map(|_| arg_bytes(args, i + 3)).transpose()?,
+        )),
+        _ => return Err(CommandError::Syntax)
+    };
+
+    let group = group(storage, &key, &group_name)?;
+
+    let (start, end, count, consumer) = match extended {
+        Some(extended) => extended,
+        None => {
+            if group.pending.is_empty() {
+                return Ok(Command::Array(vec![Command::Integer(0), Command::NullBulkString, Command::NullBulkString, Command::NullArray]));
+            }
+
+            let consumers = group.consumers.iter()
+                .filter(|(_, consumer)| !consumer.pending.is_empty())
+                .map(|(name, consumer)| Command::Array(vec![Command::BulkString(name.clone()), Command::bulk(consumer.pending.len().to_string())]))
+                .collect();
+
+            return Ok(Command::Array(vec![
+                Command::Integer(group.pending.len() as i64),
+                Command::bulk(group.pending.keys().next().unwrap().to_string()),
+                Command::bulk(group.pending.keys().next_back().unwrap().to_string()),
+                Command::Array(consumers),
+            ]));
+        }
+    };
+
+    if start > end {
+        return Ok(Command::Array(vec![]));
+    }
+
+    let now = now_millis();
+    let entries = group.pending.range(start..=end)
+        .filter(|(_, entry)| consumer.as_ref().is_none_or(|consumer| *consumer == entry.consumer))
+        .filter(|(_, entry)| now - entry.delivery_time >= min_idle)
+        .take(count)
+        .map(|(id, entry)| Command::Array(vec![
+            Command::bulk(id.to_string()),
+            Command::BulkString(entry.consumer.clone()),
+            Command::Integer(now - entry.delivery_time),
+            Command::Integer(entry.delivery_count as i64),
+        ]))
+        .collect();
+
+    Ok(Command::Array(entries))
+}
+
+/// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms]
+/// [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]
+/// [LASTID lastid]
+pub fn xclaim_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let group_name = arg_bytes(args, 1)?;
+    let consumer = arg_bytes(args, 2)?;
+    let min_idle = parse_min_idle(args, 3)?;
+
+    let mut ids = vec![];
+    let mut i = 4;
+    while i < args.len() {
+        match StreamId::parse(&arg_bytes(args, i)?, 0) {
+            Some(id) => ids.push(id),
+            None => break
+        }
+        i += 1;
+    }
+
+    let now = now_millis();
+    let (mut delivery_time, mut retry_count, mut force, mut just_id, mut last_id) = (now, None, false, false, None);
+    while i < args.len() {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "IDLE" if i + 1 < args.len() => {
+                delivery_time = now - arg_int(args, i + 1)?;
+                i += 1;
+            }
+            "TIME" if i + 1 < args.len() => {
+                delivery_time = arg_int(args, i + 1)?;
+                i += 1;
+            }
+            "RETRYCOUNT" if i + 1 < args.len() => {
+                retry_count = Some(arg_int(args, i + 1)?.max(0) as u64);
+                i += 1;
+            }
+            "LASTID" if i + 1 < args.len() => {
+                last_id = Some(parse_id(args, i + 1, 0)?);
+                i += 1;
+            }
+            "FORCE" => force = true,
+            "JUSTID" => just_id = true,
+            option => return Err(CommandError::Other(format!("Unrecognized XCLAIM option '{}'", option)))
+        }
+        i += 1;
+    }
+
+    let stream = match stream_mut(storage, &key)? {
+        Some(stream) if stream.groups.contains_key(&group_name) => stream,
+        _ => return Err(no_such_group(&key, &group_name))
+    };
+
+    let (mut claimed, mut changed_ids) = (vec![], vec![]);
+    let mut moved_last_id = false;
+    for id in ids {
+        let exists = stream.entries.contains_key(&id);
+        let group = stream.groups.get_mut(&group_name).unwrap();
+
+        if let Some(last_id) = last_id.filter(|last_id| *last_id > group.last_delivered) {
+            group.last_delivered = last_id;
+            moved_last_id = true;
+        }
+
+        // Entries deleted from the stream can't be claimed and leave the PEL
+        if !exists {
+            if let Some(entry) = group.pending.get(&id).cloned() {
+                group.acknowledge(&id);
+                changed_ids.push((id, entry));
+            }
+            continue;
+        }
+
+        // Like in Redis, a zero min-idle-time skips the check, so that the
+        // claims replicas get apply whatever their clocks say
+        let delivery_count = match group.pending.get(&id) {
+            Some(entry) if min_idle > 0 && now - entry.delivery_time < min_idle => continue,
+            Some(entry) => entry.delivery_count,
+            None if force => 0,
+            None => continue
+        };
+
+        let delivery_count = retry_count.unwrap_or(delivery_count + if just_id { 0 } else { 1 });
+        group.assign(id, &consumer, now, delivery_count);
+        group.pending.get_mut(&id).unwrap().delivery_time = delivery_time;
+        group.consumer(&consumer, now).active_time = Some(now);
+        changed_ids.push((id, group.pending[&id].clone()));
+
+        claimed.push(if just_id { Command::bulk(id.to_string()) } else { entry_reply(id, stream.entries.get(&id)) });
+    }
+
+    let group = stream.groups.get_mut(&group_name).unwrap();
+    group.consumer(&consumer, now).seen_time = now;
+    let (last_delivered, entries_read) = (group.last_delivered, group.entries_read);
+
+    for (id, entry) in changed_ids.iter() {
+        storage.propagate(claim_command(&key, &group_name, &consumer, *id, entry, last_delivered));
+    }
+    if moved_last_id && changed_ids.is_empty() {
+        storage.propagate(set_group_id_command(&key, &group_name, last_delivered, entries_read));
+    }
+    Ok(Command::Array(claimed))
+}
+
+/// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+pub fn xautoclaim_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let group_name = arg_bytes(args, 1)?;
+    let consumer = arg_bytes(args, 2)?;
+    let min_idle = parse_min_idle(args, 3)?;
+    let start = parse_range_start(args, 4)?;
+
+    let (mut count, mut just_id) = (100, false);
+    let mut i = 5;
+    while i < args.len() {
+        match arg_str(args, i)?.to_uppercase().as_str() {
+            "COUNT" if i + 1 < args.len() => {
+                count = match arg_int(args, i + 1)? {
+                    count if count >= 1 && (count as usize) < usize::MAX / XAUTOCLAIM_ATTEMPTS_FACTOR => count as usize,
+                    _ => return Err(CommandError::Other(String::from("COUNT must be > 0")))
+                };
+                i += 1;
+            }
+            "JUSTID" => just_id = true,
+            _ => return Err(CommandError::Syntax)
+        }
+        i += 1;
+    }
+
+    let stream = match stream_mut(storage, &key)? {
+        Some(stream) if stream.groups.contains_key(&group_name) => stream,
+        _ => return Err(no_such_group(&key, &group_name))
+    };
+
+    let now = now_millis();
+    let group = stream.groups.get_mut(&group_name).unwrap();
+    let mut attempts = count * XAUTOCLAIM_ATTEMPTS_FACTOR;
+    let mut candidates = vec![];
+    let mut next_cursor = StreamId::MIN;
+    for (id, entry) in group.pending.range(start..) {
+        if attempts == 0 || candidates.len() == count {
+            next_cursor = *id;
+            break;
+        }
+        attempts -= 1;
+
+        if now - entry.delivery_time >= min_idle {
+            candidates.push((*id, entry.delivery_count));
+        }
+    }
+
+    let (mut claimed, mut deleted, mut changed_ids) = (vec![], vec![], vec![]);
+    for (id, delivery_count) in candidates {
+        let group = stream.groups.get_mut(&group_name).unwrap();
+        if !stream.entries.contains_key(&id) {
+            changed_ids.push((id, group.pending[&id].clone()));
+            group.acknowledge(&id);
+            deleted.push(Command::bulk(id.to_string()));
+            continue;
+        }
+
+        group.assign(id, &consumer, now, delivery_count + if just_id { 0 } else { 1 });
+        group.consumer(&consumer, now).active_time = Some(now);
+        changed_ids.push((id, group.pending[&id].clone()));
+        claimed.push(if just_id { Command::bulk(id.to_string()) } else { entry_reply(id, stream.entries.get(&id)) });
+    }
+
+    let group = stream.groups.get_mut(&group_name).unwrap();
+    group.consumer(&consumer, now).seen_time = now;
+    let last_delivered = group.last_delivered;
+
+    // Replicas would judge the idle times by their own clocks, so they get
+    // the claims that were made instead
+    for (id, entry) in changed_ids.iter() {
+        storage.propagate(claim_command(&key, &group_name, &consumer, *id, entry, last_delivered));
+    }
+    Ok(Command::Array(vec![
+        Command::bulk(next_cursor.to_string()),
+        Command::Array(claimed),
+        Command::Array(deleted),
+    ]))
+}
+
+/// XGROUP CREATE | SETID | DESTROY | CREATECONSUMER | DELCONSUMER | HELP
+pub fn xgroup_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let subcommand = arg_str(args, 0)?.to_uppercase();
+    if subcommand == "HELP" {
+        return Ok(help_reply(&[
+            "XGROUP <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "CREATE <key> <groupname> <id|$> [option]",
+            "    Create a new consumer group. Options are:",
+            "    * MKSTREAM",
+            "      Create the empty stream if it does not exist.",
+            "    * ENTRIESREAD entries_read",
+            "      Set the group's entries_read counter (internal use).",
+            "CREATECONSUMER <key> <groupname> <consumer>",
+            "    Create a new consumer in the specified group.",
+            "DELCONSUMER <key> <groupname> <consumer>",
+            "    Remove the specified consumer.",
+            "DESTROY <key> <groupname>",
+            "    Remove the specified group.",
+            "SETID <key> <groupname> <id|$> [ENTRIESREAD entries_read]",
+            "    Set the current group ID and entries_read counter.",
+            "HELP",
+            "    Print this help.",
+        ]));
+    }
+
+    let arity_ok = match subcommand.as_str() {
+        "CREATE" => (4..=7).contains(&args.len()),
+        "SETID" => args.len() == 4 || args.len() == 6,
+        "DESTROY" => args.len() == 3,
+        "CREATECONSUMER" | "DELCONSUMER" => args.len() == 4,
+        _ => return Err(CommandError::Other(format!(
+            "unknown subcommand '{}'. Try XGROUP HELP.", arg_str(args, 0)?
+        )))
+    };
+    if !arity_ok {
+        return Err(CommandError::Other(format!(
+            "wrong number of arguments for 'xgroup|{}' command", subcommand.to_lowercase()
+        )));
+    }
+
+    let key = arg_bytes(args, 1)?;
+    let group_name = arg_bytes(args, 2)?;
+    let now = now_millis();
+
+    let mut mkstream = false;
+    let mut entries_read = None;
+    if subcommand == "CREATE" || subcommand == "SETID" {
+        let mut i = 4;
+        while i < args.len() {
+            match arg_str(args, i)?.to_uppercase().as_str() {
+                "MKSTREAM" if subcommand == "CREATE" => mkstream = true,
+                "ENTRIESREAD" if i + 1 < args.len() => {
+                    entries_read = match arg_int(args, i + 1)? {
+                        read if read >= 0 => Some(read as u64),
+                        -1 => None,
+                        _ => return Err(CommandError::Other(String::from("value for ENTRIESREAD must be positive or -1")))
+                    };
+                    i += 1;
+                }
+                _ => return Err(CommandError::Syntax)
+            }
+            i += 1;
+        }
+    }
+
+    if stream(storage, &key)?.is_none() {
+        if !mkstream {
+            return Err(CommandError::Other(String::from(
+                "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically."
+            )));
+        }
+        storage.get_or_insert(&key, || Value::Stream(Stream::new()));
+    }
+    let stream = stream_mut(storage, &key)?.unwrap();
+
+    let (reply, changed) = match subcommand.as_str() {
+        "CREATE" | "SETID" => {
+            let id = match arg_bytes(args, 3)?.as_ref() {
+                b"$" => stream.last_id,
+                _ => parse_id(args, 3, 0)?,
+            };
+
+            if subcommand == "CREATE" {
+                if stream.groups.contains_key(&group_name) {
+                    return Err(CommandError::Custom(String::from("BUSYGROUP Consumer Group name already exists")));
+                }
+                stream.groups.insert(group_name, ConsumerGroup::new(id, entries_read));
+            } else {
+                let group = stream.groups.get_mut(&group_name).ok_or_else(|| no_such_group_for_key(&key, &group_name))?;
+                group.last_delivered = id;
+                group.entries_read = entries_read;
+            }
+
+            (Command::SimpleString("OK".to_string()), true)
+        }
+        "DESTROY" => {
+            let destroyed = stream.groups.remove(&group_name).is_some();
+            (Command::Integer(destroyed as i64), destroyed)
+        }
+        "CREATECONSUMER" => {
+            let group = stream.groups.get_mut(&group_name).ok_or_else(|| no_such_group_for_key(&key, &group_name))?;
+            let consumer = arg_bytes(args, 3)?;
+            if group.consumers.contains_key(&consumer) {
+                (Command::Integer(0), false)
+            } else {
+                group.consumer(&consumer, now);
+                (Command::Integer(1), true)
+            }
+        }
+        _ => {
+            let group = stream.groups.get_mut(&group_name).ok_or_else(|| no_such_group_for_key(&key, &group_name))?;
+            let removed = group.consumers.remove(&arg_bytes(args, 3)?);
+            let pending = match &removed {
+                Some(consumer) => {
+                    for id in &consumer.pending {
+                        group.pending.remove(id);
+                    }
+                    consumer.pending.len()
+                }
+                None => 0
+            };
+
+            (Command::Integer(pending as i64), removed.is_some())
+        }
+    };
+
+    if changed {
+        storage.notify(EventClasses::STREAM, &format!("xgroup-{}", subcommand.to_lowercase()), &key);
+    }
+
+    // Clients blocked in XREADGROUP on a destroyed group get an error
+    storage.signal_ready(&key);
+    Ok(reply)
+}
+
+/// XINFO STREAM key [FULL [COUNT count]] | GROUPS key | CONSUMERS key group | HELP
+pub fn xinfo_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let subcommand = arg_str(args, 0)?.to_uppercase();
+    if subcommand == "HELP" {
+        return Ok(help_reply(&[
+            "XINFO <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "CONSUMERS <key> <groupname>",
+            "    Show consumers of <groupname>.",
+            "GROUPS <key>",
+            "    Show the stream consumer groups.",
+            "STREAM <key> [FULL [COUNT <count>]",
+            "    Show information about the stream.",
+            "HELP",
+            "    Print this help.",
+        ]));
+    }
+
+    let key = arg_bytes(args, 1)?;
+    let stream = match stream(storage, &key)? {
+        Some(stream) => stream,
+        None => return Err(CommandError::Other(String::from("no such key")))
+    };
+    let now = now_millis();
+
+    match subcommand.as_str() {
+        "STREAM" => {
+            let full_count = match args.len() {
+                2 => None,
+                3 if arg_str(args, 2)?.eq_ignore_ascii_case("FULL") => Some(10),
+                5 if arg_str(args, 2)?.eq_ignore_ascii_case("FULL") && arg_str(args, 3)?.eq_ignore_ascii_case("COUNT") => {
+                    Some(match arg_int(args, 4)? {
+                        0 => usize::MAX,
+                        count => count.max(0) as usize,
+                    })
+                }
+                _ => return Err(CommandError::Syntax)
+            };
+
+            Ok(stream_info(stream, full_count))
+        }
+        "GROUPS" if args.len() == 2 => {
+            let groups = stream.groups.iter()
+                .map(|(name, group)| Command::Map(vec![
+                    field("name", Command::BulkString(name.clone())),
+                    field("consumers", Command::Integer(group.consumers.len() as i64)),
+                    field("pending", Command::Integer(group.pending.len() as i64)),
+                    field("last-delivered-id", Command::bulk(group.last_delivered.to_string())),
+                    field("entries-read", optional_integer(group.entries_read)),
+                    field("lag", optional_integer(stream.lag(group))),
+                ]))
+                .collect();
+
+            Ok(Command::Array(groups))
+        }
+        "CONSUMERS" if args.len() == 3 => {
+            let group_name = arg_bytes(args, 2)?;
+            let group = stream.groups.get(&group_name).ok_or_else(|| no_such_group_for_key(&key, &group_name))?;
+
+            let consumers = group.consumers.iter()
+                .map(|(name, consumer)| Command::Map(vec![
+                    field("name", Command::BulkString(name.clone())),
+                    field("pending", Command::Integer(consumer.pending.len() as i64)),
+                    field("idle", Command::Integer(now - consumer.seen_time)),
+                    field("inactive", Command::Integer(consumer.active_time.map_or(-1, |active| now - active))),
+                ]))
+                .collect();
+
+            Ok(Command::Array(consumers))
+        }
+        "GROUPS" | "CONSUMERS" => Err(CommandError::Other(format!(
+            "wrong number of arguments for 'xinfo|{}' command", subcommand.to_lowercase()
+        ))),
+        _ => Err(CommandError::Other(format!("unknown subcommand '{}'. Try XINFO HELP.", arg_str(args, 0)?)))
+    }
+}
+
+impl TrimOptions {
+    /// Parses the trimming clause starting at `args[index]`, returning it with
+    /// the index of the first argument after it.
+    fn parse(args: &[Command], index: usize) -> Result<(Self, usize), CommandError> {
+        let strategy = arg_str(args, index)?.to_uppercase();
+
+        let mut i = index + 1;
+        let approximate = match arg_str(args, i)?.as_str() {
+            "~" => true,
+            "=" => false,
+            _ => {
+                i -= 1;
+                false
+            }
+        };
+        i += 1;
+
+        let trim = match strategy.as_str() {
+            "MAXLEN" => match arg_int(args, i)? {
+                max_len if max_len >= 0 => Trim::MaxLen(max_len as usize),
+                _ => return Err(CommandError::Other(String::from("The MAXLEN argument must be >= 0.")))
+            },
+            "MINID" => Trim::MinId(parse_id(args, i, 0)?),
+            _ => return Err(CommandError::Syntax)
+        };
+        i += 1;
+
+        let mut limit = usize::MAX;
+        if i < args.len() && arg_str(args, i)?.eq_ignore_ascii_case("LIMIT") {
+            if !approximate {
+                return Err(CommandError::Other(String::from("syntax error, LIMIT cannot be used without the special ~ option")));
+            }
+            limit = match arg_int(args, i + 1)? {
+                0 => usize::MAX,
+                limit if limit > 0 => limit as usize,
+                _ => return Err(CommandError::Other(String::from("The LIMIT argument must be >= 0.")))
+            };
+            i += 2;
+        }
+
+        Ok((TrimOptions { trim, limit }, i))
+    }
+
+    /// Trims `stream`, returning how many entries were removed.
+    fn apply(&self, stream: &mut Stream) -> usize {
+        match self.trim {
+            Trim::MaxLen(max_len) => stream.trim_to_len(max_len, self.limit),
+            Trim::MinId(min_id) => stream.trim_to_min_id(min_id, self.limit),
+        }
+    }
+}
+
+/// The ID XADD stores for the `*`, `ms-*` or explicit ID in `arg`.
+fn next_id(stream: &Stream, arg: &[u8]) -> Result<StreamId, CommandError> {
+    let last = stream.last_id;
+    let too_small = || CommandError::Other(String::from("The ID specified in XADD is equal or smaller than the target stream top item"));
+    let exhausted = || CommandError::Other(String::from("The stream has exhausted the last possible ID, unable to add more items"));
+
+    if arg == b"*" {
+        return stream.next_id(now_millis() as u64).ok_or_else(exhausted);
+    }
+
+    if let Some(ms) = arg.strip_suffix(b"-*") {
+        let ms = std::str::from_utf8(ms).ok().and_then(|ms| ms.parse::<u64>().ok()).ok_or_else(invalid_id)?;
+        return match ms.cmp(&last.ms) {
+            std::cmp::Ordering::Greater => Ok(StreamId::new(ms, 0)),
+            std::cmp::Ordering::Equal => last.seq.checked_add(1).map(|seq| StreamId::new(ms, seq)).ok_or_else(too_small),
+            std::cmp::Ordering::Less => Err(too_small()),
+        };
+    }
+
+    let id = StreamId::parse(arg, 0).ok_or_else(invalid_id)?;
+    if id == StreamId::MIN {
+        return Err(CommandError::Other(String::from("The ID specified in XADD must be greater than 0-0")));
+    }
+    if id <= last {
+        return Err(too_small());
+    }
+
+    Ok(id)
+}
+
+fn range(storage: &mut Storage, args: &[Command], reverse: bool) -> Result<Command, CommandError> {
+    let (start_index, end_index) = if reverse { (2, 1) } else { (1, 2) };
+    let start = parse_range_start(args, start_index)?;
+    let end = parse_range_end(args, end_index)?;
+
+    let count = match args.len() {
+        3 => usize::MAX,
+        5 if arg_str(args, 3)?.eq_ignore_ascii_case("COUNT") => arg_int(args, 4)?.max(0) as usize,
+        _ => return Err(CommandError::Syntax)
+    };
+
+    Ok(match stream(storage, &arg_bytes(args, 0)?)? {
+        Some(stream) => entries_reply(stream.range(start, end, count, reverse)),
+        None => Command::Array(vec![])
+    })
+}
+
+/// Runs a read right away, or blocking when `timeout` was given.
+async fn read(session: &mut Session, keys: Vec<Bytes>, timeout: Option<Option<Duration>>, mut op: BlockedOp) -> Result<Command, CommandError> {
+    match timeout {
+        Some(timeout) => block_on(session, keys, timeout, op, Command::NullArray).await,
+        None => {
+            let mut storage = session.lock_storage().await;
+            Ok(op(&mut storage)?.unwrap_or(Command::NullArray))
+        }
+    }
+}
+
+/// Hands `id` to `consumer`, moving the group's last delivered ID forward.
+fn deliver(stream: &mut Stream, group_name: &Bytes, consumer: &Bytes, id: StreamId, no_ack: bool, now: i64) {
+    let tombstone_after = stream.max_deleted_id > stream.groups[group_name].last_delivered;
+    let estimate = stream.estimate_entries_read(id);
+
+    let group = stream.groups.get_mut(group_name).unwrap();
+    group.entries_read = match group.entries_read {
+        Some(read) if !tombstone_after => Some(read + 1),
+        _ => estimate
+    };
+    group.last_delivered = id;
+
+    if !no_ack {
+        group.assign(id, consumer, now, 1);
+    }
+    group.consumer(consumer, now).active_time = Some(now);
+}
+
+/// The XCLAIM that makes a replica's PEL entry for `id` match `entry`, or
+/// drop it when the stream entry is gone, as Redis propagates claims.
+fn claim_command(key: &Bytes, group_name: &Bytes, consumer: &Bytes, id: StreamId, entry: &PendingEntry, last_id: StreamId) -> Vec<Command> {
+    vec![
+        Command::bulk("XCLAIM"), Command::BulkString(key.clone()), Command::BulkString(group_name.clone()),
+        Command::BulkString(consumer.clone()), Command::bulk("0"), Command::bulk(id.to_string()),
+        Command::bulk("TIME"), Command::bulk(entry.delivery_time.to_string()),
+        Command::bulk("RETRYCOUNT"), Command::bulk(entry.delivery_count.to_string()),
+        Command::bulk("FORCE"), Command::bulk("JUSTID"), Command::bulk("LASTID"), Command::bulk(last_id.to_string()),
+    ]
+}
+
+/// The XGROUP SETID that moves a replica's group to where ours is.
+fn set_group_id_command(key: &Bytes, group_name: &Bytes, last_id: StreamId, entries_read: Option<u64>) -> Vec<Command> {
+    vec![
+        Command::bulk("XGROUP"), Command::bulk("SETID"), Command::BulkString(key.clone()), Command::BulkString(group_name.clone()),
+        Command::bulk(last_id.to_string()), Command::bulk("ENTRIESREAD"), Command::bulk(entries_read.map_or(-1, |read| read as i64).to_string()),
+    ]
+}
+
+fn stream_info(stream: &Stream, full_count: Option<usize>) -> Command {
+    let mut info = vec![
+        field("length", Command::Integer(stream.len() as i64)),
+        field("radix-tree-keys", Command::Integer(stream.len().div_ceil(100) as i64)),
+        field("radix-tree-nodes", Command::Integer(stream.len().div_ceil(100) as i64 + 1)),
+        field("last-generated-id", Command::bulk(stream.last_id.to_string())),
+        field("max-deleted-entry-id", Command::bulk(stream.max_deleted_id.to_string())),
+        field("entries-added", Command::Integer(stream.entries_added as i64)),
+        field("recorded-first-entry-id", Command::bulk(stream.first_id().unwrap_or_default().to_string())),
+    ];
+
+    let count = match full_count {
+        Some(count) => count,
+        None => {
+            let first = stream.entries.first_key_value();
+            let last = stream.entries.last_key_value();
+            info.push(field("groups", Command::Integer(stream.groups.len() as i64)));
+            info.push(field("first-entry", first.map_or(Command::NullBulkString, |(id, fields)| entry_reply(*id, Some(fields)))));
+            info.push(field("last-entry", last.map_or(Command::NullBulkString, |(id, fields)| entry_reply(*id, Some(fields)))));
+            return Command::Map(info);
+        }
+    };
+
+    let groups = stream.groups.iter()
+        .map(|(name, group)| {
+            let pending = group.pending.iter()
+                .take(count)
+                .map(|(id, entry)| Command::Array(vec![
+                    Command::bulk(id.to_string()),
+                    Command::BulkString(entry.consumer.clone()),
+                    Command::Integer(entry.delivery_time),
+                    Command::Integer(entry.delivery_count as i64),
+                ]))
+                .collect();
+
+            let consumers = group.consumers.iter()
+                .map(|(consumer_name, consumer)| Command::Map(vec![
+                    field("name", Command::BulkString(consumer_name.clone())),
+                    field("seen-time", Command::Integer(consumer.seen_time)),
+                    field("active-time", Command::Integer(consumer.active_time.unwrap_or(-1))),
+                    field("pel-count", Command::Integer(consumer.pending.len() as i64)),
+                    field("pending", Command::Array(consumer.pending.iter()
+                        .take(count)
+                        .map(|id| {
+                            let entry = &group.pending[id];
+                            Command::Array(vec![
+                                Command::bulk(id.to_string()),
+                                Command::Integer(entry.delivery_time),
+                                Command::Integer(entry.delivery_count as i64),
+                            ])
+                        })
+                        .collect())),
+                ]))
+                .collect();
+
+            Command::Map(vec![
+                field("name", Command::BulkString(name.clone())),
+                field("last-delivered-id", Command::bulk(group.last_delivered.to_string())),
+                field("entries-read", optional_integer(group.entries_read)),
+                field("lag", optional_integer(stream.lag(group))),
+                field("pel-count", Command::Integer(group.pending.len() as i64)),
+                field("pending", Command::Array(pending)),
+                field("consumers", Command::Array(consumers)),
+            ])
+        })
+        .collect();
+
+    info.push(field("entries", entries_reply(stream.range(StreamId::MIN, StreamId::MAX, count, false))));
+    info.push(field("groups", Command::Array(groups)));
+    Command::Map(info)
+}
+
+/// The stream at `key`, or WRONGTYPE if the key holds another kind of value.
+fn stream<'a>(storage: &'a mut Storage, key: &[u8]) -> Result<Option<&'a Stream>, CommandError> {
+    match storage.get(key) {
+        Some(record) => Ok(Some(record.value.as_stream()?)),
+        None => Ok(None)
+    }
+}
+
+fn stream_mut<'a>(storage: &'a mut Storage, key: &[u8]) -> Result<Option<&'a mut Stream>, CommandError> {
+    match storage.get_mut(key) {
+        Some(record) => Ok(Some(record.value.as_stream_mut()?)),
+        None => Ok(None)
+    }
+}
+
+fn group<'a>(storage: &'a mut Storage, key: &Bytes, group_name: &Bytes) -> Result<&'a ConsumerGroup, CommandError> {
+    stream(storage, key)?
+        .and_then(|stream| stream.groups.get(group_name))
+        .ok_or_else(|| no_such_group(key, group_name))
+}
+
+/// Parses `STREAMS key [key ...] id [id ...]` from `args[index]` onwards.
+fn parse_streams(args: &[Command], index: usize, name: &str, group: bool) -> Result<(Vec<Bytes>, Vec<ReadFrom>), CommandError> {
+    let remaining = args.len().saturating_sub(index);
+    if remaining == 0 || !remaining.is_multiple_of(2) {
+        return Err(CommandError::Other(format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '$' must be specified.", name
+        )));
+    }
+
+    let count = remaining / 2;
+    let keys = (index..index + count).map(|i| arg_bytes(args, i)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut ids = vec![];
+    for i in index + count..args.len() {
+        ids.push(match arg_bytes(args, i)?.as_ref() {
+            b"$" if !group => ReadFrom::Last,
+            b">" if group => ReadFrom::Undelivered,
+            b"$" => return Err(CommandError::Other(String::from(
+                "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set."
+            ))),
+            b">" => return Err(CommandError::Other(String::from(
+                "The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option."
+            ))),
+            _ => ReadFrom::Id(parse_id(args, i, 0)?),
+        });
+    }
+
+    Ok((keys, ids))
+}
+
+/// A full or partial (`ms`) ID, the sequence defaulting to `missing_seq`.
+fn parse_id(args: &[Command], index: usize, missing_seq: u64) -> Result<StreamId, CommandError> {
+    StreamId::parse(&arg_bytes(args, index)?, missing_seq).ok_or_else(invalid_id)
+}
+
+/// The start of an ID interval: `-`, an ID, or `(id` to exclude it.
+fn parse_range_start(args: &[Command], index: usize) -> Result<StreamId, CommandError> {
+    let arg = arg_bytes(args, index)?;
+
+    match arg.as_ref() {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        _ => match arg.strip_prefix(b"(") {
+            Some(id) => StreamId::parse(id, 0).ok_or_else(invalid_id)?
+                .next()
+                .ok_or_else(|| CommandError::Other(String::from("invalid start ID for the interval"))),
+            None => parse_id(args, index, 0)
+        }
+    }
+}
+
+/// The end of an ID interval: `+`, an ID, or `(id` to exclude it.
+fn parse_range_end(args: &[Command], index: usize) -> Result<StreamId, CommandError> {
+    let arg = arg_bytes(args, index)?;
+
+    match arg.as_ref() {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        _ => match arg.strip_prefix(b"(") {
+            Some(id) => StreamId::parse(id, u64::MAX).ok_or_else(invalid_id)?
+                .prev()
+                .ok_or_else(|| CommandError::Other(String::from("invalid end ID for the interval"))),
+            None => parse_id(args, index, u64::MAX)
+        }
+    }
+}
+
+fn parse_count(args: &[Command], index: usize) -> Result<usize, CommandError> {
+    Ok(match arg_int(args, index)? {
+        count if count > 0 => count as usize,
+        _ => usize::MAX
+    })
+}
+
+/// A BLOCK timeout in milliseconds; `0` blocks forever and yields `None`.
+fn parse_block(args: &[Command], index: usize) -> Result<Option<Duration>, CommandError> {
+    match arg_int(args, index) {
+        Ok(0) => Ok(None),
+        Ok(millis) if millis > 0 => Ok(Some(Duration::from_millis(millis as u64))),
+        Ok(_) => Err(CommandError::Other(String::from("timeout is negative"))),
+        Err(_) => Err(CommandError::Other(String::from("timeout is not an integer or out of range")))
+    }
+}
+
+fn parse_min_idle(args: &[Command], index: usize) -> Result<i64, CommandError> {
+    arg_int(args, index).map(|idle| idle.max(0))
+        .map_err(|_| CommandError::Other(String::from("Invalid min-idle-time argument for XCLAIM")))
+}
+
+fn entry_reply(id: StreamId, fields: Option<&StreamFields>) -> Command {
+    let fields = match fields {
+        Some(fields) => Command::Array(fields.iter()
+            .flat_map(|(field, value)| [Command::BulkString(field.clone()), Command::BulkString(value.clone())])
+            .collect()),
+        None => Command::NullArray
+    };
+
+    Command::Array(vec![Command::bulk(id.to_string()), fields])
+}
+
+fn entries_reply(entries: Vec<(StreamId, &StreamFields)>) -> Command {
+    Command::Array(entries.into_iter().map(|(id, fields)| entry_reply(id, Some(fields))).collect())
+}
+
+fn field(name: &str, value: Command) -> (Command, Command) {
+    (Command::bulk(name.to_string()), value)
+}
+
+fn optional_integer(value: Option<u64>) -> Command {
+    match value {
+        Some(value) => Command::Integer(value as i64),
+        None => Command::NullBulkString
+    }
+}
+
+fn help_reply(lines: &[&str]) -> Command {
+    Command::Array(lines.iter().map(|line| Command::SimpleString(line.to_string())).collect())
+}
+
+fn invalid_id() -> CommandError {
+    CommandError::Other(String::from("Invalid stream ID specified as stream command argument"))
+}
+
+fn no_such_group(key: &[u8], group: &[u8]) -> CommandError {
+    CommandError::Custom(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'", String::from_utf8_lossy(key), String::from_utf8_lossy(group)
+    ))
+}
+
+fn no_such_group_for_key(key: &[u8], group: &[u8]) -> CommandError {
+    CommandError::Custom(format!(
+        "NOGROUP No such consumer group '{}' for key name '{}'", String::from_utf8_lossy(group), String::from_utf8_lossy(key)
+    ))
+}
@@ -0,0 +1,427 @@
+use crate::command_handler::{format_float_value, Command};
+use crate::commands::{arg_bytes, arg_int, arg_str};
+use crate::error::CommandError;
+use crate::storage::{EventClasses, Storage, StorageRecord, Value};
+use crate::util::{now_millis, parse_i64};
+use bytes::{Bytes, BytesMut};
+
+/// Largest string SETRANGE and APPEND may produce, as Redis's `proto-max-bulk-len`
+const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+
+enum SetCondition {
+    Always,
+    IfMissing,
+    IfExists,
+}
+
+enum SetExpiry {
+    None,
+    At(i64),
+    KeepTtl,
+}
+
+/// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |
+/// EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+pub fn set_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let k = arg_bytes(args, 0)?;
+    let v = arg_bytes(args, 1)?;
+
+    let mut condition = SetCondition::Always;
+    let mut expiry = SetExpiry::None;
+    let mut get = false;
+
+    let mut i = 2;
+    while i < args.len() {
+        let option = arg_str(args, i)?.to_uppercase();
+
+        match (option.as_str(), &condition, &expiry) {
+            ("NX", SetCondition::Always | SetCondition::IfMissing, _) => condition = SetCondition::IfMissing,
+            ("XX", SetCondition::Always | SetCondition::IfExists, _) => condition = SetCondition::IfExists,
+            ("GET", _, _) => get = true,
+            ("KEEPTTL", _, SetExpiry::None | SetExpiry::KeepTtl) => expiry = SetExpiry::KeepTtl,
+            ("EX" | "PX" | "EXAT" | "PXAT", _, SetExpiry::None) => {
+                i += 1;
+                expiry = SetExpiry::At(parse_expiry(&option, args, i, "set")?);
+            }
+            _ => return Err(CommandError::Syntax)
+        }
+
+        i += 1;
+    }
+
+    // SET overwrites any type, but GET can only return a string
+    let previous = if get { storage.get_string(&k)? } else { None };
+    let existing_expiry = storage.get(&k).map(|record| record.expires_at);
+
+    let should_set = match condition {
+        SetCondition::Always => true,
+        SetCondition::IfMissing => existing_expiry.is_none(),
+        SetCondition::IfExists => existing_expiry.is_some(),
+    };
+
+    if should_set {
+        let exp_at = match expiry {
+            SetExpiry::None => 0,
+            SetExpiry::At(expires_at) => expires_at,
+            SetExpiry::KeepTtl => existing_expiry.unwrap_or(0),
+        };
+
+        // Replicas get the outcome, with the deadline resolved to PXAT so they
+        // expire the key at the same moment as the master
+        let mut propagated = vec![Command::bulk("SET"), Command::BulkString(k.clone()), Command::BulkString(v.clone())];
+        if exp_at != 0 {
+            propagated.extend([Command::bulk("PXAT"), Command::bulk(exp_at.to_string())]);
+        }
+        storage.propagate(propagated);
+
+        storage.set((k.clone(), v), exp_at);
+        storage.notify(EventClasses::STRING, "set", &k);
+        if exp_at != 0 {
+            storage.notify(EventClasses::GENERIC, "expire", &k);
+        }
+    }
+
+    Ok(match (get, previous) {
+        (true, Some(value)) => Command::BulkString(value),
+        (true, None) => Command::NullBulkString,
+        (false, _) if should_set => Command::SimpleString("OK".to_string()),
+        (false, _) => Command::NullBulkString,
+    })
+}
+
+pub fn get_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    Ok(match storage.get_string(&arg_bytes(args, 0)?)? {
+        Some(value) => {
+            Command::BulkString(value)
+        }
+        None => Command::NullBulkString
+    })
+}
+
+pub fn getdel_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    Ok(match storage.get_string(&key)? {
+        Some(value) => {
+            storage.remove(&key);
+            storage.notify(EventClasses::GENERIC, "del", &key);
+            Command::BulkString(value)
+        }
+        None => Command::NullBulkString
+    })
+}
+
+/// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]
+pub fn getex_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let expiry = match args.len() {
+        1 => None,
+        2 if arg_str(args, 1)?.eq_ignore_ascii_case("PERSIST") => Some(0),
+        3 => Some(parse_expiry(&arg_str(args, 1)?.to_uppercase(), args, 2, "getex")?),
+        _ => return Err(CommandError::Syntax)
+    };
+
+    let value = match storage.get_string(&key)? {
+        Some(value) => value,
+        None => return Ok(Command::NullBulkString)
+    };
+
+    // Replicas are told the outcome, with the deadline resolved
+    match expiry {
+        Some(expires_at) if expires_at != 0 && expires_at <= now_millis() => {
+            storage.propagate(vec![Command::bulk("DEL"), Command::BulkString(key.clone())]);
+            storage.remove(&key);
+            storage.notify(EventClasses::GENERIC, "del", &key);
+        }
+        Some(0) if storage.get(&key).is_some_and(|record| record.expires_at != 0) => {
+            storage.propagate(vec![Command::bulk("PERSIST"), Command::BulkString(key.clone())]);
+            storage.set_expires_at(&key, 0);
+            storage.notify(EventClasses::GENERIC, "persist", &key);
+        }
+        Some(0) | None => (),
+        Some(expires_at) => {
+            storage.propagate(vec![Command::bulk("PEXPIREAT"), Command::BulkString(key.clone()), Command::bulk(expires_at.to_string())]);
+            storage.set_expires_at(&key, expires_at);
+            storage.notify(EventClasses::GENERIC, "expire", &key);
+        }
+    }
+
+    Ok(Command::BulkString(value))
+}
+
+pub fn mget_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let mut values = vec![];
+
+    for i in 0..args.len() {
+        // Keys holding other types read as missing rather than failing the batch
+        values.push(match storage.get(&arg_bytes(args, i)?).map(|record| record.value.as_string()) {
+            Some(Ok(value)) => Command::BulkString(value.clone()),
+            _ => Command::NullBulkString
+        });
+    }
+
+    Ok(Command::Array(values))
+}
+
+pub fn mset_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    for (k, v) in key_value_pairs(args, "mset")? {
+        storage.set((k.clone(), v), 0);
+        storage.notify(EventClasses::STRING, "set", &k);
+    }
+
+    Ok(Command::SimpleString("OK".to_string()))
+}
+
+pub fn msetnx_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let pairs = key_value_pairs(args, "msetnx")?;
+
+    if pairs.iter().any(|(k, _)| storage.contains(k)) {
+        return Ok(Command::Integer(0));
+    }
+
+    for (k, v) in pairs {
+        storage.set((k.clone(), v), 0);
+        storage.notify(EventClasses::STRING, "set", &k);
+    }
+
+    Ok(Command::Integer(1))
+}
+
+pub fn strlen_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    Ok(Command::Integer(storage.get_string(&arg_bytes(args, 0)?)?.map_or(0, |value| value.len() as i64)))
+}
+
+pub fn append_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let suffix = arg_bytes(args, 1)?;
+
+    let length = match storage.get_mut(&key) {
+        Some(record) => {
+            let current = record.value.as_string_mut()?;
+            if current.len() + suffix.len() > MAX_STRING_LENGTH {
+                return Err(CommandError::Other(String::from("string exceeds maximum allowed size (proto-max-bulk-len)")));
+            }
+
+            let mut value = BytesMut::from(current.as_ref());
+            value.extend_from_slice(&suffix);
+            *current = value.freeze();
+            current.len()
+        }
+        None => {
+            let length = suffix.len();
+            storage.set((key.clone(), suffix), 0);
+            length
+        }
+    };
+    storage.notify(EventClasses::STRING, "append", &key);
+
+    Ok(Command::Integer(length as i64))
+}
+
+pub fn getrange_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let (start, end) = (arg_int(args, 1)?, arg_int(args, 2)?);
+
+    let value = match storage.get_string(&key)? {
+        Some(value) => value,
+        None => return Ok(Command::bulk(""))
+    };
+
+    let length = value.len() as i64;
+    if length == 0 || (start < 0 && end < 0 && start > end) {
+        return Ok(Command::bulk(""));
+    }
+
+    // Negative offsets count from the end; the range is clamped to the value
+    let start = if start < 0 { (length + start).max(0) } else { start };
+    let end = if end < 0 { (length + end).max(0) } else { end.min(length - 1) };
+
+    if start > end {
+        return Ok(Command::bulk(""));
+    }
+
+    Ok(Command::BulkString(value.slice(start as usize..=end as usize)))
+}
+
+pub fn setrange_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let offset = arg_int(args, 1)?;
+    let patch = arg_bytes(args, 2)?;
+
+    if offset < 0 {
+        return Err(CommandError::Other(String::from("offset is out of range")));
+    }
+    let offset = offset as usize;
+
+    let current = storage.get_string(&key)?;
+
+    // Writing nothing neither creates nor extends the key
+    if patch.is_empty() {
+        return Ok(Command::Integer(current.map_or(0, |value| value.len() as i64)));
+    }
+
+    if offset + patch.len() > MAX_STRING_LENGTH {
+        return Err(CommandError::Other(String::from("string exceeds maximum allowed size (proto-max-bulk-len)")));
+    }
+
+    let mut value = BytesMut::from(current.clone().unwrap_or_default().as_ref());
+    if value.len() < offset + patch.len() {
+        value.resize(offset + patch.len(), 0);
+    }
+    value[offset..offset + patch.len()].copy_from_slice(&patch);
+    let length = value.len();
+
+    match storage.get_mut(&key) {
+        Some(record) => record.value = Value::String(value.freeze()),
+        None => storage.set((key.clone(), value.freeze()), 0)
+    }
+    storage.notify(EventClasses::STRING, "setrange", &key);
+
+    Ok(Command::Integer(length as i64))
+}
+
+pub fn incr_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    increment_by(storage, args, 1)
+}
+
+pub fn decr_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    increment_by(storage, args, -1)
+}
+
+pub fn incrby_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let increment = arg_int(args, 1)?;
+
+    increment_by(storage, args, increment)
+}
+
+pub fn decrby_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let decrement = arg_int(args, 1)?;
+
+    match decrement.checked_neg() {
+        Some(increment) => increment_by(storage, args, increment),
+        None => Err(CommandError::Other(String::from("decrement would overflow")))
+    }
+}
+
+pub fn incrbyfloat_command(storage: &mut Storage, args: &[Command]) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+    let increment = parse_f64(&arg_bytes(args, 1)?)?;
+
+    let current = match storage.get_string(&key)? {
+        Some(value) => parse_f64(&value)?,
+        None => 0.0
+    };
+
+    let result = current + increment;
+    if result.is_nan() || result.is_infinite() {
+        return Err(CommandError::Other(String::from("increment would produce NaN or Infinity")));
+    }
+
+    let value = Bytes::from(format_float_value(result));
+    store_keeping_ttl(storage, key.clone(), value.clone());
+    storage.notify(EventClasses::STRING, "incrbyfloat", &key);
+
+    Ok(Command::BulkString(value))
+}
+
+fn increment_by(storage: &mut Storage, args: &[Command], increment: i64) -> Result<Command, CommandError> {
+    let key = arg_bytes(args, 0)?;
+
+    let current = match storage.get_string(&key)? {
+        Some(value) => parse_i64(&value).ok_or(CommandError::NotAnInteger)?,
+        None => 0
+    };
+
+    let result = match current.checked_add(increment) {
+        Some(result) => result,
+        None => return Err(CommandError::Other(String::from("increment or decrement would overflow")))
+    };
+
+    store_keeping_ttl(storage, key.clone(), Bytes::from(result.to_string()));
+    storage.notify(EventClasses::STRING, "incrby", &key);
+
+    Ok(Command::Integer(result))
+}
+
+/// Replaces the value of `key`, keeping its TTL if it already exists.
+fn store_keeping_ttl(storage: &mut Storage, key: Bytes, value: Bytes) {
+    match storage.get_mut(&key) {
+        Some(record) => record.value = Value::String(value),
+        None => storage.insert(key, StorageRecord::new(Value::String(value), 0))
+    }
+}
+
+fn key_value_pairs(args: &[Command], name: &str) -> Result<Vec<(Bytes, Bytes)>, CommandError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(CommandError::wrong_number_of_arguments(name));
+    }
+
+    let mut pairs = vec![];
+    for i in (0..args.len()).step_by(2) {
+        pairs.push((arg_bytes(args, i)?, arg_bytes(args, i + 1)?));
+    }
+
+    Ok(pairs)
+}
+
+/// Resolves an EX/PX/EXAT/PXAT option whose amount is at `args[index]` into
+/// an absolute expiry in milliseconds.
+fn parse_expiry(option: &str, args: &[Command], index: usize, name: &str) -> Result<i64, CommandError> {
+    if index >= args.len() {
+        return Err(CommandError::Syntax);
+    }
+
+    let amount = arg_int(args, index)?;
+    if amount <= 0 {
+        return Err(invalid_expire_time(name));
+    }
+
+    let expires_at = match option {
+        "EX" => amount.checked_mul(1000).and_then(|ms| ms.checked_add(now_millis())),
+        "PX" => amount.checked_add(now_millis()),
+        "EXAT" => amount.checked_mul(1000),
+        "PXAT" => Some(amount),
+        _ => return Err(CommandError::Syntax)
+    };
+
+    expires_at.ok_or_else(|| invalid_expire_time(name))
+}
+
+pub fn parse_f64(value: &[u8]) -> Result<f64, CommandError> {
+    let invalid = || CommandError::Other(String::from("value is not a valid float"));
+
+    let string = std::str::from_utf8(value).map_err(|_| invalid())?;
+    if string.is_empty() || string.trim() != string {
+        return Err(invalid());
+    }
+
+    match string.parse::<f64>() {
+        Ok(value) if !value.is_nan() => Ok(value),
+        _ => Err(invalid())
+    }
+}
+
+fn invalid_expire_time(name: &str) -> CommandError {
+    CommandError::Other(format!("invalid expire time in '{}' command", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<Command> {
+        args.iter().map(|arg| Command::bulk(arg.to_string())).collect()
+    }
+
+    #[test]
+    fn incrbyfloat_rounds_away_binary_noise() {
+        let mut storage = Storage::new();
+        storage.set((Bytes::from("key"), Bytes::from("0.1")), 0);
+
+        assert_eq!(incrbyfloat_command(&mut storage, &args(&["key", "0.2"])).unwrap(), Command::bulk("0.3"));
+        assert_eq!(incrbyfloat_command(&mut storage, &args(&["key", "1e300"])).unwrap(), Command::bulk("1e+300"));
+        assert_eq!(incrbyfloat_command(&mut storage, &args(&["key", "-1e300"])).unwrap(), Command::bulk("0"));
+    }
+}
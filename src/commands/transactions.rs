@@ -0,0 +1,99 @@
+use crate::command_handler::Command;
+use crate::commands::{arg_bytes, reply, CommandResult};
+use crate::error::CommandError;
+use crate::server::execute;
+use crate::session::{Exec, Session, Transaction};
+use std::sync::Arc;
+
+/// MULTI
+pub async fn multi_command(session: &mut Session, _args: &[Command]) -> CommandResult {
+    if session.transaction.is_some() {
+        return Err(CommandError::Other(String::from("MULTI calls can not be nested")));
+    }
+
+    session.transaction = Some(Transaction::default());
+    reply(session, Command::SimpleString("OK".to_string())).await
+}
+
+/// EXEC
+pub async fn exec_command(session: &mut Session, _args: &[Command]) -> CommandResult {
+    let transaction = match session.transaction.take() {
+        Some(transaction) => transaction,
+        None => return Err(CommandError::Other(String::from("EXEC without MULTI")))
+    };
+
+    if transaction.aborted {
+        session.unwatch_all().await;
+        return Err(CommandError::Custom(String::from("EXECABORT Transaction discarded because of previous errors.")));
+    }
+
+    let mut storage = Arc::clone(&session.storage).lock_owned().await;
+    let changed = session.watched.iter().any(|(key, version)| storage.key_version(key) != *version);
+    session.exec = Some(Exec { storage, replies: vec![] });
+    session.unwatch_all().await;
+
+    if changed {
+        session.exec = None;
+        return reply(session, Command::NullArray).await;
+    }
+
+    for (command, args) in transaction.commands {
+        match execute(session, &command, &args).await {
+            Ok(()) => (),
+            Err(e @ CommandError::Connection(_)) => {
+                session.exec = None;
+                return Err(e);
+            }
+            Err(e) => reply(session, Command::error(e.to_string())).await?
+        }
+    }
+
+    let Exec { mut storage, replies } = session.exec.take().unwrap();
+
+    // Replicas apply the transaction's writes atomically too. A replica
+    // running its master's EXEC forwards the master's stream instead.
+    let mut writes = storage.take_propagated();
+    if !writes.is_empty() && !session.master_link {
+        writes.insert(0, Command::Array(vec![Command::bulk("MULTI")]));
+        writes.push(Command::Array(vec![Command::bulk("EXEC")]));
+        session.config.lock().await.propagate(writes);
+    }
+    drop(storage);
+
+    reply(session, Command::Array(replies)).await
+}
+
+/// DISCARD
+pub async fn discard_command(session: &mut Session, _args: &[Command]) -> CommandResult {
+    if session.transaction.take().is_none() {
+        return Err(CommandError::Other(String::from("DISCARD without MULTI")));
+    }
+
+    session.unwatch_all().await;
+    reply(session, Command::SimpleString("OK".to_string())).await
+}
+
+/// WATCH key [key ...]
+pub async fn watch_command(session: &mut Session, args: &[Command]) -> CommandResult {
+    if session.transaction.is_some() {
+        return Err(CommandError::Other(String::from("WATCH inside MULTI is not allowed")));
+    }
+
+    for i in 0..args.len() {
+        let key = arg_bytes(args, i)?;
+        if session.watched.contains_key(&key) {
+            continue;
+        }
+
+        let version = session.lock_storage().await.watch(&key);
+        session.watched.insert(key, version);
+    }
+
+    reply(session, Command::SimpleString("OK".to_string())).await
+}
+
+/// UNWATCH
+pub async fn unwatch_command(session: &mut Session, _args: &[Command]) -> CommandResult {
+    session.unwatch_all().await;
+    reply(session, Command::SimpleString("OK".to_string())).await
+}
@@ -1,4 +1,5 @@
 use crate::command_handler::{Command, CommandHandler, WriteData};
+use anyhow::Result;
 use tokio::net::TcpStream;
 
 pub struct Connection {
@@ -6,23 +7,17 @@ pub struct Connection {
 }
 
 impl Connection {
-    pub async fn new(address: String) -> Self {
-        Connection {
-            command_handler: CommandHandler::new(match TcpStream::connect(address.clone()).await {
-                Ok(stream) => stream,
-                Err(e) => {
-                    eprintln!("Error creating command handler {}", e);
-                    std::process::exit(1);
-                }
-            })
-        }
+    pub async fn new(address: String) -> Result<Self> {
+        Ok(Connection {
+            command_handler: CommandHandler::new(TcpStream::connect(address).await?)
+        })
     }
 
-    pub async fn write(&mut self, command: Command) {
-        self.command_handler.write(WriteData::Command(command)).await.unwrap()
+    pub async fn write(&mut self, command: Command) -> Result<()> {
+        self.command_handler.write(WriteData::Command(command)).await
     }
 
-    pub async fn read(&mut self) -> Option<Command> {
-        self.command_handler.read().await.unwrap()
+    pub async fn read(&mut self) -> Result<Option<Command>> {
+        self.command_handler.read().await
     }
 }
@@ -0,0 +1,31 @@
+use thiserror::Error;
+
+/// Errors a command can fail with. The `Display` output is the exact error
+/// line sent back to the client, prefix included.
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongNumberOfArguments(String),
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR syntax error")]
+    Syntax,
+    #[error("ERR unknown command '{0}', with args beginning with: {1}")]
+    UnknownCommand(String, String),
+    #[error("ERR value is not an integer or out of range")]
+    NotAnInteger,
+    #[error("ERR {0}")]
+    Other(String),
+    /// Errors that carry their own prefix, e.g. `NOPROTO`
+    #[error("{0}")]
+    Custom(String),
+    /// The reply could not be written; the connection is no longer usable
+    #[error("connection error: {0}")]
+    Connection(#[from] anyhow::Error),
+}
+
+impl CommandError {
+    pub fn wrong_number_of_arguments(command: &str) -> Self {
+        CommandError::WrongNumberOfArguments(command.to_lowercase())
+    }
+}
@@ -1,9 +1,14 @@
 mod command_handler;
+mod command_table;
 mod server;
 mod storage;
 mod commands;
 mod util;
 mod connection;
+mod error;
+mod session;
+mod rdb;
+mod backlog;
 
 use std::collections::HashMap;
 use std::env;
@@ -24,11 +29,11 @@ async fn main() {
     let mut replica_of: Option<String> = None;
 
     for (i, arg) in runtime_args.iter().enumerate() {
-        if args["port"].contains(&arg) {
+        if args["port"].contains(arg) {
             port = String::from(&runtime_args[i + 1]);
         }
 
-        if args["replicaof"].contains(&arg) {
+        if args["replicaof"].contains(arg) {
             replica_of = Some(String::from(&runtime_args[i + 1]))
         }
     }
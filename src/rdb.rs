@@ -0,0 +1,760 @@
+mod listpack;
+
+use crate::storage::{Consumer, ConsumerGroup, HashField, HashValue, PendingEntry, SetValue, SortedSet, Storage, StorageRecord, Stream, StreamFields, StreamId, Value};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+/// The version written, that of Redis 7.2. Versions up to 12 are read.
+const RDB_VERSION: u32 = 11;
+/// Written instead when a hash has field TTLs, whose type only Redis 7.4 and
+/// later can load
+const RDB_VERSION_HASH_FIELD_TTL: u32 = 12;
+const MAX_RDB_VERSION: u32 = 12;
+
+/// Stream entries per listpack node (`stream-node-max-entries`)
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
+const OPCODE_SLOT_INFO: u8 = 0xF4;
+const OPCODE_FUNCTION2: u8 = 0xF5;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+/// A hash with field TTLs, from Redis 7.4
+const TYPE_HASH_METADATA: u8 = 24;
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+/// Quicklist nodes holding a single large element rather than a listpack
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+
+/// Serializes the live keys of `storage` as an RDB file, the snapshot sent
+/// to a replica after FULLRESYNC.
+pub fn dump(storage: &Storage) -> Vec<u8> {
+    let version = match storage.keys().any(|(_, record)| record.value.has_volatile_fields()) {
+        true => RDB_VERSION_HASH_FIELD_TTL,
+        false => RDB_VERSION
+    };
+    let mut out = format!("REDIS{:04}", version).into_bytes();
+
+    for (key, value) in [("redis-ver", crate::commands::SERVER_VERSION), ("redis-bits", "64")] {
+        out.push(OPCODE_AUX);
+        write_string(&mut out, key.as_bytes());
+        write_string(&mut out, value.as_bytes());
+    }
+
+    out.push(OPCODE_SELECTDB);
+    write_length(&mut out, 0);
+    out.push(OPCODE_RESIZEDB);
+    write_length(&mut out, storage.len() as u64);
+    write_length(&mut out, storage.volatile_len() as u64);
+
+    for (key, record) in storage.keys() {
+        if record.expires_at != 0 {
+            out.push(OPCODE_EXPIRETIME_MS);
+            out.extend_from_slice(&record.expires_at.to_le_bytes());
+        }
+
+        write_value(&mut out, key, &record.value);
+    }
+
+    // A zero checksum tells the loader not to verify it
+    out.push(OPCODE_EOF);
+    out.extend_from_slice(&[0; 8]);
+    out
+}
+
+/// Reads the keys of database 0 out of an RDB file.
+pub fn load(data: &[u8]) -> Result<Vec<(Bytes, StorageRecord)>> {
+    let mut reader = Reader { data, pos: 0 };
+
+    if reader.take(5)? != b"REDIS" {
+        return Err(anyhow!("Not an RDB file"));
+    }
+    let version: u32 = std::str::from_utf8(reader.take(4)?)?.parse()?;
+    if version > MAX_RDB_VERSION {
+        return Err(anyhow!("Can't handle RDB format version {}", version));
+    }
+
+    let mut records = vec![];
+    let mut db = 0;
+    let mut expires_at = 0;
+
+    loop {
+        match reader.u8()? {
+            OPCODE_EOF => return Ok(records),
+            OPCODE_SELECTDB => db = reader.length()?,
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_AUX => {
+                reader.string()?;
+                reader.string()?;
+            }
+            OPCODE_EXPIRETIME_MS => expires_at = reader.millis()?,
+            OPCODE_EXPIRETIME => expires_at = u32::from_le_bytes(reader.array()?) as i64 * 1000,
+            OPCODE_IDLE => {
+                reader.length()?;
+            }
+            OPCODE_FREQ => {
+                reader.u8()?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.length()?;
+                }
+            }
+            OPCODE_FUNCTION2 => {
+                reader.string()?;
+            }
+            value_type => {
+                let key = reader.string()?;
+                let value = reader.value(value_type)?;
+
+                if db == 0 {
+                    records.push((key, StorageRecord::new(value, expires_at)));
+                }
+                expires_at = 0;
+            }
+        }
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, key: &[u8], value: &Value) {
+    let value_type = match value {
+        Value::String(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Set(_) => TYPE_SET,
+        Value::SortedSet(_) => TYPE_ZSET_2,
+        Value::Hash(_) if value.has_volatile_fields() => TYPE_HASH_METADATA,
+        Value::Hash(_) => TYPE_HASH,
+        Value::Stream(_) => TYPE_STREAM_LISTPACKS_3,
+    };
+    out.push(value_type);
+    write_string(out, key);
+
+    match value {
+        Value::String(string) => write_string(out, string),
+        Value::List(list) => {
+            write_length(out, list.len() as u64);
+            for element in list {
+                write_string(out, element);
+            }
+        }
+        Value::Set(set) => {
+            let members = set.members();
+            write_length(out, members.len() as u64);
+            for member in members {
+                write_string(out, &member);
+            }
+        }
+        Value::SortedSet(zset) => {
+            write_length(out, zset.len() as u64);
+            for (member, score) in zset.iter() {
+                write_string(out, member);
+                out.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+        Value::Hash(hash) if value_type == TYPE_HASH_METADATA => {
+            // Field TTLs are stored relative to the earliest one, 0 meaning none
+            let min_expire = hash.values().map(|field| field.expires_at).filter(|expires_at| *expires_at != 0).min().unwrap_or(0);
+            out.extend_from_slice(&min_expire.to_le_bytes());
+            write_length(out, hash.len() as u64);
+
+            for (name, field) in hash {
+                write_length(out, if field.expires_at == 0 { 0 } else { (field.expires_at - min_expire + 1) as u64 });
+                write_string(out, name);
+                write_string(out, &field.value);
+            }
+        }
+        Value::Hash(hash) => {
+            write_length(out, hash.len() as u64);
+            for (name, field) in hash {
+                write_string(out, name);
+                write_string(out, &field.value);
+            }
+        }
+        Value::Stream(stream) => write_stream(out, stream),
+    }
+}
+
+/// Streams are stored as listpack nodes keyed by their first ID, each
+/// starting with a master entry whose fields later entries can refer to.
+fn write_stream(out: &mut Vec<u8>, stream: &Stream) {
+    let entries: Vec<(&StreamId, &StreamFields)> = stream.entries.iter().collect();
+    let nodes = entries.chunks(STREAM_NODE_MAX_ENTRIES);
+    write_length(out, nodes.len() as u64);
+
+    for node in nodes {
+        let (master_id, master_fields) = node[0];
+        let int = |int: i64| Bytes::from(int.to_string());
+
+        let mut elements = vec![int(node.len() as i64), int(0), int(master_fields.len() as i64)];
+        elements.extend(master_fields.iter().map(|(field, _)| field.clone()));
+        elements.push(int(0));
+
+        for (id, fields) in node {
+            let same_fields = fields.len() == master_fields.len()
+                && fields.iter().zip(master_fields.iter()).all(|((field, _), (master, _))| field == master);
+
+            elements.push(int(if same_fields { STREAM_ITEM_FLAG_SAMEFIELDS } else { 0 }));
+            elements.push(int(id.ms.wrapping_sub(master_id.ms) as i64));
+            elements.push(int(id.seq.wrapping_sub(master_id.seq) as i64));
+            if same_fields {
+                elements.extend(fields.iter().map(|(_, value)| value.clone()));
+            } else {
+                elements.push(int(fields.len() as i64));
+                elements.extend(fields.iter().flat_map(|(field, value)| [field.clone(), value.clone()]));
+            }
+            elements.push(int(if same_fields { fields.len() + 3 } else { fields.len() * 2 + 4 } as i64));
+        }
+
+        write_string(out, &stream_id_bytes(master_id));
+        write_string(out, &listpack::encode(elements.iter().map(|element| element.as_ref())));
+    }
+
+    let first_id = stream.first_id().unwrap_or_default();
+    for value in [
+        stream.len() as u64, stream.last_id.ms, stream.last_id.seq, first_id.ms, first_id.seq,
+        stream.max_deleted_id.ms, stream.max_deleted_id.seq, stream.entries_added, stream.groups.len() as u64,
+    ] {
+        write_length(out, value);
+    }
+
+    for (name, group) in &stream.groups {
+        write_string(out, name);
+        write_length(out, group.last_delivered.ms);
+        write_length(out, group.last_delivered.seq);
+        write_length(out, group.entries_read.unwrap_or(u64::MAX));
+
+        write_length(out, group.pending.len() as u64);
+        for (id, entry) in &group.pending {
+            out.extend_from_slice(&stream_id_bytes(id));
+            out.extend_from_slice(&entry.delivery_time.to_le_bytes());
+            write_length(out, entry.delivery_count);
+        }
+
+        write_length(out, group.consumers.len() as u64);
+        for (name, consumer) in &group.consumers {
+            write_string(out, name);
+            out.extend_from_slice(&consumer.seen_time.to_le_bytes());
+            out.extend_from_slice(&consumer.active_time.unwrap_or(-1).to_le_bytes());
+            write_length(out, consumer.pending.len() as u64);
+            for id in &consumer.pending {
+                out.extend_from_slice(&stream_id_bytes(id));
+            }
+        }
+    }
+}
+
+/// Lengths take 6, 14, 32 or 64 bits, marked by the top bits of the first
+/// byte.
+fn write_length(out: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.extend_from_slice(&[0x40 | (len >> 8) as u8, len as u8]);
+    } else if len <= u32::MAX as u64 {
+        out.push(0x80);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, string: &[u8]) {
+    write_length(out, string.len() as u64);
+    out.extend_from_slice(string);
+}
+
+fn stream_id_bytes(id: &StreamId) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&id.ms.to_be_bytes());
+    bytes[8..].copy_from_slice(&id.seq.to_be_bytes());
+    bytes
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+/// The first byte of a length with these top bits introduces an encoded
+/// string instead: an integer or LZF-compressed data.
+enum Length {
+    Plain(u64),
+    Encoded(u8),
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self.pos.checked_add(n).ok_or_else(|| anyhow!("Truncated RDB file"))?;
+        let bytes = self.data.get(self.pos..end).ok_or_else(|| anyhow!("Truncated RDB file"))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn millis(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn raw_length(&mut self) -> Result<Length> {
+        let first = self.u8()?;
+
+        Ok(match first >> 6 {
+            0 => Length::Plain((first & 0x3F) as u64),
+            1 => Length::Plain((((first & 0x3F) as u64) << 8) | self.u8()? as u64),
+            2 if first == 0x80 => Length::Plain(u32::from_be_bytes(self.array()?) as u64),
+            2 if first == 0x81 => Length::Plain(u64::from_be_bytes(self.array()?)),
+            3 => Length::Encoded(first & 0x3F),
+            _ => return Err(anyhow!("Invalid length encoding {:#x}", first))
+        })
+    }
+
+    fn length(&mut self) -> Result<u64> {
+        match self.raw_length()? {
+            Length::Plain(len) => Ok(len),
+            Length::Encoded(_) => Err(anyhow!("Expected a length, got an encoded string"))
+        }
+    }
+
+    fn string(&mut self) -> Result<Bytes> {
+        match self.raw_length()? {
+            Length::Plain(len) => Ok(Bytes::copy_from_slice(self.take(len as usize)?)),
+            Length::Encoded(0) => Ok(Bytes::from((self.u8()? as i8).to_string())),
+            Length::Encoded(1) => Ok(Bytes::from(i16::from_le_bytes(self.array()?).to_string())),
+            Length::Encoded(2) => Ok(Bytes::from(i32::from_le_bytes(self.array()?).to_string())),
+            Length::Encoded(3) => {
+                let compressed_len = self.length()? as usize;
+                let len = self.length()? as usize;
+                Ok(Bytes::from(lzf_decompress(self.take(compressed_len)?, len)?))
+            }
+            Length::Encoded(encoding) => Err(anyhow!("Unknown string encoding {}", encoding))
+        }
+    }
+
+    fn listpack(&mut self) -> Result<Vec<Bytes>> {
+        listpack::decode(&self.string()?)
+    }
+
+    fn value(&mut self, value_type: u8) -> Result<Value> {
+        Ok(match value_type {
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_LIST => Value::List(self.strings()?.into()),
+            TYPE_LIST_QUICKLIST_2 => {
+                let mut list = VecDeque::new();
+                for _ in 0..self.length()? {
+                    if self.length()? == QUICKLIST_NODE_PLAIN {
+                        list.push_back(self.string()?);
+                    } else {
+                        list.extend(self.listpack()?);
+                    }
+                }
+                Value::List(list)
+            }
+            TYPE_SET => Value::Set(SetValue::from_members(self.strings()?)),
+            TYPE_SET_LISTPACK => Value::Set(SetValue::from_members(self.listpack()?)),
+            TYPE_SET_INTSET => Value::Set(SetValue::from_members(intset_members(&self.string()?)?)),
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut zset = SortedSet::new();
+                for _ in 0..self.length()? {
+                    let member = self.string()?;
+                    let score = if value_type == TYPE_ZSET_2 { f64::from_le_bytes(self.array()?) } else { self.old_double()? };
+                    zset.insert(member, score);
+                }
+                Value::SortedSet(zset)
+            }
+            TYPE_ZSET_LISTPACK => {
+                let mut zset = SortedSet::new();
+                for pair in groups(&self.listpack()?, 2)? {
+                    let score = std::str::from_utf8(&pair[1])?.parse()?;
+                    zset.insert(pair[0].clone(), score);
+                }
+                Value::SortedSet(zset)
+            }
+            TYPE_HASH => {
+                let mut hash = HashValue::new();
+                for _ in 0..self.length()? {
+                    hash.insert(self.string()?, HashField::new(self.string()?));
+                }
+                Value::Hash(hash)
+            }
+            TYPE_HASH_LISTPACK => Value::Hash(groups(&self.listpack()?, 2)?
+                .map(|pair| (pair[0].clone(), HashField::new(pair[1].clone())))
+                .collect()),
+            TYPE_HASH_METADATA => {
+                let min_expire = self.millis()?;
+                let mut hash = HashValue::new();
+                for _ in 0..self.length()? {
+                    let ttl = self.length()? as i64;
+                    let (name, value) = (self.string()?, self.string()?);
+                    hash.insert(name, HashField { value, expires_at: if ttl == 0 { 0 } else { min_expire + ttl - 1 } });
+                }
+                Value::Hash(hash)
+            }
+            TYPE_HASH_LISTPACK_EX => {
+                self.millis()?;
+                let mut hash = HashValue::new();
+                for triple in groups(&self.listpack()?, 3)? {
+                    let expires_at = std::str::from_utf8(&triple[2])?.parse()?;
+                    hash.insert(triple[0].clone(), HashField { value: triple[1].clone(), expires_at });
+                }
+                Value::Hash(hash)
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => Value::Stream(self.stream(value_type)?),
+            _ => return Err(anyhow!("Unsupported RDB value type {}", value_type))
+        })
+    }
+
+    fn strings(&mut self) -> Result<Vec<Bytes>> {
+        (0..self.length()?).map(|_| self.string()).collect()
+    }
+
+    /// A score stored as text, with special lengths for infinities and NaN.
+    fn old_double(&mut self) -> Result<f64> {
+        Ok(match self.u8()? {
+            253 => f64::NAN,
+            254 => f64::INFINITY,
+            255 => f64::NEG_INFINITY,
+            len => std::str::from_utf8(self.take(len as usize)?)?.parse()?
+        })
+    }
+
+    fn stream_id(&mut self) -> Result<StreamId> {
+        Ok(StreamId::new(u64::from_be_bytes(self.array()?), u64::from_be_bytes(self.array()?)))
+    }
+
+    fn length_id(&mut self) -> Result<StreamId> {
+        Ok(StreamId::new(self.length()?, self.length()?))
+    }
+
+    fn stream(&mut self, value_type: u8) -> Result<Stream> {
+        let mut stream = Stream::new();
+
+        for _ in 0..self.length()? {
+            let master_id = {
+                let key = self.string()?;
+                Reader { data: &key, pos: 0 }.stream_id()?
+            };
+            read_stream_node(&mut stream, master_id, &self.listpack()?)?;
+        }
+
+        self.length()?;
+        stream.last_id = self.length_id()?;
+        if value_type >= TYPE_STREAM_LISTPACKS_2 {
+            self.length_id()?;
+            stream.max_deleted_id = self.length_id()?;
+            stream.entries_added = self.length()?;
+        } else {
+            stream.entries_added = stream.len() as u64;
+        }
+
+        for _ in 0..self.length()? {
+            let name = self.string()?;
+            let last_delivered = self.length_id()?;
+            let entries_read = match value_type >= TYPE_STREAM_LISTPACKS_2 {
+                true => Some(self.length()?).filter(|read| *read != u64::MAX),
+                false => None
+            };
+            let mut group = ConsumerGroup::new(last_delivered, entries_read);
+
+            for _ in 0..self.length()? {
+                let id = self.stream_id()?;
+                let delivery_time = self.millis()?;
+                let delivery_count = self.length()?;
+                group.pending.insert(id, PendingEntry { consumer: Bytes::new(), delivery_time, delivery_count });
+            }
+
+            for _ in 0..self.length()? {
+                let consumer_name = self.string()?;
+                let mut consumer = Consumer::new(self.millis()?);
+                if value_type >= TYPE_STREAM_LISTPACKS_3 {
+                    consumer.active_time = Some(self.millis()?).filter(|time| *time != -1);
+                }
+
+                for _ in 0..self.length()? {
+                    let id = self.stream_id()?;
+                    if let Some(entry) = group.pending.get_mut(&id) {
+                        entry.consumer = consumer_name.clone();
+                    }
+                    consumer.pending.insert(id);
+                }
+                group.consumers.insert(consumer_name, consumer);
+            }
+
+            stream.groups.insert(name, group);
+        }
+
+        Ok(stream)
+    }
+}
+
+/// Adds the live entries of one listpack node to `stream`.
+fn read_stream_node(stream: &mut Stream, master_id: StreamId, elements: &[Bytes]) -> Result<()> {
+    let mut elements = elements.iter();
+
+    let entries = next_int(&mut elements)?.checked_add(next_int(&mut elements)?).ok_or_else(|| anyhow!("Invalid stream node"))?;
+    let master_fields: Vec<Bytes> = (0..next_int(&mut elements)?).map(|_| next(&mut elements).cloned()).collect::<Result<_>>()?;
+    next_int(&mut elements)?;
+
+    for _ in 0..entries {
+        let flags = next_int(&mut elements)?;
+        let ms = master_id.ms.wrapping_add(next_int(&mut elements)? as u64);
+        let id = StreamId::new(ms, master_id.seq.wrapping_add(next_int(&mut elements)? as u64));
+
+        let fields: StreamFields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            master_fields.iter().map(|field| Ok((field.clone(), next(&mut elements)?.clone()))).collect::<Result<_>>()?
+        } else {
+            (0..next_int(&mut elements)?).map(|_| Ok((next(&mut elements)?.clone(), next(&mut elements)?.clone()))).collect::<Result<_>>()?
+        };
+        next_int(&mut elements)?;
+
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            stream.entries.insert(id, fields);
+        }
+    }
+
+    Ok(())
+}
+
+/// `elements` in groups of `size`, e.g. the field-value pairs of a hash,
+/// failing if the last group would be incomplete.
+fn groups(elements: &[Bytes], size: usize) -> Result<std::slice::ChunksExact<'_, Bytes>> {
+    let groups = elements.chunks_exact(size);
+    if !groups.remainder().is_empty() {
+        return Err(anyhow!("Listpack of {} elements does not hold groups of {}", elements.len(), size));
+    }
+    Ok(groups)
+}
+
+fn next<'a>(elements: &mut impl Iterator<Item=&'a Bytes>) -> Result<&'a Bytes> {
+    elements.next().ok_or_else(|| anyhow!("Truncated stream node"))
+}
+
+fn next_int<'a>(elements: &mut impl Iterator<Item=&'a Bytes>) -> Result<i64> {
+    Ok(std::str::from_utf8(next(elements)?)?.parse()?)
+}
+
+fn intset_members(data: &[u8]) -> Result<Vec<Bytes>> {
+    let mut reader = Reader { data, pos: 0 };
+    let width = u32::from_le_bytes(reader.array()?) as usize;
+    let len = u32::from_le_bytes(reader.array()?);
+
+    (0..len).map(|_| {
+        let int = match width {
+            2 => i16::from_le_bytes(reader.array()?) as i64,
+            4 => i32::from_le_bytes(reader.array()?) as i64,
+            8 => i64::from_le_bytes(reader.array()?),
+            _ => return Err(anyhow!("Invalid intset encoding {}", width))
+        };
+        Ok(Bytes::from(int.to_string()))
+    }).collect()
+}
+
+/// Decompresses LZF data, which alternates literal runs with back
+/// references into the output produced so far.
+fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    let corrupt = || anyhow!("Invalid LZF data");
+
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+
+        if ctrl < 32 {
+            let literal = input.get(i..i + ctrl + 1).ok_or_else(corrupt)?;
+            out.extend_from_slice(literal);
+            i += ctrl + 1;
+        } else {
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(i).ok_or_else(corrupt)? as usize;
+                i += 1;
+            }
+            let offset = ((ctrl & 0x1F) << 8) + *input.get(i).ok_or_else(corrupt)? as usize + 1;
+            i += 1;
+
+            let start = out.len().checked_sub(offset).ok_or_else(corrupt)?;
+            for j in 0..run + 2 {
+                out.push(out[start + j]);
+            }
+        }
+    }
+
+    if out.len() != len {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn roundtrip(records: Vec<(&str, Value, i64)>) -> (Vec<u8>, HashMap<Bytes, StorageRecord>) {
+        let mut storage = Storage::new();
+        for (key, value, expires_at) in records {
+            storage.insert(Bytes::from(key.to_string()), StorageRecord::new(value, expires_at));
+        }
+
+        let rdb = dump(&storage);
+        let loaded = load(&rdb).unwrap().into_iter().collect();
+        (rdb, loaded)
+    }
+
+    fn bytes(values: &[&str]) -> Vec<Bytes> {
+        values.iter().map(|value| Bytes::from(value.to_string())).collect()
+    }
+
+    #[test]
+    fn strings_and_lists_keep_their_contents_and_ttls() {
+        let expires_at = crate::util::now_millis() + 60_000;
+        let (rdb, loaded) = roundtrip(vec![
+            ("string", Value::String(Bytes::from("value")), expires_at),
+            ("list", Value::List(bytes(&["a", "b", "a"]).into()), 0),
+        ]);
+
+        assert_eq!(&rdb[..9], b"REDIS0011");
+        assert_eq!(loaded[b"string".as_slice()].value.as_string().unwrap(), "value");
+        assert_eq!(loaded[b"string".as_slice()].expires_at, expires_at);
+        assert_eq!(loaded[b"list".as_slice()].value.as_list().unwrap(), &VecDeque::from(bytes(&["a", "b", "a"])));
+        assert_eq!(loaded[b"list".as_slice()].expires_at, 0);
+    }
+
+    #[test]
+    fn sets_keep_their_members_in_either_encoding() {
+        let (_, loaded) = roundtrip(vec![
+            ("ints", Value::Set(SetValue::from_members(bytes(&["3", "-1", "20"]))), 0),
+            ("strings", Value::Set(SetValue::from_members(bytes(&["x", "1", "y"]))), 0),
+        ]);
+
+        let mut ints = loaded[b"ints".as_slice()].value.as_set().unwrap().members();
+        ints.sort();
+        assert_eq!(ints, bytes(&["-1", "20", "3"]));
+
+        let mut strings = loaded[b"strings".as_slice()].value.as_set().unwrap().members();
+        strings.sort();
+        assert_eq!(strings, bytes(&["1", "x", "y"]));
+    }
+
+    #[test]
+    fn sorted_sets_keep_their_scores() {
+        let mut zset = SortedSet::new();
+        zset.insert(Bytes::from("a"), 1.5);
+        zset.insert(Bytes::from("b"), -2.0);
+        zset.insert(Bytes::from("c"), f64::INFINITY);
+        let (_, loaded) = roundtrip(vec![("zset", Value::SortedSet(zset), 0)]);
+
+        let zset = loaded[b"zset".as_slice()].value.as_sorted_set().unwrap();
+        assert_eq!(zset.range_by_rank(0, 2, false), vec![
+            (Bytes::from("b"), -2.0),
+            (Bytes::from("a"), 1.5),
+            (Bytes::from("c"), f64::INFINITY),
+        ]);
+    }
+
+    #[test]
+    fn hashes_without_field_ttls_load_on_older_servers() {
+        let hash = [("f", "1"), ("g", "2")].into_iter()
+            .map(|(name, value)| (Bytes::from(name), HashField::new(Bytes::from(value))))
+            .collect();
+        let (rdb, loaded) = roundtrip(vec![("hash", Value::Hash(hash), 0)]);
+
+        assert_eq!(&rdb[..9], b"REDIS0011");
+        assert!(!rdb.contains(&TYPE_HASH_METADATA));
+        let hash = loaded[b"hash".as_slice()].value.as_hash().unwrap();
+        assert_eq!(hash.len(), 2);
+        assert_eq!(hash[b"f".as_slice()].value, "1");
+        assert_eq!(hash[b"g".as_slice()].value, "2");
+    }
+
+    #[test]
+    fn hashes_with_field_ttls_bump_the_version() {
+        let expires_at = crate::util::now_millis() + 60_000;
+        let hash = [
+            (Bytes::from("f"), HashField { value: Bytes::from("1"), expires_at }),
+            (Bytes::from("g"), HashField::new(Bytes::from("2"))),
+        ].into_iter().collect();
+        let (rdb, loaded) = roundtrip(vec![("hash", Value::Hash(hash), 0)]);
+
+        assert_eq!(&rdb[..9], b"REDIS0012");
+        let hash = loaded[b"hash".as_slice()].value.as_hash().unwrap();
+        assert_eq!(hash[b"f".as_slice()].expires_at, expires_at);
+        assert_eq!(hash[b"g".as_slice()].expires_at, 0);
+    }
+
+    #[test]
+    fn streams_keep_their_entries_and_consumer_groups() {
+        let mut stream = Stream::new();
+        for ms in 1..=150 {
+            stream.append(StreamId::new(ms, 0), vec![(Bytes::from("field"), Bytes::from(ms.to_string()))]);
+        }
+        stream.append(StreamId::new(151, 0), vec![(Bytes::from("other"), Bytes::from("x"))]);
+
+        let mut group = ConsumerGroup::new(StreamId::new(2, 0), Some(2));
+        let mut consumer = Consumer::new(1000);
+        consumer.active_time = Some(2000);
+        consumer.pending.insert(StreamId::new(2, 0));
+        group.pending.insert(StreamId::new(2, 0), PendingEntry { consumer: Bytes::from("alice"), delivery_time: 3000, delivery_count: 4 });
+        group.consumers.insert(Bytes::from("alice"), consumer);
+        stream.groups.insert(Bytes::from("group"), group);
+
+        let expected = format!("{:?}", stream);
+        let (_, loaded) = roundtrip(vec![("stream", Value::Stream(stream), 0)]);
+        assert_eq!(format!("{:?}", loaded[b"stream".as_slice()].value.as_stream().unwrap()), expected);
+    }
+
+    #[test]
+    fn rejects_listpacks_with_an_incomplete_group() {
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.push(TYPE_HASH_LISTPACK);
+        write_string(&mut rdb, b"hash");
+        write_string(&mut rdb, &listpack::encode([b"f".as_slice(), b"1", b"g"]));
+        rdb.push(OPCODE_EOF);
+
+        assert!(load(&rdb).is_err());
+    }
+
+    #[test]
+    fn rejects_lengths_past_the_end_of_the_file() {
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.push(TYPE_STRING);
+        write_length(&mut rdb, u64::MAX);
+
+        assert!(load(&rdb).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::util::parse_i64;
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+
+const HEADER_SIZE: usize = 6;
+const EOF: u8 = 0xFF;
+
+/// Encodes `elements` as a listpack. Elements that are canonical integers
+/// are stored as such, as Redis does.
+pub fn encode<'a>(elements: impl IntoIterator<Item=&'a [u8]>) -> Vec<u8> {
+    let mut out = vec![0; HEADER_SIZE];
+    let mut count = 0;
+
+    for element in elements {
+        let start = out.len();
+        match parse_i64(element) {
+            Some(int) => encode_int(&mut out, int),
+            None => encode_string(&mut out, element),
+        }
+        let len = out.len() - start;
+        encode_backlen(&mut out, len);
+        count += 1;
+    }
+    out.push(EOF);
+
+    let total = out.len() as u32;
+    out[0..4].copy_from_slice(&total.to_le_bytes());
+    out[4..6].copy_from_slice(&(count.min(u16::MAX as usize) as u16).to_le_bytes());
+    out
+}
+
+/// The elements of a listpack, integers rendered in decimal.
+pub fn decode(data: &[u8]) -> Result<Vec<Bytes>> {
+    let mut elements = vec![];
+    let mut pos = HEADER_SIZE;
+
+    loop {
+        let byte = *data.get(pos).ok_or_else(truncated)?;
+        if byte == EOF {
+            return Ok(elements);
+        }
+
+        let (element, len) = match byte {
+            0x00..=0x7F => (int_element(byte as i64), 1),
+            0x80..=0xBF => string_element(data, pos + 1, (byte & 0x3F) as usize)?,
+            0xC0..=0xDF => {
+                let value = (((byte & 0x1F) as i64) << 8) | *data.get(pos + 1).ok_or_else(truncated)? as i64;
+                (int_element(sign_extend(value, 13)), 2)
+            }
+            0xE0..=0xEF => {
+                let len = (((byte & 0x0F) as usize) << 8) | *data.get(pos + 1).ok_or_else(truncated)? as usize;
+                let (element, consumed) = string_element(data, pos + 2, len)?;
+                (element, consumed + 1)
+            }
+            0xF0 => {
+                let len = u32::from_le_bytes(bytes_at(data, pos + 1)?) as usize;
+                let (element, consumed) = string_element(data, pos + 5, len)?;
+                (element, consumed + 4)
+            }
+            0xF1 => (int_element(i16::from_le_bytes(bytes_at(data, pos + 1)?) as i64), 3),
+            0xF2 => {
+                let [a, b, c] = bytes_at(data, pos + 1)?;
+                (int_element(sign_extend(i64::from_le_bytes([a, b, c, 0, 0, 0, 0, 0]), 24)), 4)
+            }
+            0xF3 => (int_element(i32::from_le_bytes(bytes_at(data, pos + 1)?) as i64), 5),
+            0xF4 => (int_element(i64::from_le_bytes(bytes_at(data, pos + 1)?)), 9),
+            _ => return Err(anyhow!("Invalid listpack encoding {:#x}", byte))
+        };
+
+        elements.push(element);
+        pos += len + backlen_size(len);
+    }
+}
+
+fn encode_int(out: &mut Vec<u8>, int: i64) {
+    match int {
+        0..=127 => out.push(int as u8),
+        -4096..=4095 => {
+            let value = (int as u64 & 0x1FFF) as u16;
+            out.extend_from_slice(&[0xC0 | (value >> 8) as u8, value as u8]);
+        }
+        -32768..=32767 => {
+            out.push(0xF1);
+            out.extend_from_slice(&(int as i16).to_le_bytes());
+        }
+        -8388608..=8388607 => {
+            out.push(0xF2);
+            out.extend_from_slice(&(int as i32).to_le_bytes()[..3]);
+        }
+        _ if i32::try_from(int).is_ok() => {
+            out.push(0xF3);
+            out.extend_from_slice(&(int as i32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xF4);
+            out.extend_from_slice(&int.to_le_bytes());
+        }
+    }
+}
+
+fn encode_string(out: &mut Vec<u8>, string: &[u8]) {
+    let len = string.len();
+
+    if len < 64 {
+        out.push(0x80 | len as u8);
+    } else if len < 4096 {
+        out.extend_from_slice(&[0xE0 | (len >> 8) as u8, len as u8]);
+    } else {
+        out.push(0xF0);
+        out.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    out.extend_from_slice(string);
+}
+
+/// Every entry ends with its own length, so a listpack can also be walked
+/// backwards. It takes 7 bits per byte.
+fn encode_backlen(out: &mut Vec<u8>, len: usize) {
+    let size = backlen_size(len);
+
+    for i in (0..size).rev() {
+        let byte = ((len >> (7 * i)) & 0x7F) as u8;
+        out.push(if i == size - 1 { byte } else { byte | 0x80 });
+    }
+}
+
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5
+    }
+}
+
+fn sign_extend(value: i64, bits: u32) -> i64 {
+    if value >= 1 << (bits - 1) {
+        value - (1 << bits)
+    } else {
+        value
+    }
+}
+
+fn int_element(int: i64) -> Bytes {
+    Bytes::from(int.to_string())
+}
+
+/// A string of `len` bytes at `start`, and the length of the entry up to
+/// its backlen counting from the byte before `start`.
+fn string_element(data: &[u8], start: usize, len: usize) -> Result<(Bytes, usize)> {
+    let string = data.get(start..start + len).ok_or_else(truncated)?;
+
+    Ok((Bytes::copy_from_slice(string), len + 1))
+}
+
+fn bytes_at<const N: usize>(data: &[u8], start: usize) -> Result<[u8; N]> {
+    data.get(start..start + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(truncated)
+}
+
+fn truncated() -> anyhow::Error {
+    anyhow!("Truncated listpack")
+}
@@ -1,16 +1,58 @@
-use crate::command_handler::{Command, CommandHandler};
-use crate::commands::{echo_command, get_command, info_command, ping_command, psync_command, replconf_command, set_command};
+use crate::backlog::{Backlog, DEFAULT_BACKLOG_SIZE};
+use crate::command_handler::{Command, CommandHandler, Protocol, WriteData};
+use crate::command_table::{self, CommandSpec, Flag, Handler};
+use crate::commands::{reply, CommandResult};
 use crate::connection::Connection;
-use crate::storage::Storage;
+use crate::error::CommandError;
+use crate::rdb;
+use crate::session::Session;
+use crate::storage::{ExpiryMode, Storage, Subscriber};
 use crate::util::generate_random_string;
-use std::collections::HashSet;
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use std::collections::HashMap;
 use std::io::Error;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::{
     net::TcpListener,
-    sync::Mutex,
+    sync::{Mutex, Notify},
 };
 
+/// How long a replica waits before connecting to its master again, doubling
+/// after every failed attempt
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How much of the stream may wait to be written to a replica before it is
+/// disconnected, like the hard `client-output-buffer-limit` for replicas
+pub const REPLICA_OUTPUT_BUFFER_LIMIT: usize = 256 * 1024 * 1024;
+
+/// How long a replica waits to hear from its master before dropping the link,
+/// until changed with `CONFIG SET repl-timeout`
+const DEFAULT_REPL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a master pings its replicas, so they can tell an idle master
+/// from a dead link (`repl-ping-replica-period`)
+const REPL_PING_REPLICA_PERIOD: Duration = Duration::from_secs(10);
+
+/// How often a replica reports its offset to the master unprompted
+const REPLICA_ACK_PERIOD: Duration = Duration::from_secs(1);
+
+/// How often the active expire cycle runs (Redis's default `hz` of 10)
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a RESP2 connection may still run once it has subscribed to something
+const SUBSCRIBE_CONTEXT_COMMANDS: [&str; 9] = [
+    "subscribe", "psubscribe", "ssubscribe", "unsubscribe", "punsubscribe", "sunsubscribe", "ping", "quit", "reset",
+];
+
+/// The replication ID reported while there is no secondary history
+const NO_REPLICATION_ID: &str = "0000000000000000000000000000000000000000";
+
+/// What runs right away after MULTI instead of being queued
+const TRANSACTION_CONTROL_COMMANDS: [&str; 6] = ["multi", "exec", "discard", "watch", "quit", "reset"];
+
 pub struct ServerStartupConfig {
     pub host: String,
     pub port: String,
@@ -22,30 +64,144 @@ pub struct ServerConfig {
     pub port: String,
     pub replica_of: Option<ServerReplicaOf>,
     pub replication_id: String,
-    pub replication_offset: i32,
-    pub replicas: HashSet<String>,
+    pub replication_offset: i64,
+    /// The ID of the history this one continues, e.g. the former master's on
+    /// a replica, so its own replicas can still PSYNC up to where it forked
+    pub replication_id2: String,
+    /// The first offset that is not part of the `replication_id2` history
+    pub second_replication_offset: i64,
+    /// Created once the first replica syncs, after which the offset advances
+    /// with every write
+    pub backlog: Option<Backlog>,
+    pub backlog_size: usize,
+    /// Silence from the master after which a replica reconnects
+    pub repl_timeout: Duration,
+    /// Connected replicas by client ID
+    pub replicas: HashMap<u64, Replica>,
+    /// Woken whenever a replica acknowledges an offset, for WAIT
+    pub acks: Arc<Notify>,
+    /// On a replica, the state of the link to the master
+    pub master_link: MasterLink,
 }
 
-#[derive(Debug, Clone)]
-pub struct ServerReplicaOf {
-    pub host: String,
-    pub port: String,
+#[derive(Debug, Default)]
+pub struct MasterLink {
+    pub up: bool,
+    /// A snapshot from the master is being loaded
+    pub sync_in_progress: bool,
+    /// When the master was last heard from
+    pub last_io: Option<Instant>,
 }
 
-pub struct RedisConnection {
-    pub host: String,
-    pub port: String,
-    pub connection: Option<Connection>,
+#[derive(Debug, Default)]
+pub struct Replica {
+    /// The `ip:port` the replica listens on, from REPLCONF listening-port
+    pub address: String,
+    /// Where propagated writes are queued, once the replica has sent PSYNC
+    pub stream: Option<Subscriber>,
+    /// How far into the stream the replica has confirmed, from REPLCONF ACK
+    pub acked_offset: i64,
 }
 
-impl Clone for RedisConnection {
-    fn clone(&self) -> Self {
-        RedisConnection {
-            host: self.host.clone(),
-            port: self.port.clone(),
-            connection: None,
+impl ServerConfig {
+    /// Appends writes made on this server to the replication stream. A
+    /// replica only ever passes on its master's stream, see `feed`.
+    pub fn propagate(&mut self, commands: Vec<Command>) {
+        if self.replica_of.is_some() {
+            return;
+        }
+
+        for command in commands {
+            self.feed(command);
         }
     }
+
+    /// Appends `command` to the backlog and to the stream of every replica
+    /// past PSYNC. The replication offset advances by the bytes sent.
+    pub fn feed(&mut self, command: Command) {
+        let backlog = match &mut self.backlog {
+            Some(backlog) => backlog,
+            None => return
+        };
+
+        let bytes = command.clone().serialize();
+        self.replication_offset += bytes.len() as i64;
+        backlog.feed(&bytes);
+
+        let mut overflowed = vec![];
+        for (id, replica) in &self.replicas {
+            if let Some(stream) = &replica.stream {
+                if !stream.send(command.clone()) {
+                    overflowed.push(*id);
+                }
+            }
+        }
+
+        // Its connection closes on the overflow, and the replica can resync
+        for id in overflowed {
+            self.replicas.remove(&id);
+        }
+    }
+
+    /// Starts the backlog if no replica has synced before.
+    pub fn create_backlog(&mut self) {
+        if self.backlog.is_none() {
+            self.backlog = Some(Backlog::new(self.backlog_size, self.replication_offset));
+        }
+    }
+
+    pub fn set_backlog_size(&mut self, size: usize) {
+        self.backlog_size = size;
+        if let Some(backlog) = &mut self.backlog {
+            backlog.resize(size);
+        }
+    }
+
+    /// Moves on to the `replication_id` history, which continues the current
+    /// one from the next byte.
+    pub fn shift_replication_id(&mut self, replication_id: String) {
+        self.replication_id2 = std::mem::replace(&mut self.replication_id, replication_id);
+        self.second_replication_offset = self.replication_offset + 1;
+    }
+
+    /// Records that replica `id` has processed the stream up to `offset`.
+    pub fn ack(&mut self, id: u64, offset: i64) {
+        // No replica can have processed bytes that were never sent, so such an
+        // ACK says nothing about what it has received from this stream
+        if offset > self.replication_offset {
+            return;
+        }
+
+        if let Some(replica) = self.replicas.get_mut(&id) {
+            replica.acked_offset = replica.acked_offset.max(offset);
+        }
+        self.acks.notify_waiters();
+    }
+
+    /// How many replicas have acknowledged the stream up to `offset`.
+    pub fn count_acks(&self, offset: i64) -> usize {
+        self.replicas.values()
+            .filter(|replica| replica.stream.is_some() && replica.acked_offset >= offset)
+            .count()
+    }
+
+    /// What a replica that asks for `offset` of the `replication_id` history
+    /// is missing, if the backlog still holds all of it.
+    pub fn missing_since(&self, replication_id: &str, offset: i64) -> Option<Vec<u8>> {
+        let known = replication_id == self.replication_id
+            || (replication_id == self.replication_id2 && offset <= self.second_replication_offset);
+        if !known {
+            return None;
+        }
+
+        self.backlog.as_ref()?.range_from(offset)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerReplicaOf {
+    pub host: String,
+    pub port: String,
 }
 
 pub struct Server {
@@ -56,13 +212,20 @@ pub struct Server {
 
 impl Server {
     pub async fn new(startup_config: ServerStartupConfig) -> Self {
-        let address = vec![startup_config.host.as_str(), ":", startup_config.port.as_str()].concat();
+        let address = [startup_config.host.as_str(), ":", startup_config.port.as_str()].concat();
 
 
         let config = ServerConfig {
             port: startup_config.port,
             replication_id: generate_random_string(40),
             replication_offset: 0,
+            replication_id2: String::from(NO_REPLICATION_ID),
+            second_replication_offset: -1,
+            backlog: None,
+            backlog_size: DEFAULT_BACKLOG_SIZE,
+            repl_timeout: DEFAULT_REPL_TIMEOUT,
+            acks: Arc::new(Notify::new()),
+            master_link: MasterLink::default(),
             replica_of: match &startup_config.replica_of {
                 Some(val) => {
                     let (host, port) = val.split_once(" ").unwrap();
@@ -74,24 +237,28 @@ impl Server {
                 }
                 None => None
             },
-            replicas: HashSet::new(),
+            replicas: HashMap::new(),
         };
 
-        let storage = Arc::new(Mutex::new(Storage::new()));
+        let mut storage = Storage::new();
+        if config.replica_of.is_some() {
+            storage.expiry_mode = ExpiryMode::Hide;
+        }
+        let storage = Arc::new(Mutex::new(storage));
+        let config = Arc::new(Mutex::new(config));
+        // A replica leaves deleting expired keys to its master
+        if startup_config.replica_of.is_none() {
+            start_active_expire(Arc::clone(&storage), Arc::clone(&config));
+        }
+        start_replica_pings(Arc::clone(&config));
         let mut server = Server {
-            config: Arc::new(Mutex::new(config)),
+            config,
             listener: TcpListener::bind(&address).await.unwrap(),
             storage,
         };
 
         if startup_config.replica_of.is_some() {
-            match server.sync_with_master().await {
-                Ok(connection) => Some(connection),
-                Err(e) => {
-                    eprintln!("Error: {:?}", e);
-                    None
-                }
-            };
+            tokio::spawn(replicate(Arc::clone(&server.storage), Arc::clone(&server.config)));
         }
 
         println!("Server started on: {}", address);
@@ -111,8 +278,8 @@ impl Server {
             match connection {
                 Ok((stream, _)) => {
                     tokio::spawn(async move {
-                        let mut command_handler = CommandHandler::new(stream);
-                        handle_connection(&mut command_handler, storage, config).await
+                        let mut session = Session::new(CommandHandler::new(stream), storage, config);
+                        handle_connection(&mut session).await
                     });
                 }
                 Err(e) => {
@@ -121,132 +288,446 @@ impl Server {
             }
         }
     }
+}
 
-    async fn sync_with_master(&mut self) -> Result<(), String> {
-        println!("Connecting to master...");
-        println!("Handshake...");
-        // let storage = Arc::clone(&self.storage);
-        let config = Arc::clone(&self.config);
-        let port = config.lock().await.port.clone();
-        let replica_of = config.lock().await.replica_of.clone().unwrap();
-
-
-        let master_address = format!("{}:{}", replica_of.clone().host, replica_of.clone().port);
-
-        let mut connection = Connection::new(master_address.clone()).await;
-        let mut handshake_steps = vec![
-            vec![
-                Command::Array(vec![Command::BulkString(String::from("PING"))]),
-                Command::SimpleString(String::from("PONG"))
-            ],
-            vec![
-                Command::Array(vec![
-                    Command::BulkString(String::from("REPLCONF")),
-                    Command::BulkString(String::from("listening-port")),
-                    Command::BulkString(port)
-                ]),
-                Command::SimpleString(String::from("OK"))
-            ],
-            vec![
-                Command::Array(vec![
-                    Command::BulkString(String::from("REPLCONF")),
-                    Command::BulkString(String::from("capa")),
-                    Command::BulkString(String::from("psync2")),
-                ]),
-                Command::SimpleString(String::from("OK"))
-            ],
-            vec![
-                Command::Array(vec![
-                    Command::BulkString(String::from("PSYNC")),
-                    Command::BulkString(String::from("?")),
-                    Command::BulkString(String::from("-1")),
-                ]),
-                Command::SimpleString(String::from("FULLRESYNC"))
-            ]
-        ];
+/// Keeps a replica in sync with its master, connecting again with a growing
+/// delay whenever the link drops or cannot be set up.
+async fn replicate(storage: Arc<Mutex<Storage>>, config: Arc<Mutex<ServerConfig>>) {
+    let mut backoff = RECONNECT_MIN_BACKOFF;
+
+    loop {
+        match sync_with_master(&storage, &config).await {
+            Ok(mut session) => {
+                backoff = RECONNECT_MIN_BACKOFF;
+                follow_master(&mut session).await;
+            }
+            Err(e) => eprintln!("Error syncing with master: {:?}", e)
+        }
 
-        while handshake_steps.len() > 0 {
-            let step = handshake_steps.remove(0);
-            let request = step[0].clone();
-            let expected_response = step[1].clone();
+        config.lock().await.master_link = MasterLink::default();
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+    }
+}
 
-            println!("Handshake step: {:?}", &request);
-            connection.write(request).await;
-            let master_response = connection.read().await;
-            match master_response {
-                Some(cmd) => {
-                    println!("Response from master: {:?}", cmd);
+/// Runs the handshake with the master and brings the dataset up to date,
+/// either from the backlog or from a snapshot. Returns the master link,
+/// ready for `follow_master`.
+async fn sync_with_master(storage: &Arc<Mutex<Storage>>, config: &Arc<Mutex<ServerConfig>>) -> Result<Session> {
+    let (port, master_address, psync, repl_timeout) = {
+        let config = config.lock().await;
+        let replica_of = config.replica_of.clone().unwrap();
 
-                    let received_command = cmd.serialize();
-                    let expected_command = expected_response.serialize();
+        // A replica that has synced before asks to continue where it left off
+        let psync = match config.backlog {
+            Some(_) => (config.replication_id.clone(), (config.replication_offset + 1).to_string()),
+            None => (String::from("?"), String::from("-1"))
+        };
+        (config.port.clone(), format!("{}:{}", replica_of.host, replica_of.port), psync, config.repl_timeout)
+    };
 
-                    if expected_command == Command::SimpleString(String::from("FULLRESYNC")).serialize() {
-                        if !received_command.contains("FULLRESYNC") {
-                            return Err(String::from("Cannot connect to master"));
-                        }
-                    } else if received_command != expected_command {
-                        return Err(String::from("Cannot connect to master"));
-                    }
+    let mut connection = Connection::new(master_address).await?;
+
+    let handshake_steps = vec![
+        vec![
+            Command::Array(vec![Command::bulk("PING")]),
+            Command::SimpleString(String::from("PONG"))
+        ],
+        vec![
+            Command::Array(vec![
+                Command::bulk("REPLCONF"),
+                Command::bulk("listening-port"),
+                Command::bulk(port)
+            ]),
+            Command::SimpleString(String::from("OK"))
+        ],
+        vec![
+            Command::Array(vec![
+                Command::bulk("REPLCONF"),
+                Command::bulk("capa"),
+                Command::bulk("psync2"),
+            ]),
+            Command::SimpleString(String::from("OK"))
+        ],
+    ];
+
+    for step in handshake_steps {
+        connection.write(step[0].clone()).await?;
+
+        match read_from_master(&mut connection, repl_timeout).await? {
+            Some(response) if response == step[1] => (),
+            Some(response) => return Err(anyhow!("Unexpected response from master: {:?}", response)),
+            None => return Err(anyhow!("Master closed the connection during the handshake"))
+        }
+    }
+
+    connection.write(Command::Array(vec![Command::bulk("PSYNC"), Command::bulk(psync.0), Command::bulk(psync.1)])).await?;
+    let response = match read_from_master(&mut connection, repl_timeout).await? {
+        Some(Command::SimpleString(response)) => response,
+        Some(response) => return Err(anyhow!("Unexpected response from master: {:?}", response)),
+        None => return Err(anyhow!("Master closed the connection during the handshake"))
+    };
+
+    if let Some(replication_id) = parse_continue(&response) {
+        let mut config = config.lock().await;
+
+        // The master has moved on to a history of its own, e.g. after a
+        // failover, which carries on from where ours ends
+        if let Some(replication_id) = replication_id.filter(|id| *id != config.replication_id) {
+            config.shift_replication_id(replication_id);
+        }
+    } else {
+        let (replication_id, offset) = parse_full_resync(&response)
+            .ok_or_else(|| anyhow!("Unexpected response from master: {:?}", response))?;
+        config.lock().await.master_link.sync_in_progress = true;
+
+        let rdb = tokio::time::timeout(repl_timeout, connection.command_handler.read_payload()).await
+            .map_err(|_| anyhow!("Timeout receiving the RDB from master"))??;
+        let records = rdb::load(&rdb).map_err(|e| anyhow!("Cannot load the master's RDB: {}", e))?;
+        storage.lock().await.replace_all(records);
+        let mut config = config.lock().await;
+        config.replication_id = replication_id;
+        config.replication_offset = offset;
+        config.replication_id2 = String::from(NO_REPLICATION_ID);
+        config.second_replication_offset = -1;
+        config.backlog = Some(Backlog::new(config.backlog_size, offset));
+    }
+
+    config.lock().await.master_link = MasterLink {
+        up: true,
+        sync_in_progress: false,
+        last_io: Some(Instant::now()),
+    };
+
+    let mut session = Session::new(connection.command_handler, Arc::clone(storage), Arc::clone(config));
+    session.master_link = true;
+    Ok(session)
+}
+
+/// A reply from the master during the handshake, or an error once it has
+/// kept quiet for `repl_timeout`.
+async fn read_from_master(connection: &mut Connection, repl_timeout: Duration) -> Result<Option<Command>> {
+    tokio::time::timeout(repl_timeout, connection.read()).await
+        .map_err(|_| anyhow!("Timeout waiting for the master to reply"))?
+}
+
+/// The replication ID of a `CONTINUE [<replid>]` reply, which older masters
+/// leave out.
+fn parse_continue(reply: &str) -> Option<Option<String>> {
+    match reply.split_once(' ') {
+        Some(("CONTINUE", replication_id)) => Some(Some(replication_id.to_string())),
+        _ if reply == "CONTINUE" => Some(None),
+        _ => None
+    }
+}
+
+/// The replication ID and offset of a `FULLRESYNC <replid> <offset>` reply.
+fn parse_full_resync(reply: &str) -> Option<(String, i64)> {
+    let mut parts = reply.split(' ');
+
+    match (parts.next(), parts.next(), parts.next().and_then(|offset| offset.parse().ok())) {
+        (Some("FULLRESYNC"), Some(replication_id), Some(offset)) => Some((replication_id.to_string(), offset)),
+        _ => None
+    }
+}
+
+/// Applies the writes the master streams after the snapshot, without
+/// replying. The stream is passed on as is to our own backlog and replicas,
+/// which advances the replication offset by exactly the bytes received. The
+/// link is dropped once the master has been silent for `repl-timeout`, which
+/// its periodic PINGs prevent while it is alive.
+async fn follow_master(session: &mut Session) {
+    let mut last_read = tokio::time::Instant::now();
+    let mut acks = tokio::time::interval(REPLICA_ACK_PERIOD);
+
+    loop {
+        let repl_timeout = session.config.lock().await.repl_timeout;
+        let read = tokio::select! {
+            read = session.command_handler.read_request() => read,
+            _ = tokio::time::sleep_until(last_read + repl_timeout) => {
+                eprintln!("Error: no data from master in {:?}", repl_timeout);
+                break;
+            }
+            _ = acks.tick() => {
+                let offset = session.config.lock().await.replication_offset;
+                let ack = Command::Array(vec![Command::bulk("REPLCONF"), Command::bulk("ACK"), Command::bulk(offset.to_string())]);
+                if let Err(e) = session.command_handler.write(WriteData::Command(ack)).await {
+                    eprintln!("Error: {:?}", e);
+                    break;
+                }
+                continue;
+            }
+        };
+        last_read = tokio::time::Instant::now();
+
+        let (command, len) = match read {
+            Ok(Some(request)) => request,
+            Ok(None) => {
+                eprintln!("Error: master closed the connection");
+                break;
+            }
+            Err(e) => {
+                eprintln!("Error: {:?}", e);
+                break;
+            }
+        };
+
+        // Should never happen, as only canonical requests are accepted, but
+        // the offset must not drift from the master's
+        if command.clone().serialize().len() != len {
+            eprintln!("Error: request from master does not serialize to its {} bytes", len);
+            break;
+        }
+
+        match unpack_command(command.clone()) {
+            Ok((name, args)) => {
+                if let Err(e) = execute(session, &name, &args).await {
+                    eprintln!("Error applying {} from master: {}", name, e);
                 }
-                None => break
             }
+            Err(e) => eprintln!("Error: {:?}", e)
         }
 
-        println!("Connected to master at: {}", master_address);
-        Ok(())
+        // Whatever the command queued for the replicas is superseded by the
+        // master's own version of it
+        let mut storage = session.storage.lock().await;
+        storage.take_propagated();
+        let mut config = session.config.lock().await;
+        config.feed(command);
+        config.master_link.last_io = Some(Instant::now());
     }
 }
 
+/// Pings the replicas through the replication stream while there are any, so
+/// their `repl-timeout` only runs out when the link is actually gone.
+fn start_replica_pings(config: Arc<Mutex<ServerConfig>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REPL_PING_REPLICA_PERIOD);
+
+        loop {
+            interval.tick().await;
+            let mut config = config.lock().await;
+            if config.replicas.values().any(|replica| replica.stream.is_some()) {
+                config.propagate(vec![Command::Array(vec![Command::bulk("PING")])]);
+            }
+        }
+    });
+}
+
+/// Runs the active expire cycle in the background so keys that are never read
+/// again still get evicted, and the replicas are told to delete them too.
+fn start_active_expire(storage: Arc<Mutex<Storage>>, config: Arc<Mutex<ServerConfig>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            let mut storage = storage.lock().await;
+            storage.active_expire_cycle();
+            propagate(&mut storage, &config).await;
+        }
+    });
+}
+
 fn handle_error(e: Error) {
     println!("error: {}", e);
 }
 
-pub async fn handle_connection(command_handler: &mut CommandHandler, storage: Arc<Mutex<Storage>>, config: Arc<Mutex<ServerConfig>>) {
+pub async fn handle_connection(session: &mut Session) {
     println!("Handling new connection...");
 
     loop {
-        let command_read = command_handler.read().await.unwrap_or_else(|e| {
-            eprintln!("Error: {:?}", e);
-            None
-        });
+        let read = tokio::select! {
+            read = session.command_handler.read_request() => read,
+            Some(message) = session.messages.recv() => {
+                session.output.dequeue(message.clone().serialize().len());
+
+                let written = tokio::select! {
+                    written = session.command_handler.write(WriteData::Command(message)) => written.is_ok(),
+                    _ = session.output.overflowed() => false,
+                };
+                if !written {
+                    break;
+                }
+                continue;
+            }
+            // Like Redis, drop a subscriber or replica that can't keep up
+            // rather than buffer without end
+            _ = session.output.overflowed() => {
+                eprintln!("Closing connection {}: output buffer over its limit", session.command_handler.id);
+                break;
+            }
+        };
+
+        let command_read = match read {
+            Ok(command_read) => command_read,
+            Err(e) => {
+                // The rest of the stream cannot be made sense of, so like
+                // Redis say why and close the connection
+                let _ = session.command_handler.write(WriteData::Command(Command::error(format!("ERR Protocol error: {}", e)))).await;
+                break;
+            }
+        };
 
         match command_read {
-            Some(cmd) => {
+            Some((cmd, _)) => {
                 println!("Command {:?}", cmd);
-                let (command, args) = unpack_command(cmd.clone()).unwrap();
-
-                match command.to_lowercase().as_str() {
-                    "ping" => ping_command(command_handler).await,
-                    "echo" => echo_command(command_handler, &args).await,
-                    "set" => set_command(command_handler, &args, &storage).await,
-                    "get" => get_command(command_handler, &unpack_bulk_str(args[0].clone()).unwrap(), &storage).await,
-                    "info" => info_command(command_handler, &config, &args).await,
-                    "replconf" => replconf_command(command_handler, &config, &args).await,
-                    "psync" => psync_command(command_handler).await,
-                    _ => ()
+                let (command, args) = match unpack_command(cmd.clone()) {
+                    Ok(unpacked) => unpacked,
+                    Err(e) => {
+                        let _ = session.command_handler.write(WriteData::Command(Command::error(format!("ERR Protocol error: {}", e)))).await;
+                        continue;
+                    }
                 };
 
-                if vec!["get", "set"].contains(&command.to_lowercase().as_str()) {
-                    let command = cmd.clone();
-                    for replica in config.lock().await.replicas.iter() {
-                        eprintln!("Replicating command to: {}", replica.to_string());
-                        let mut c = Connection::new(replica.to_string()).await;
-                        c.write(command.clone()).await;
-                        c.read().await;
+                match execute(session, &command, &args).await {
+                    Ok(()) if session.closing => break,
+                    Ok(()) => (),
+                    Err(CommandError::Connection(e)) => {
+                        eprintln!("Error: {:?}", e);
+                        break;
+                    }
+                    Err(e) => {
+                        if session.command_handler.write(WriteData::Command(Command::error(e.to_string()))).await.is_err() {
+                            break;
+                        }
                     }
                 }
             }
-            None => ()
+            None => break
         }
     }
+
+    session.unsubscribe_all().await;
+    session.unwatch_all().await;
+    session.config.lock().await.replicas.remove(&session.command_handler.id);
 }
 
+/// Looks the command up in the command table, checks its arity and runs it,
+/// or queues it while a transaction is open.
+pub async fn execute(session: &mut Session, command_name: &str, args: &[Command]) -> CommandResult {
+    let mut checked = check(session, command_name, args);
+    if let Ok(spec) = checked {
+        // Only the master may change a replica's data
+        if spec.has_flag(Flag::Write) && !session.master_link && session.config.lock().await.replica_of.is_some() {
+            checked = Err(CommandError::Custom(String::from("READONLY You can't write against a read only replica.")));
+        }
+    }
+
+    let spec = match checked {
+        Ok(spec) => spec,
+        Err(e) => {
+            // A command rejected while queueing makes EXEC fail as a whole
+            if let Some(transaction) = &mut session.transaction {
+                transaction.aborted = true;
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(transaction) = &mut session.transaction {
+        if !TRANSACTION_CONTROL_COMMANDS.contains(&spec.name) {
+            transaction.commands.push((command_name.to_string(), args.to_vec()));
+            return reply(session, Command::SimpleString("QUEUED".to_string())).await;
+        }
+    }
+
+    // EXEC sends the writes of the whole transaction itself, and a replica
+    // forwards the stream of its master instead
+    let config = Arc::clone(&session.config);
+    let forward = session.exec.is_none() && !session.master_link;
+    let master_link = session.master_link;
+
+    match spec.handler {
+        Handler::Keyspace(handler) => {
+            let command = {
+                let mut storage = session.lock_storage().await;
+                let queued = storage.propagated_by_commands();
+                let dirty = storage.dirty();
+                let command = if master_link {
+                    storage.apply_master_stream(|storage| handler(storage, args))
+                } else {
+                    handler(&mut storage, args)
+                };
+
+                // Writes that changed nothing are not sent at all, like DEL of
+                // a missing key
+                let changed = storage.dirty() != dirty;
+                if command.is_ok() && spec.has_flag(Flag::Write) && changed && storage.propagated_by_commands() == queued {
+                    let mut propagated = vec![Command::bulk(command_name.to_string())];
+                    propagated.extend_from_slice(args);
+                    storage.propagate(propagated);
+                }
+                storage.serve_blocked();
+                if forward {
+                    propagate(&mut storage, &config).await;
+                }
+
+                command?
+            };
+            reply(session, command).await
+        }
+        Handler::Session(handler) => {
+            let result = handler(session, args).await;
+            if spec.has_flag(Flag::Write) {
+                let mut storage = session.lock_storage().await;
+                storage.serve_blocked();
+                if forward {
+                    propagate(&mut storage, &config).await;
+                }
+            }
+            result
+        }
+    }
+}
+
+/// Hands the writes queued in `storage` to the replicas. The storage lock is
+/// held throughout, so the stream follows the order the writes were made in.
+pub async fn propagate(storage: &mut Storage, config: &Mutex<ServerConfig>) {
+    let commands = storage.take_propagated();
+
+    if !commands.is_empty() {
+        config.lock().await.propagate(commands);
+    }
+}
+
+/// Finds the spec of `command` and checks it may run with `args` on this
+/// connection.
+fn check(session: &Session, command: &str, args: &[Command]) -> Result<&'static CommandSpec, CommandError> {
+    let spec = match command_table::lookup(command) {
+        Some(spec) => spec,
+        None => return Err(CommandError::UnknownCommand(command.to_string(), describe_args(args)))
+    };
+
+    if !spec.arity_matches(args.len() + 1) {
+        return Err(CommandError::wrong_number_of_arguments(spec.name));
+    }
+
+    // RESP3 can interleave pushes with replies, so only RESP2 is restricted
+    if session.in_subscribe_mode() && session.command_handler.protocol == Protocol::Resp2 && !SUBSCRIBE_CONTEXT_COMMANDS.contains(&spec.name) {
+        return Err(CommandError::Other(format!(
+            "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context", spec.name
+        )));
+    }
+
+    Ok(spec)
+}
+
+/// Renders the arguments of an unknown command the way Redis echoes them back.
+fn describe_args(args: &[Command]) -> String {
+    args.iter()
+        .map(|arg| match unpack_bulk_bytes(arg.clone()) {
+            Ok(arg) => format!("'{}' ", String::from_utf8_lossy(&arg)),
+            Err(_) => String::new()
+        })
+        .collect()
+}
 
 pub fn unpack_command(command: Command) -> Result<(String, Vec<Command>), anyhow::Error> {
     match command {
         Command::Array(arr) => {
             Ok((
-                unpack_bulk_str(arr.first().unwrap().clone())?,
+                unpack_bulk_str(arr.first().ok_or_else(|| anyhow::anyhow!("Empty command"))?.clone())?,
                 arr.into_iter().skip(1).collect(),
             ))
         }
@@ -255,6 +736,10 @@ pub fn unpack_command(command: Command) -> Result<(String, Vec<Command>), anyhow
 }
 
 pub fn unpack_bulk_str(command: Command) -> Result<String, anyhow::Error> {
+    Ok(String::from_utf8(unpack_bulk_bytes(command)?.to_vec())?)
+}
+
+pub fn unpack_bulk_bytes(command: Command) -> Result<Bytes, anyhow::Error> {
     match command {
         Command::BulkString(bulk_string) => Ok(bulk_string),
         _ => Err(anyhow::anyhow!("Expected command to be a bulk string"))
@@ -262,3 +747,489 @@ pub fn unpack_bulk_str(command: Command) -> Result<String, anyhow::Error> {
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::PUBSUB_OUTPUT_BUFFER_LIMIT;
+    use tokio::net::TcpStream;
+
+    fn start(port: u16, replica_of: Option<u16>) {
+        tokio::spawn(Server::new(ServerStartupConfig {
+            host: String::from("127.0.0.1"),
+            port: port.to_string(),
+            replica_of: replica_of.map(|port| format!("127.0.0.1 {}", port)),
+        }));
+    }
+
+    /// Forwards connections from `listener` to `port`, one at a time, until
+    /// aborted, which drops the connection being forwarded.
+    async fn proxy(listener: Arc<TcpListener>, port: u16) {
+        loop {
+            let (mut inbound, _) = listener.accept().await.unwrap();
+            let mut outbound = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+        }
+    }
+
+    async fn connect(port: u16) -> CommandHandler {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)).await {
+                return CommandHandler::new(stream);
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        panic!("Server on port {} did not start", port);
+    }
+
+    async fn call(client: &mut CommandHandler, args: &[&str]) -> Command {
+        let request = Command::Array(args.iter().map(|arg| Command::bulk(arg.to_string())).collect());
+        client.write(WriteData::Command(request)).await.unwrap();
+        client.read().await.unwrap().unwrap()
+    }
+
+    /// Sends a command that blocks, giving the server time to park it
+    /// before anything else is sent. Its reply is read with `receive`.
+    async fn send_blocking(client: &mut CommandHandler, args: &[&str]) {
+        let request = Command::Array(args.iter().map(|arg| Command::bulk(arg.to_string())).collect());
+        client.write(WriteData::Command(request)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    async fn receive(client: &mut CommandHandler) -> Command {
+        tokio::time::timeout(Duration::from_secs(1), client.read()).await.unwrap().unwrap().unwrap()
+    }
+
+    fn popped(key: &str, element: &str) -> Command {
+        Command::Array(vec![Command::bulk(key.to_string()), Command::bulk(element.to_string())])
+    }
+
+    async fn wait_for(client: &mut CommandHandler, args: &[&str], expected: Command) {
+        for _ in 0..250 {
+            if call(client, args).await == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        panic!("{:?} never replied {:?}", args, expected);
+    }
+
+    /// Syncs like a replica would, returning the link the replication stream
+    /// arrives on.
+    async fn fake_replica(port: u16) -> CommandHandler {
+        let mut link = connect(port).await;
+        call(&mut link, &["REPLCONF", "listening-port", "0"]).await;
+        call(&mut link, &["PSYNC", "?", "-1"]).await;
+        link.read_payload().await.unwrap();
+        link
+    }
+
+    /// The next write on the replication stream, as the words of the command.
+    async fn next_propagated(link: &mut CommandHandler) -> Vec<String> {
+        let command = tokio::time::timeout(Duration::from_secs(1), link.read()).await.unwrap().unwrap().unwrap();
+        let (name, args) = unpack_command(command).unwrap();
+        std::iter::once(name).chain(args.into_iter().map(|arg| unpack_bulk_str(arg).unwrap())).collect()
+    }
+
+    async fn replication_info(client: &mut CommandHandler, field: &str) -> String {
+        let info = match call(client, &["INFO", "replication"]).await {
+            Command::BulkString(info) => String::from_utf8(info.to_vec()).unwrap(),
+            other => panic!("Unexpected INFO reply {:?}", other)
+        };
+
+        info.lines()
+            .find_map(|line| line.strip_prefix(&format!("{}:", field)))
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn replica_continues_at_the_masters_offset_after_reconnecting() {
+        start(16390, None);
+        let mut master = connect(16390).await;
+
+        let listener = Arc::new(TcpListener::bind("127.0.0.1:16391").await.unwrap());
+        let link = tokio::spawn(proxy(Arc::clone(&listener), 16390));
+        start(16392, Some(16391));
+        let mut replica = connect(16392).await;
+
+        call(&mut master, &["SET", "before", "1"]).await;
+        wait_for(&mut replica, &["GET", "before"], Command::bulk("1")).await;
+
+        // Writes on the replica itself must not move its offset ahead
+        let rejected = call(&mut replica, &["SET", "local", "1"]).await;
+        assert_eq!(rejected, Command::error("READONLY You can't write against a read only replica."));
+
+        link.abort();
+        call(&mut master, &["SET", "during", "1"]).await;
+        call(&mut master, &["RPUSH", "list", "a", "b"]).await;
+        tokio::spawn(proxy(listener, 16390));
+
+        wait_for(&mut replica, &["GET", "during"], Command::bulk("1")).await;
+        wait_for(&mut replica, &["LLEN", "list"], Command::Integer(2)).await;
+
+        let offset = replication_info(&mut master, "master_repl_offset").await;
+        assert_eq!(replication_info(&mut replica, "master_repl_offset").await, offset);
+        assert_eq!(replication_info(&mut replica, "master_replid").await, replication_info(&mut master, "master_replid").await);
+
+        // Caught up from the backlog, without a new snapshot restarting its own
+        assert_eq!(replication_info(&mut replica, "repl_backlog_first_byte_offset").await, "1");
+    }
+
+    #[tokio::test]
+    async fn wait_counts_replicas_that_received_the_write() {
+        start(16393, None);
+        let mut master = connect(16393).await;
+        start(16394, Some(16393));
+        let mut replica = connect(16394).await;
+
+        call(&mut master, &["SET", "key", "1"]).await;
+        assert_eq!(call(&mut master, &["WAIT", "1", "5000"]).await, Command::Integer(1));
+        assert_eq!(replication_info(&mut replica, "master_repl_offset").await, replication_info(&mut master, "master_repl_offset").await);
+
+        // A replica claiming bytes that were never sent is not caught up
+        let mut liar = connect(16393).await;
+        call(&mut liar, &["REPLCONF", "listening-port", "16395"]).await;
+        call(&mut liar, &["PSYNC", "?", "-1"]).await;
+        let request = Command::Array(vec![Command::bulk("REPLCONF"), Command::bulk("ACK"), Command::bulk("1000000")]);
+        liar.write(WriteData::Command(request)).await.unwrap();
+        assert_eq!(call(&mut master, &["WAIT", "2", "200"]).await, Command::Integer(1));
+
+        assert_eq!(call(&mut replica, &["WAIT", "0", "0"]).await, Command::error("ERR WAIT cannot be used with replica instances."));
+    }
+
+    #[tokio::test]
+    async fn replica_reports_its_offset_and_drops_a_silent_master() {
+        let listener = TcpListener::bind("127.0.0.1:16395").await.unwrap();
+        start(16396, Some(16395));
+
+        // A master that completes the handshake and then never sends a thing
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut link = CommandHandler::new(stream);
+        loop {
+            let name = match link.read_request().await.unwrap().unwrap().0 {
+                Command::Array(args) => args[0].clone(),
+                other => panic!("Unexpected request {:?}", other)
+            };
+            let reply = match name {
+                name if name == Command::bulk("PSYNC") => break,
+                name if name == Command::bulk("PING") => "PONG",
+                _ => "OK"
+            };
+            link.write(WriteData::Command(Command::SimpleString(reply.to_string()))).await.unwrap();
+        }
+        let rdb = rdb::dump(&Storage::new());
+        link.write(WriteData::Command(Command::SimpleString(format!("FULLRESYNC {} 0", "a".repeat(40))))).await.unwrap();
+        link.write(WriteData::String(format!("${}\r\n", rdb.len()))).await.unwrap();
+        link.write(WriteData::Raw(rdb)).await.unwrap();
+
+        let ack = tokio::time::timeout(Duration::from_secs(3), link.read_request()).await.unwrap().unwrap().unwrap().0;
+        assert_eq!(ack, Command::Array(vec![Command::bulk("REPLCONF"), Command::bulk("ACK"), Command::bulk("0")]));
+
+        let mut replica = connect(16396).await;
+        assert_eq!(call(&mut replica, &["CONFIG", "SET", "repl-timeout", "1"]).await, Command::SimpleString(String::from("OK")));
+        tokio::time::timeout(Duration::from_secs(5), listener.accept()).await.unwrap().unwrap();
+        assert_eq!(replication_info(&mut replica, "master_link_status").await, "down");
+    }
+
+    #[tokio::test]
+    async fn reset_leaves_subscribe_mode_and_quit_closes_the_connection() {
+        start(16397, None);
+        let mut client = connect(16397).await;
+
+        call(&mut client, &["SUBSCRIBE", "channel"]).await;
+        assert_eq!(call(&mut client, &["RESET"]).await, Command::SimpleString(String::from("RESET")));
+        assert_eq!(call(&mut client, &["GET", "key"]).await, Command::NullBulkString);
+
+        call(&mut client, &["WATCH", "key"]).await;
+        call(&mut client, &["MULTI"]).await;
+        call(&mut client, &["SET", "key", "1"]).await;
+        assert_eq!(call(&mut client, &["RESET"]).await, Command::SimpleString(String::from("RESET")));
+        assert_eq!(call(&mut client, &["EXEC"]).await, Command::error("ERR EXEC without MULTI"));
+        assert_eq!(call(&mut client, &["GET", "key"]).await, Command::NullBulkString);
+
+        assert_eq!(call(&mut client, &["QUIT"]).await, Command::SimpleString(String::from("OK")));
+        assert!(client.read().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn writes_that_change_nothing_are_not_propagated() {
+        start(16398, None);
+        let mut client = connect(16398).await;
+        let mut link = fake_replica(16398).await;
+
+        call(&mut client, &["DEL", "missing"]).await;
+        call(&mut client, &["SADD", "set", "a"]).await;
+        call(&mut client, &["SREM", "set", "b"]).await;
+        call(&mut client, &["HDEL", "missing", "field"]).await;
+        call(&mut client, &["LPOP", "missing"]).await;
+        call(&mut client, &["XADD", "missing", "NOMKSTREAM", "*", "field", "value"]).await;
+        call(&mut client, &["SET", "key", "1"]).await;
+
+        assert_eq!(next_propagated(&mut link).await, ["SADD", "set", "a"]);
+        assert_eq!(next_propagated(&mut link).await, ["SET", "key", "1"]);
+    }
+
+    #[tokio::test]
+    async fn conditional_writes_propagate_their_outcome() {
+        start(16399, None);
+        let mut client = connect(16399).await;
+        let mut link = fake_replica(16399).await;
+
+        call(&mut client, &["SET", "key", "1", "NX", "GET", "PXAT", "99999999999999"]).await;
+        call(&mut client, &["SET", "key", "2", "NX", "PX", "100"]).await;
+        call(&mut client, &["EXPIRE", "key", "100", "NX"]).await;
+        call(&mut client, &["PEXPIREAT", "key", "88888888888888", "LT"]).await;
+        call(&mut client, &["PEXPIRE", "key", "-1"]).await;
+        call(&mut client, &["HSET", "hash", "a", "1", "b", "2"]).await;
+        call(&mut client, &["HPEXPIREAT", "hash", "77777777777777", "NX", "FIELDS", "2", "a", "missing"]).await;
+        call(&mut client, &["HPEXPIREAT", "hash", "66666666666666", "GT", "FIELDS", "2", "a", "b"]).await;
+        call(&mut client, &["HPEXPIRE", "hash", "0", "FIELDS", "1", "b"]).await;
+
+        assert_eq!(next_propagated(&mut link).await, ["SET", "key", "1", "PXAT", "99999999999999"]);
+        assert_eq!(next_propagated(&mut link).await, ["PEXPIREAT", "key", "88888888888888"]);
+        assert_eq!(next_propagated(&mut link).await, ["DEL", "key"]);
+        assert_eq!(next_propagated(&mut link).await, ["HSET", "hash", "a", "1", "b", "2"]);
+        assert_eq!(next_propagated(&mut link).await, ["HPEXPIREAT", "hash", "77777777777777", "FIELDS", "1", "a"]);
+        assert_eq!(next_propagated(&mut link).await, ["HDEL", "hash", "b"]);
+    }
+
+    #[tokio::test]
+    async fn xreadgroup_delivers_nothing_when_one_group_is_missing() {
+        start(16400, None);
+        let mut client = connect(16400).await;
+
+        call(&mut client, &["XADD", "first", "1-1", "field", "value"]).await;
+        call(&mut client, &["XGROUP", "CREATE", "first", "group", "0"]).await;
+        call(&mut client, &["XADD", "second", "1-1", "field", "value"]).await;
+
+        let reply = call(&mut client, &["XREADGROUP", "GROUP", "group", "consumer", "STREAMS", "first", "second", ">", ">"]).await;
+        assert_eq!(reply, Command::error("NOGROUP No such key 'second' or consumer group 'group' in XREADGROUP with GROUP option"));
+        let pending = call(&mut client, &["XPENDING", "first", "group"]).await;
+        assert_eq!(pending, Command::Array(vec![Command::Integer(0), Command::NullBulkString, Command::NullBulkString, Command::NullArray]));
+
+        let reply = call(&mut client, &["XREADGROUP", "GROUP", "group", "consumer", "STREAMS", "first", ">"]).await;
+        assert_ne!(reply, Command::NullArray);
+    }
+
+    #[tokio::test]
+    async fn claims_propagate_as_forced_xclaims() {
+        start(16401, None);
+        let mut client = connect(16401).await;
+        let mut link = fake_replica(16401).await;
+
+        call(&mut client, &["XADD", "stream", "1-1", "field", "value"]).await;
+        call(&mut client, &["XADD", "stream", "1-2", "field", "value"]).await;
+        call(&mut client, &["XGROUP", "CREATE", "stream", "group", "0"]).await;
+        call(&mut client, &["XREADGROUP", "GROUP", "group", "alice", "STREAMS", "stream", ">"]).await;
+        call(&mut client, &["XDEL", "stream", "1-2"]).await;
+        call(&mut client, &["XAUTOCLAIM", "stream", "group", "bob", "0", "0-0"]).await;
+        call(&mut client, &["XCLAIM", "stream", "group", "alice", "0", "1-1", "RETRYCOUNT", "7"]).await;
+        call(&mut client, &["XCLAIM", "stream", "group", "alice", "0", "9-9", "LASTID", "5-5"]).await;
+
+        for _ in 0..5 {
+            next_propagated(&mut link).await;
+        }
+        let claims = [
+            next_propagated(&mut link).await,
+            next_propagated(&mut link).await,
+            next_propagated(&mut link).await,
+        ];
+        let expected = [
+            ("1-1", "bob", "2", "1-2"),
+            ("1-2", "bob", "1", "1-2"),
+            ("1-1", "alice", "7", "1-2"),
+        ];
+        for (claim, (id, consumer, retry_count, last_id)) in claims.iter().zip(expected) {
+            assert_eq!(claim[..7], ["XCLAIM", "stream", "group", consumer, "0", id, "TIME"]);
+            assert_eq!(claim[8..], ["RETRYCOUNT", retry_count, "FORCE", "JUSTID", "LASTID", last_id]);
+        }
+        assert_eq!(next_propagated(&mut link).await, ["XGROUP", "SETID", "stream", "group", "5-5", "ENTRIESREAD", "2"]);
+    }
+
+    #[tokio::test]
+    async fn subscribers_that_fall_behind_are_disconnected() {
+        start(16402, None);
+        let mut subscriber = connect(16402).await;
+        let mut publisher = connect(16402).await;
+        call(&mut subscriber, &["SUBSCRIBE", "channel"]).await;
+
+        // The subscriber never reads, so past what the socket takes the
+        // messages pile up on the server
+        let message = "x".repeat(1024 * 1024);
+        for _ in 0..PUBSUB_OUTPUT_BUFFER_LIMIT / message.len() + 16 {
+            call(&mut publisher, &["PUBLISH", "channel", &message]).await;
+        }
+
+        let unsubscribed = Command::Array(vec![Command::bulk("channel"), Command::Integer(0)]);
+        wait_for(&mut publisher, &["PUBSUB", "NUMSUB", "channel"], unsubscribed).await;
+    }
+
+    #[tokio::test]
+    async fn exec_aborts_after_a_queueing_error_and_never_blocks() {
+        start(16403, None);
+        let mut client = connect(16403).await;
+
+        call(&mut client, &["MULTI"]).await;
+        assert_eq!(call(&mut client, &["SET", "key", "value"]).await, Command::SimpleString(String::from("QUEUED")));
+        assert!(matches!(call(&mut client, &["NOSUCHCOMMAND"]).await, Command::Error(_)));
+        assert!(matches!(call(&mut client, &["GET"]).await, Command::Error(_)));
+        assert_eq!(call(&mut client, &["EXEC"]).await, Command::error("EXECABORT Transaction discarded because of previous errors."));
+        assert_eq!(call(&mut client, &["EXISTS", "key"]).await, Command::Integer(0));
+
+        // Blocking inside a transaction would hold every other client up
+        call(&mut client, &["MULTI"]).await;
+        call(&mut client, &["BLPOP", "list", "0"]).await;
+        call(&mut client, &["BLMOVE", "list", "other", "LEFT", "RIGHT", "0"]).await;
+        call(&mut client, &["XREAD", "BLOCK", "0", "STREAMS", "stream", "$"]).await;
+        let reply = tokio::time::timeout(Duration::from_secs(1), call(&mut client, &["EXEC"])).await.unwrap();
+        assert_eq!(reply, Command::Array(vec![Command::NullArray, Command::NullBulkString, Command::NullArray]));
+    }
+
+    #[tokio::test]
+    async fn exec_fails_once_a_watched_key_changes() {
+        start(16404, None);
+        let mut client = connect(16404).await;
+        let mut other = connect(16404).await;
+        async fn transaction(client: &mut CommandHandler) -> Command {
+            call(client, &["MULTI"]).await;
+            call(client, &["INCR", "counter"]).await;
+            call(client, &["EXEC"]).await
+        }
+
+        call(&mut client, &["SET", "key", "1"]).await;
+        call(&mut client, &["WATCH", "key"]).await;
+        call(&mut other, &["SET", "key", "2"]).await;
+        assert_eq!(transaction(&mut client).await, Command::NullArray);
+
+        call(&mut client, &["SET", "key", "1", "PX", "20"]).await;
+        call(&mut client, &["WATCH", "key"]).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(transaction(&mut client).await, Command::NullArray);
+
+        call(&mut client, &["SET", "key", "1"]).await;
+        call(&mut client, &["WATCH", "key"]).await;
+        call(&mut other, &["FLUSHALL"]).await;
+        assert_eq!(transaction(&mut client).await, Command::NullArray);
+
+        call(&mut client, &["WATCH", "key"]).await;
+        call(&mut other, &["SET", "unrelated", "1"]).await;
+        assert_eq!(transaction(&mut client).await, Command::Array(vec![Command::Integer(1)]));
+    }
+
+    #[tokio::test]
+    async fn transactions_are_propagated_as_one_multi_exec() {
+        start(16405, None);
+        let mut client = connect(16405).await;
+        let mut link = fake_replica(16405).await;
+
+        call(&mut client, &["MULTI"]).await;
+        call(&mut client, &["SET", "key", "value"]).await;
+        call(&mut client, &["GET", "key"]).await;
+        call(&mut client, &["RPUSH", "list", "a"]).await;
+        call(&mut client, &["EXEC"]).await;
+
+        // Nothing to propagate from a transaction that changed nothing
+        call(&mut client, &["MULTI"]).await;
+        call(&mut client, &["GET", "key"]).await;
+        call(&mut client, &["EXEC"]).await;
+        call(&mut client, &["DEL", "key"]).await;
+
+        assert_eq!(next_propagated(&mut link).await, ["MULTI"]);
+        assert_eq!(next_propagated(&mut link).await, ["SET", "key", "value"]);
+        assert_eq!(next_propagated(&mut link).await, ["RPUSH", "list", "a"]);
+        assert_eq!(next_propagated(&mut link).await, ["EXEC"]);
+        assert_eq!(next_propagated(&mut link).await, ["DEL", "key"]);
+    }
+
+    #[tokio::test]
+    async fn blocked_clients_are_served_in_the_order_they_blocked() {
+        start(16406, None);
+        let mut pusher = connect(16406).await;
+        let mut clients = vec![];
+        for _ in 0..3 {
+            let mut client = connect(16406).await;
+            send_blocking(&mut client, &["BLPOP", "list", "0"]).await;
+            clients.push(client);
+        }
+
+        call(&mut pusher, &["RPUSH", "list", "a", "b", "c"]).await;
+        for (client, element) in clients.iter_mut().zip(["a", "b", "c"]) {
+            assert_eq!(receive(client).await, popped("list", element));
+        }
+        assert_eq!(call(&mut pusher, &["EXISTS", "list"]).await, Command::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn a_single_push_serves_a_single_blocked_client() {
+        start(16407, None);
+        let mut pusher = connect(16407).await;
+        let mut first = connect(16407).await;
+        let mut second = connect(16407).await;
+        send_blocking(&mut first, &["BLPOP", "list", "0"]).await;
+        send_blocking(&mut second, &["BRPOP", "list", "0"]).await;
+
+        call(&mut pusher, &["RPUSH", "list", "a"]).await;
+        assert_eq!(receive(&mut first).await, popped("list", "a"));
+        assert!(tokio::time::timeout(Duration::from_millis(100), second.read()).await.is_err());
+
+        call(&mut pusher, &["RPUSH", "list", "b"]).await;
+        assert_eq!(receive(&mut second).await, popped("list", "b"));
+    }
+
+    #[tokio::test]
+    async fn blmove_chains_run_until_nothing_else_can_be_served() {
+        start(16408, None);
+        let mut pusher = connect(16408).await;
+        let mut first = connect(16408).await;
+        let mut second = connect(16408).await;
+        let mut last = connect(16408).await;
+        send_blocking(&mut first, &["BLMOVE", "a", "b", "LEFT", "RIGHT", "0"]).await;
+        send_blocking(&mut second, &["BRPOPLPUSH", "b", "c", "0"]).await;
+        send_blocking(&mut last, &["BLPOP", "c", "0"]).await;
+
+        call(&mut pusher, &["RPUSH", "a", "element"]).await;
+        assert_eq!(receive(&mut first).await, Command::bulk("element"));
+        assert_eq!(receive(&mut second).await, Command::bulk("element"));
+        assert_eq!(receive(&mut last).await, popped("c", "element"));
+        assert_eq!(call(&mut pusher, &["EXISTS", "a", "b", "c"]).await, Command::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn served_blocking_commands_propagate_as_their_plain_form() {
+        start(16409, None);
+        let mut pusher = connect(16409).await;
+        let mut client = connect(16409).await;
+        let mut link = fake_replica(16409).await;
+
+        send_blocking(&mut client, &["BLPOP", "missing", "list", "0"]).await;
+        call(&mut pusher, &["RPUSH", "list", "a", "b"]).await;
+        receive(&mut client).await;
+        send_blocking(&mut client, &["BLMOVE", "list", "other", "RIGHT", "LEFT", "0"]).await;
+        receive(&mut client).await;
+
+        assert_eq!(next_propagated(&mut link).await, ["RPUSH", "list", "a", "b"]);
+        assert_eq!(next_propagated(&mut link).await, ["LPOP", "list"]);
+        assert_eq!(next_propagated(&mut link).await, ["LMOVE", "list", "other", "RIGHT", "LEFT"]);
+    }
+
+    #[tokio::test]
+    async fn timed_out_and_disconnected_clients_stop_waiting() {
+        start(16410, None);
+        let mut pusher = connect(16410).await;
+        let mut timed_out = connect(16410).await;
+        let mut waiting = connect(16410).await;
+
+        assert_eq!(call(&mut timed_out, &["BLPOP", "list", "0.05"]).await, Command::NullArray);
+        let mut gone = connect(16410).await;
+        send_blocking(&mut gone, &["BLPOP", "list", "0"]).await;
+        drop(gone);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Neither is served, so the element is left for whoever asks next
+        call(&mut pusher, &["RPUSH", "list", "a"]).await;
+        assert_eq!(call(&mut pusher, &["LLEN", "list"]).await, Command::Integer(1));
+        assert_eq!(call(&mut waiting, &["BLPOP", "list", "0"]).await, popped("list", "a"));
+    }
+}
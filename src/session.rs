@@ -0,0 +1,145 @@
+use crate::command_handler::{Command, CommandHandler};
+use crate::server::ServerConfig;
+use crate::storage::{OutputBuffer, Storage, Subscriber, SubscriptionKind, PUBSUB_OUTPUT_BUFFER_LIMIT};
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex, MutexGuard, OwnedMutexGuard};
+
+/// Per-connection state handed to every command handler.
+pub struct Session {
+    pub command_handler: CommandHandler,
+    pub storage: Arc<Mutex<Storage>>,
+    pub config: Arc<Mutex<ServerConfig>>,
+    /// Channels and patterns this connection is subscribed to
+    pub subscriptions: HashSet<(SubscriptionKind, Bytes)>,
+    /// Handed to the pub/sub registry so publishers can reach this connection
+    pub subscriber: Subscriber,
+    /// Messages published to this connection, written out between commands
+    pub messages: mpsc::UnboundedReceiver<Command>,
+    /// How much of `messages` is still to be written out
+    pub output: Arc<OutputBuffer>,
+    /// Commands queued since MULTI, or `None` outside a transaction
+    pub transaction: Option<Transaction>,
+    /// Keys WATCHed by this connection, with their version at the time
+    pub watched: HashMap<Bytes, u64>,
+    /// Set while EXEC runs the queued commands
+    pub exec: Option<Exec>,
+    /// Set on a replica's link to its master, whose commands get no replies
+    pub master_link: bool,
+    /// Set by QUIT, so the connection closes once the reply is written
+    pub closing: bool,
+}
+
+#[derive(Default)]
+pub struct Transaction {
+    pub commands: Vec<(String, Vec<Command>)>,
+    /// A command was rejected while queueing, so EXEC will refuse to run
+    pub aborted: bool,
+}
+
+/// The state of a running EXEC. The storage lock is held throughout so no
+/// other client can interleave, and replies are collected for the EXEC array
+/// instead of being written out.
+pub struct Exec {
+    pub storage: OwnedMutexGuard<Storage>,
+    pub replies: Vec<Command>,
+}
+
+/// The storage, either locked for the caller or borrowed from a running EXEC.
+pub enum StorageGuard<'a> {
+    Locked(MutexGuard<'a, Storage>),
+    Held(&'a mut Storage),
+}
+
+impl Deref for StorageGuard<'_> {
+    type Target = Storage;
+
+    fn deref(&self) -> &Storage {
+        match self {
+            StorageGuard::Locked(guard) => guard,
+            StorageGuard::Held(storage) => storage,
+        }
+    }
+}
+
+impl DerefMut for StorageGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Storage {
+        match self {
+            StorageGuard::Locked(guard) => guard,
+            StorageGuard::Held(storage) => storage,
+        }
+    }
+}
+
+impl Session {
+    pub fn new(command_handler: CommandHandler, storage: Arc<Mutex<Storage>>, config: Arc<Mutex<ServerConfig>>) -> Self {
+        let (subscriber, messages) = Subscriber::channel(PUBSUB_OUTPUT_BUFFER_LIMIT);
+        let output = Arc::clone(subscriber.output());
+
+        Session {
+            command_handler,
+            storage,
+            config,
+            subscriptions: HashSet::new(),
+            subscriber,
+            messages,
+            output,
+            transaction: None,
+            watched: HashMap::new(),
+            exec: None,
+            master_link: false,
+            closing: false,
+        }
+    }
+
+    /// Locks the storage, unless a running EXEC already holds the lock.
+    pub async fn lock_storage(&mut self) -> StorageGuard<'_> {
+        match &mut self.exec {
+            Some(exec) => StorageGuard::Held(&mut exec.storage),
+            None => StorageGuard::Locked(self.storage.lock().await),
+        }
+    }
+
+    /// Whether a RESP2 connection is restricted to subscribe-family commands.
+    pub fn in_subscribe_mode(&self) -> bool {
+        !self.subscriptions.is_empty()
+    }
+
+    /// The count reported in subscribe confirmations: shard channels are
+    /// counted on their own, channels and patterns together.
+    pub fn subscription_count(&self, kind: SubscriptionKind) -> usize {
+        let sharded = kind == SubscriptionKind::ShardChannel;
+
+        self.subscriptions.iter()
+            .filter(|(subscribed, _)| (*subscribed == SubscriptionKind::ShardChannel) == sharded)
+            .count()
+    }
+
+    /// Drops every subscription from the registry, e.g. once the connection
+    /// has closed.
+    pub async fn unsubscribe_all(&mut self) {
+        if self.subscriptions.is_empty() {
+            return;
+        }
+
+        let mut storage = self.storage.lock().await;
+        for (kind, name) in self.subscriptions.drain() {
+            storage.pubsub.unsubscribe(kind, &name, self.command_handler.id);
+        }
+    }
+
+    /// Forgets every WATCHed key, as EXEC, DISCARD and UNWATCH do.
+    pub async fn unwatch_all(&mut self) {
+        if self.watched.is_empty() {
+            return;
+        }
+
+        let watched = std::mem::take(&mut self.watched);
+        let mut storage = self.lock_storage().await;
+        for key in watched.keys() {
+            storage.unwatch(key);
+        }
+    }
+}
@@ -1,55 +1,449 @@
-use std::collections::HashMap;
-use chrono::{Utc};
+mod blocking;
+mod hash;
+mod notify;
+mod propagate;
+mod pubsub;
+mod sample;
+mod scan;
+mod set;
+mod skiplist;
+mod sorted_set;
+mod stream;
+mod value;
+mod watch;
 
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use crate::command_handler::Command;
+use crate::error::CommandError;
+use crate::util::now_millis;
+
+pub use blocking::{BlockedOp, Blocking};
+pub use hash::{HashField, HashValue, LiveFields};
+pub use notify::EventClasses;
+pub use pubsub::{OutputBuffer, PubSub, Subscriber, SubscriptionKind, PUBSUB_OUTPUT_BUFFER_LIMIT};
+pub use sample::SampleSet;
+pub use scan::{ScanMap, ScanSet};
+pub use set::SetValue;
+pub use skiplist::{LexBound, ScoreBound};
+pub use sorted_set::SortedSet;
+pub use stream::{Consumer, ConsumerGroup, PendingEntry, Stream, StreamFields, StreamId};
+pub use value::Value;
+pub use watch::WatchedKeys;
+
+/// Keys sampled per active expire iteration, as in Redis.
+const ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP: usize = 20;
+/// An iteration finding more than this share of expired keys triggers another.
+const ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE: f64 = 0.10;
+/// Upper bound on the time a single cycle may hold the storage lock.
+const ACTIVE_EXPIRE_CYCLE_TIME_LIMIT: Duration = Duration::from_millis(25);
+/// Picks RANDOMKEY makes on a replica before settling for an expired key,
+/// which it cannot delete, as in Redis.
+const RANDOM_KEY_MAX_TRIES_ON_REPLICA: usize = 100;
+
+/// What happens to keys and hash fields found past their TTL.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExpiryMode {
+    /// They are deleted and the replicas told to delete them too, as on a master
+    #[default]
+    Evict,
+    /// They are reported missing but kept, as on a replica, where only the
+    /// master's DEL or HDEL removes data so that both stay the same
+    Hide,
+    /// They are treated as live, as when a replica applies its master's
+    /// stream, which was written against data the master still had
+    Keep,
+}
+
+#[derive(Clone)]
 pub struct StorageRecord {
-    pub value: String,
+    pub value: Value,
     pub expires_at: i64,
 }
 impl StorageRecord {
-    pub fn new(v: String, exp: i64) -> Self {
+    pub fn new(v: Value, exp: i64) -> Self {
         StorageRecord {
             value: v,
             expires_at: exp,
         }
     }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at != 0 && self.expires_at <= now
+    }
+
+}
+
+#[derive(Default)]
+pub struct ExpiryStats {
+    pub expired_keys: u64,
+    /// Running estimate of the share of volatile keys that are logically
+    /// expired but not yet reclaimed, in percent.
+    pub expired_stale_perc: f64,
+    pub expire_cycle_cpu_milliseconds: u128,
+    /// Hash fields removed because their own TTL passed
+    pub expired_subkeys: u64,
 }
 
 
 pub struct Storage {
-    pub _set: HashMap<String, StorageRecord>,
+    pub _set: ScanMap<StorageRecord>,
+    /// Keys with a TTL, sampled by the active expire cycle
+    volatile: SampleSet,
+    /// Hashes with at least one field TTL, also sampled by the cycle
+    volatile_fields: SampleSet,
+    pub stats: ExpiryStats,
+    blocking: Blocking,
+    pub pubsub: PubSub,
+    /// `Hide` on a replica, see `ExpiryMode`
+    pub expiry_mode: ExpiryMode,
+    /// Set through `CONFIG SET notify-keyspace-events`
+    pub notify_keyspace_events: EventClasses,
+    watched: WatchedKeys,
+    /// Writes waiting to be sent to the replicas
+    propagated: Vec<Command>,
+    /// Changes made to the dataset so far, so that a write command that
+    /// changed nothing is not sent to the replicas
+    dirty: u64,
+    /// How many of `propagated` were queued by commands themselves, as
+    /// opposed to deletions of keys found expired along the way
+    propagated_by_commands: usize,
 }
 
 impl Storage {
     pub fn new() -> Self {
         Storage {
-            _set: HashMap::new()
+            _set: ScanMap::new(),
+            volatile: SampleSet::default(),
+            volatile_fields: SampleSet::default(),
+            stats: ExpiryStats::default(),
+            blocking: Blocking::default(),
+            pubsub: PubSub::default(),
+            expiry_mode: ExpiryMode::Evict,
+            notify_keyspace_events: EventClasses::NONE,
+            watched: WatchedKeys::default(),
+            dirty: 0,
+            propagated: vec![],
+            propagated_by_commands: 0,
         }
     }
 
-    pub fn set(&mut self, kv: (String, String), exp_at: i64) {
-        self._set.insert(kv.0.clone(), StorageRecord::new(kv.1.clone(), exp_at));
+    pub fn set(&mut self, kv: (Bytes, Bytes), exp_at: i64) {
+        self.insert(kv.0, StorageRecord::new(Value::String(kv.1), exp_at));
     }
 
-    pub fn get(&mut self, k: &str) -> Option<&StorageRecord> {
-        let record_option = self._set.remove(k);
+    /// Stores `record` under `k`, replacing any previous value and TTL.
+    pub fn insert(&mut self, k: Bytes, record: StorageRecord) {
+        self.expire_if_needed(&k, now_millis());
+        if !self._set.contains_key(&k) {
+            self.notify(EventClasses::NEW, "new", &k);
+        }
 
-        if let Some(record) = record_option {
-            let expires = record.expires_at;
-            let now = Utc::now().timestamp_millis();
-            println!("Get...........");
-            println!("key: {}; now: {}; expires: {}; diff: {}", k, now, expires, expires - now);
-            println!(".................");
+        if record.expires_at == 0 {
+            self.volatile.remove(&k);
+        } else {
+            self.volatile.insert(k.clone());
+        }
 
-            if expires == 0 || (expires > now) {
-                println!("not expired yet");
-                self._set.insert(k.to_string(), record);
-            } else {
-                return None;
-            }
+        if record.value.has_volatile_fields() {
+            self.volatile_fields.insert(k.clone());
+        } else {
+            self.volatile_fields.remove(&k);
+        }
+
+        self._set.insert(k, record);
+    }
+
+    /// Registers `k` as a hash with field TTLs so expired fields get reclaimed
+    /// even if the hash is never read again.
+    pub fn track_field_expiry(&mut self, k: &Bytes) {
+        self.volatile_fields.insert(k.clone());
+    }
+
+    pub fn contains(&mut self, k: &[u8]) -> bool {
+        self.get(k).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self._set.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.dirty += 1;
+        self.touch_all();
+        self._set.clear();
+        self.volatile.clear();
+        self.volatile_fields.clear();
+    }
+
+    /// Replaces the whole dataset, e.g. with the snapshot a replica loads
+    /// after FULLRESYNC.
+    pub fn replace_all(&mut self, records: Vec<(Bytes, StorageRecord)>) {
+        self.clear();
+        for (key, record) in records {
+            self.insert(key, record);
+        }
+    }
 
-            return Some(&self._set.get(k).unwrap());
+    /// Keys that have not expired yet, in arbitrary order.
+    pub fn keys(&self) -> impl Iterator<Item=(&Bytes, &StorageRecord)> {
+        let now = now_millis();
+
+        self._set.iter().filter(move |(_, record)| self.is_live(record, now))
+    }
+
+    /// One SCAN step over the keys, skipping the ones that have expired.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&Bytes, &StorageRecord)>) {
+        let now = now_millis();
+        let (next_cursor, mut keys) = self._set.scan(cursor, count);
+        keys.retain(|(_, record)| self.is_live(record, now));
+
+        (next_cursor, keys)
+    }
+
+    /// A random key that has not expired, evicting expired ones it runs into.
+    pub fn random_key(&mut self) -> Option<Bytes> {
+        let mut tries = 0;
+
+        while let Some(key) = self._set.random().map(|(key, _)| key.clone()) {
+            tries += 1;
+            if !self.expire_if_needed(&key, now_millis()) {
+                return Some(key);
+            }
+            // A replica keeps its expired keys, so it could pick them forever
+            if self.expiry_mode == ExpiryMode::Hide && tries >= RANDOM_KEY_MAX_TRIES_ON_REPLICA {
+                return Some(key);
+            }
         }
 
         None
     }
+
+    pub fn get(&mut self, k: &[u8]) -> Option<&StorageRecord> {
+        if self.expire_if_needed(k, now_millis()) {
+            return None;
+        }
+
+        self._set.get(k)
+    }
+
+    /// A live record without evicting anything, for reading several keys at
+    /// once. Callers that may see expired keys should `get` them first.
+    pub fn peek(&self, k: &[u8]) -> Option<&StorageRecord> {
+        self._set.get(k).filter(|record| self.is_live(record, now_millis()))
+    }
+
+    /// Runs `f` the way a replica applies its master's stream, which must find
+    /// the keys and fields the master still had, whatever our clock says.
+    pub fn apply_master_stream<T>(&mut self, f: impl FnOnce(&mut Storage) -> T) -> T {
+        let mode = std::mem::replace(&mut self.expiry_mode, ExpiryMode::Keep);
+        let result = f(self);
+        self.expiry_mode = mode;
+        result
+    }
+
+    fn is_live(&self, record: &StorageRecord, now: i64) -> bool {
+        self.expiry_mode == ExpiryMode::Keep || !record.is_expired(now)
+    }
+
+    /// The string stored at `k`, or WRONGTYPE if it holds another kind of value.
+    pub fn get_string(&mut self, k: &[u8]) -> Result<Option<Bytes>, CommandError> {
+        match self.get(k) {
+            Some(record) => Ok(Some(record.value.as_string()?.clone())),
+            None => Ok(None)
+        }
+    }
+
+    /// The value at `k`, created with `default` if the key does not exist.
+    pub fn get_or_insert(&mut self, k: &Bytes, default: impl FnOnce() -> Value) -> &mut Value {
+        if !self.contains(k) {
+            self.insert(k.clone(), StorageRecord::new(default(), 0));
+        }
+
+        &mut self._set.get_mut(k.as_ref()).unwrap().value
+    }
+
+    /// Deletes `k` if it holds a collection that has been emptied.
+    pub fn remove_if_empty(&mut self, k: &[u8]) {
+        if self._set.get(k).is_some_and(|record| record.value.is_empty_collection()) {
+            self.remove(k);
+            self.notify(EventClasses::GENERIC, "del", k);
+        }
+    }
+
+    /// Mutable access to a live record. Changing `expires_at` through it
+    /// bypasses TTL tracking; use `set_expires_at` for that.
+    pub fn get_mut(&mut self, k: &[u8]) -> Option<&mut StorageRecord> {
+        if self.expire_if_needed(k, now_millis()) {
+            return None;
+        }
+
+        self._set.get_mut(k)
+    }
+
+    pub fn remove(&mut self, k: &[u8]) -> Option<StorageRecord> {
+        self.volatile.remove(k);
+        self.volatile_fields.remove(k);
+        self._set.remove(k)
+    }
+
+    /// Sets the absolute expiry of an existing key, `0` clearing it. Returns
+    /// false when the key does not exist.
+    pub fn set_expires_at(&mut self, k: &[u8], exp_at: i64) -> bool {
+        if self.expire_if_needed(k, now_millis()) {
+            return false;
+        }
+
+        let key = match self._set.get_key_value(k) {
+            Some((key, _)) => key.clone(),
+            None => return false
+        };
+        self._set.get_mut(k).unwrap().expires_at = exp_at;
+
+        if exp_at == 0 {
+            self.volatile.remove(k);
+        } else {
+            self.volatile.insert(key);
+        }
+
+        true
+    }
+
+    /// How many changes were made to the dataset. Each `notify` counts as one.
+    pub fn dirty(&self) -> u64 {
+        self.dirty
+    }
+
+    /// Counts a change that, unlike the others, fires no keyspace event.
+    pub fn mark_dirty(&mut self) {
+        self.dirty += 1;
+    }
+
+    pub fn volatile_len(&self) -> usize {
+        self.volatile.len()
+    }
+
+    /// Samples keys with a TTL and evicts the expired ones, repeating while a
+    /// large share of each sample turns out to be expired, like Redis's
+    /// `activeExpireCycle`.
+    pub fn active_expire_cycle(&mut self) {
+        if self.expiry_mode != ExpiryMode::Evict {
+            return;
+        }
+
+        let started = Instant::now();
+        let mut sampled = 0;
+        let mut expired = 0;
+
+        loop {
+            let sample: Vec<Bytes> = self.volatile.sample(ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP).into_iter().cloned().collect();
+            if sample.is_empty() {
+                break;
+            }
+
+            let now = now_millis();
+            let expired_in_sample = sample.iter().filter(|key| self.expire_if_needed(key, now)).count();
+
+            sampled += sample.len();
+            expired += expired_in_sample;
+
+            let stale = expired_in_sample as f64 / sample.len() as f64;
+            if stale <= ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE || started.elapsed() >= ACTIVE_EXPIRE_CYCLE_TIME_LIMIT {
+                break;
+            }
+        }
+
+        // Field TTLs don't feed the stale estimate, they just get reclaimed
+        let now = now_millis();
+        let sample: Vec<Bytes> = self.volatile_fields.sample(ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP).into_iter().cloned().collect();
+        for key in sample {
+            self.expire_fields(&key, now);
+        }
+
+        let current_perc = if sampled > 0 { expired as f64 / sampled as f64 * 100.0 } else { 0.0 };
+        self.stats.expired_stale_perc = current_perc * 0.05 + self.stats.expired_stale_perc * 0.95;
+        self.stats.expire_cycle_cpu_milliseconds += started.elapsed().as_millis();
+    }
+
+    /// Removes `key` if its TTL has passed, returning whether it is gone as
+    /// far as the caller is concerned. Expired hash fields are dropped along
+    /// the way. See `ExpiryMode` for what a replica does instead.
+    fn expire_if_needed(&mut self, key: &[u8], now: i64) -> bool {
+        match (self.expiry_mode, self._set.get(key)) {
+            (ExpiryMode::Keep, _) => false,
+            (ExpiryMode::Hide, record) => record.is_some_and(|record| record.is_expired(now)),
+            (ExpiryMode::Evict, Some(record)) if record.is_expired(now) => {
+                self.remove(key);
+                self.stats.expired_keys += 1;
+                self.publish(EventClasses::EXPIRED, "expired", key);
+                self.propagate_expired(vec![Command::bulk("DEL"), Command::bulk(Bytes::copy_from_slice(key))]);
+                true
+            }
+            (ExpiryMode::Evict, Some(_)) if self.volatile_fields.contains(key) => {
+                self.expire_fields(key, now);
+                false
+            }
+            (ExpiryMode::Evict, _) => false
+        }
+    }
+
+    /// Drops the expired fields of the hash at `key`, deleting the key once no
+    /// fields are left.
+    fn expire_fields(&mut self, key: &[u8], now: i64) {
+        let hash = match self._set.get_mut(key).map(|record| &mut record.value) {
+            Some(Value::Hash(hash)) => hash,
+            _ => {
+                self.volatile_fields.remove(key);
+                return;
+            }
+        };
+
+        let expired = hash.expire_fields(now);
+        self.stats.expired_subkeys += expired.len() as u64;
+
+        let (is_empty, has_ttls) = (hash.is_empty(), hash.has_volatile_fields());
+        if !expired.is_empty() {
+            self.publish(EventClasses::HASH, "hexpired", key);
+            self.propagate_expired([Command::bulk("HDEL"), Command::bulk(Bytes::copy_from_slice(key))].into_iter()
+                .chain(expired.into_iter().map(Command::BulkString))
+                .collect());
+        }
+        if is_empty {
+            self.remove(key);
+            self.publish(EventClasses::GENERIC, "del", key);
+        } else if !has_ttls {
+            self.volatile_fields.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicas_hide_expired_keys_and_fields_until_the_master_deletes_them() {
+        let mut storage = Storage::new();
+        storage.expiry_mode = ExpiryMode::Hide;
+        storage.set((Bytes::from("key"), Bytes::from("value")), 1);
+        let mut hash = HashValue::new();
+        hash.insert(Bytes::from("field"), HashField { value: Bytes::from("value"), expires_at: 1 });
+        storage.insert(Bytes::from("hash"), StorageRecord::new(Value::Hash(hash), 0));
+
+        assert!(storage.get(b"key".as_slice()).is_none());
+        assert!(storage.get_mut(b"key".as_slice()).is_none());
+        assert_eq!(storage.keys().count(), 1);
+        assert_eq!(storage.get(b"hash".as_slice()).unwrap().value.as_hash().unwrap().live(now_millis()).len(), 0);
+
+        storage.active_expire_cycle();
+        assert_eq!(storage.len(), 2);
+        assert!(storage.take_propagated().is_empty());
+
+        // The master still had both when it sent its writes
+        storage.apply_master_stream(|storage| {
+            assert!(storage.get(b"key".as_slice()).is_some());
+            assert_eq!(storage.get(b"hash".as_slice()).unwrap().value.as_hash().unwrap().len(), 1);
+        });
+        assert!(storage.get(b"key".as_slice()).is_none());
+    }
 }
@@ -0,0 +1,116 @@
+use crate::command_handler::Command;
+use crate::error::CommandError;
+use crate::storage::Storage;
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::sync::oneshot;
+
+/// Re-runs a blocked command against the keyspace. Returns `Ok(None)` while
+/// there is still nothing to serve, which keeps the client blocked.
+pub type BlockedOp = Box<dyn FnMut(&mut Storage) -> Result<Option<Command>, CommandError> + Send>;
+
+pub type BlockedReply = Result<Command, CommandError>;
+
+struct BlockedClient {
+    keys: Vec<Bytes>,
+    op: BlockedOp,
+    reply: oneshot::Sender<BlockedReply>,
+}
+
+/// Clients blocked on keys, served in the order they blocked.
+#[derive(Default)]
+pub struct Blocking {
+    next_id: u64,
+    clients: HashMap<u64, BlockedClient>,
+    by_key: HashMap<Bytes, VecDeque<u64>>,
+    ready: Vec<Bytes>,
+}
+
+impl Storage {
+    /// Parks a client until `op` produces a reply for one of `keys`. The
+    /// reply is delivered on the returned receiver.
+    pub fn block(&mut self, keys: Vec<Bytes>, op: BlockedOp) -> (u64, oneshot::Receiver<BlockedReply>) {
+        let (sender, receiver) = oneshot::channel();
+        let id = self.blocking.next_id;
+        self.blocking.next_id += 1;
+
+        for key in keys.iter().collect::<HashSet<_>>() {
+            self.blocking.by_key.entry(key.clone()).or_default().push_back(id);
+        }
+        self.blocking.clients.insert(id, BlockedClient { keys, op, reply: sender });
+
+        (id, receiver)
+    }
+
+    /// Removes a blocked client, e.g. once its timeout has elapsed.
+    pub fn unblock(&mut self, id: u64) {
+        if let Some(client) = self.blocking.clients.remove(&id) {
+            self.forget_blocked_client(id, &client.keys);
+        }
+    }
+
+    /// Marks `key` as having received data that blocked clients may want.
+    pub fn signal_ready(&mut self, key: &Bytes) {
+        if self.blocking.by_key.contains_key(key) && !self.blocking.ready.contains(key) {
+            self.blocking.ready.push(key.clone());
+        }
+    }
+
+    /// Serves clients blocked on keys signalled as ready, oldest first, until
+    /// the keys run dry. Serving one client can make further keys ready (e.g.
+    /// BLMOVE pushing to its destination), so this runs to a fixed point.
+    pub fn serve_blocked(&mut self) {
+        while !self.blocking.ready.is_empty() {
+            let ready = std::mem::take(&mut self.blocking.ready);
+
+            for key in ready {
+                self.serve_blocked_on(&key);
+            }
+        }
+    }
+
+    fn serve_blocked_on(&mut self, key: &Bytes) {
+        let queue: Vec<u64> = match self.blocking.by_key.get(key) {
+            Some(queue) => queue.iter().copied().collect(),
+            None => return
+        };
+
+        for id in queue {
+            let mut client = match self.blocking.clients.remove(&id) {
+                Some(client) => client,
+                None => continue
+            };
+
+            // A client that has gone away must not consume anything
+            if client.reply.is_closed() {
+                self.forget_blocked_client(id, &client.keys);
+                continue;
+            }
+
+            let reply = match (client.op)(self) {
+                Ok(Some(reply)) => Ok(reply),
+                Ok(None) => {
+                    // Nothing left for this client, so none for those behind it either
+                    self.blocking.clients.insert(id, client);
+                    return;
+                }
+                Err(e) => Err(e)
+            };
+
+            self.forget_blocked_client(id, &client.keys);
+            let _ = client.reply.send(reply);
+        }
+    }
+
+    fn forget_blocked_client(&mut self, id: u64, keys: &[Bytes]) {
+        for key in keys {
+            if let Some(queue) = self.blocking.by_key.get_mut(key) {
+                queue.retain(|queued| *queued != id);
+
+                if queue.is_empty() {
+                    self.blocking.by_key.remove(key);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::storage::ScanMap;
+use bytes::Bytes;
+use std::ops::{Deref, DerefMut};
+
+/// A hash field, which can carry its own TTL (HEXPIRE and friends)
+#[derive(Clone, Debug)]
+pub struct HashField {
+    pub value: Bytes,
+    pub expires_at: i64,
+}
+
+impl HashField {
+    pub fn new(value: Bytes) -> Self {
+        HashField {
+            value,
+            expires_at: 0,
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at != 0 && self.expires_at <= now
+    }
+}
+
+/// The fields of a hash, along with a lower bound on their TTLs so that
+/// looking for expired fields only walks the hash once one may be due. TTLs
+/// are set through `insert` or `set_expiry`; removing or extending one leaves
+/// the bound early, which costs a single extra walk.
+#[derive(Clone, Debug)]
+pub struct HashValue {
+    fields: ScanMap<HashField>,
+    /// No field expires before this, `0` when none has a TTL
+    min_expiry: i64,
+}
+
+impl HashValue {
+    pub fn new() -> Self {
+        HashValue {
+            fields: ScanMap::new(),
+            min_expiry: 0,
+        }
+    }
+
+    pub fn insert(&mut self, name: Bytes, field: HashField) -> Option<HashField> {
+        self.lower_min_expiry(field.expires_at);
+        self.fields.insert(name, field)
+    }
+
+    /// Sets the TTL of an existing field, `0` clearing it.
+    pub fn set_expiry(&mut self, name: &[u8], expires_at: i64) {
+        if let Some(field) = self.fields.get_mut(name) {
+            field.expires_at = expires_at;
+            self.lower_min_expiry(expires_at);
+        }
+    }
+
+    /// Whether some field may carry a TTL.
+    pub fn has_volatile_fields(&self) -> bool {
+        self.min_expiry != 0
+    }
+
+    /// Whether some field may have expired by `now`, without walking them.
+    pub fn may_have_expired(&self, now: i64) -> bool {
+        self.min_expiry != 0 && self.min_expiry <= now
+    }
+
+    /// Removes the fields that expired by `now`, returning their names.
+    pub fn expire_fields(&mut self, now: i64) -> Vec<Bytes> {
+        if !self.may_have_expired(now) {
+            return vec![];
+        }
+
+        let expired: Vec<Bytes> = self.fields.iter()
+            .filter(|(_, field)| field.is_expired(now))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &expired {
+            self.fields.remove(name);
+        }
+
+        self.min_expiry = self.fields.values().map(|field| field.expires_at).filter(|expires_at| *expires_at != 0).min().unwrap_or(0);
+        expired
+    }
+
+    /// The fields a read at `now` gets to see.
+    pub fn live(&self, now: i64) -> LiveFields<'_> {
+        LiveFields { hash: self, now }
+    }
+
+    fn lower_min_expiry(&mut self, expires_at: i64) {
+        if expires_at != 0 && (self.min_expiry == 0 || expires_at < self.min_expiry) {
+            self.min_expiry = expires_at;
+        }
+    }
+}
+
+/// The fields of a hash that have not expired by `now`. A master drops
+/// expired fields before anything reads the hash, but a replica keeps them
+/// until its master's HDEL arrives, so reads skip them here.
+#[derive(Clone, Copy)]
+pub struct LiveFields<'a> {
+    hash: &'a HashValue,
+    now: i64,
+}
+
+impl<'a> LiveFields<'a> {
+    pub fn get(&self, name: &[u8]) -> Option<&'a HashField> {
+        self.hash.fields.get(name).filter(|field| !field.is_expired(self.now))
+    }
+
+    pub fn contains_key(&self, name: &[u8]) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        if self.hash.may_have_expired(self.now) {
+            self.iter().count()
+        } else {
+            self.hash.len()
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&'a Bytes, &'a HashField)> {
+        let now = self.now;
+        self.hash.fields.iter().filter(move |(_, field)| !field.is_expired(now))
+    }
+
+    /// A field picked at random.
+    pub fn random(&self) -> Option<(&'a Bytes, &'a HashField)> {
+        self.hash.fields.random().filter(|(_, field)| !field.is_expired(self.now))
+    }
+
+    /// Up to `count` distinct fields picked at random.
+    pub fn sample(&self, count: usize) -> Vec<(&'a Bytes, &'a HashField)> {
+        let mut fields = self.hash.fields.sample(count);
+        fields.retain(|(_, field)| !field.is_expired(self.now));
+        fields
+    }
+
+    /// One HSCAN step, see `ScanMap::scan`.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&'a Bytes, &'a HashField)>) {
+        let (next_cursor, mut fields) = self.hash.fields.scan(cursor, count);
+        fields.retain(|(_, field)| !field.is_expired(self.now));
+        (next_cursor, fields)
+    }
+}
+
+impl Deref for HashValue {
+    type Target = ScanMap<HashField>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.fields
+    }
+}
+
+impl DerefMut for HashValue {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.fields
+    }
+}
+
+impl<'a> IntoIterator for &'a HashValue {
+    type Item = (&'a Bytes, &'a HashField);
+    type IntoIter = std::collections::hash_map::Iter<'a, Bytes, HashField>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fields.iter()
+    }
+}
+
+impl FromIterator<(Bytes, HashField)> for HashValue {
+    fn from_iter<I: IntoIterator<Item=(Bytes, HashField)>>(fields: I) -> Self {
+        let mut hash = HashValue::new();
+        for (name, field) in fields {
+            hash.insert(name, field);
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(expires_at: i64) -> HashField {
+        HashField { value: Bytes::from("value"), expires_at }
+    }
+
+    #[test]
+    fn fields_are_only_walked_once_the_earliest_ttl_is_due() {
+        let mut hash: HashValue = [("a", 0), ("b", 200), ("c", 100)].into_iter()
+            .map(|(name, expires_at)| (Bytes::from(name), field(expires_at)))
+            .collect();
+
+        assert!(!hash.may_have_expired(99));
+        assert!(hash.expire_fields(99).is_empty());
+        assert_eq!(hash.expire_fields(150), vec![Bytes::from("c")]);
+        assert!(!hash.may_have_expired(199));
+
+        hash.set_expiry(b"b", 0);
+        assert!(hash.may_have_expired(200));
+        assert!(hash.expire_fields(200).is_empty());
+        assert!(!hash.has_volatile_fields());
+        assert_eq!(hash.len(), 2);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::storage::Storage;
+use bytes::{Bytes, BytesMut};
+use std::fmt;
+use std::ops::BitOr;
+
+/// The event classes selected by the `notify-keyspace-events` flag string.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EventClasses(u32);
+
+impl EventClasses {
+    pub const NONE: EventClasses = EventClasses(0);
+    /// `K`: publish to `__keyspace@0__:<key>`
+    pub const KEYSPACE: EventClasses = EventClasses(1 << 0);
+    /// `E`: publish to `__keyevent@0__:<event>`
+    pub const KEYEVENT: EventClasses = EventClasses(1 << 1);
+    pub const GENERIC: EventClasses = EventClasses(1 << 2);
+    pub const STRING: EventClasses = EventClasses(1 << 3);
+    pub const LIST: EventClasses = EventClasses(1 << 4);
+    pub const SET: EventClasses = EventClasses(1 << 5);
+    pub const HASH: EventClasses = EventClasses(1 << 6);
+    pub const ZSET: EventClasses = EventClasses(1 << 7);
+    pub const EXPIRED: EventClasses = EventClasses(1 << 8);
+    pub const EVICTED: EventClasses = EventClasses(1 << 9);
+    pub const STREAM: EventClasses = EventClasses(1 << 10);
+    pub const KEY_MISS: EventClasses = EventClasses(1 << 11);
+    pub const MODULE: EventClasses = EventClasses(1 << 12);
+    pub const NEW: EventClasses = EventClasses(1 << 13);
+
+    /// `A`, which leaves out key misses and new keys like Redis does
+    const ALL: EventClasses = EventClasses(
+        Self::GENERIC.0 | Self::STRING.0 | Self::LIST.0 | Self::SET.0 | Self::HASH.0 | Self::ZSET.0
+            | Self::EXPIRED.0 | Self::EVICTED.0 | Self::STREAM.0 | Self::MODULE.0
+    );
+
+    /// Parses a flag string such as `KEA` or `Ex`, rejecting unknown characters.
+    pub fn parse(flags: &str) -> Option<Self> {
+        let mut classes = EventClasses::NONE;
+
+        for flag in flags.chars() {
+            classes = classes | match flag {
+                'A' => EventClasses::ALL,
+                'g' => EventClasses::GENERIC,
+                '$' => EventClasses::STRING,
+                'l' => EventClasses::LIST,
+                's' => EventClasses::SET,
+                'h' => EventClasses::HASH,
+                'z' => EventClasses::ZSET,
+                'x' => EventClasses::EXPIRED,
+                'e' => EventClasses::EVICTED,
+                'K' => EventClasses::KEYSPACE,
+                'E' => EventClasses::KEYEVENT,
+                't' => EventClasses::STREAM,
+                'm' => EventClasses::KEY_MISS,
+                'd' => EventClasses::MODULE,
+                'n' => EventClasses::NEW,
+                _ => return None
+            };
+        }
+
+        Some(classes)
+    }
+
+    pub fn intersects(self, other: EventClasses) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for EventClasses {
+    type Output = EventClasses;
+
+    fn bitor(self, other: EventClasses) -> EventClasses {
+        EventClasses(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for EventClasses {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut flags = String::new();
+
+        if self.0 & EventClasses::ALL.0 == EventClasses::ALL.0 {
+            flags.push('A');
+        } else {
+            for (class, flag) in [
+                (EventClasses::GENERIC, 'g'), (EventClasses::STRING, '$'), (EventClasses::LIST, 'l'),
+                (EventClasses::SET, 's'), (EventClasses::HASH, 'h'), (EventClasses::ZSET, 'z'),
+                (EventClasses::EXPIRED, 'x'), (EventClasses::EVICTED, 'e'), (EventClasses::STREAM, 't'),
+                (EventClasses::MODULE, 'd'),
+            ] {
+                if self.intersects(class) {
+                    flags.push(flag);
+                }
+            }
+        }
+
+        for (class, flag) in [(EventClasses::KEYSPACE, 'K'), (EventClasses::KEYEVENT, 'E'), (EventClasses::KEY_MISS, 'm'), (EventClasses::NEW, 'n')] {
+            if self.intersects(class) {
+                flags.push(flag);
+            }
+        }
+
+        f.write_str(&flags)
+    }
+}
+
+impl Storage {
+    /// Publishes `event` on `key` to the keyspace and keyevent channels, if
+    /// `class` is one of the configured event classes. Every change to a key
+    /// is reported here, so this is also where WATCHes get invalidated and
+    /// the change is counted in `dirty`.
+    pub fn notify(&mut self, class: EventClasses, event: &str, key: &[u8]) {
+        self.mark_dirty();
+        self.publish(class, event, key);
+    }
+
+    /// Like `notify`, for changes the server makes by itself, such as
+    /// deleting expired keys, which are not a change made by the command
+    /// that ran into them.
+    pub(super) fn publish(&mut self, class: EventClasses, event: &str, key: &[u8]) {
+        self.touch(key);
+
+        let classes = self.notify_keyspace_events;
+        if !classes.intersects(class) {
+            return;
+        }
+
+        if classes.intersects(EventClasses::KEYSPACE) {
+            self.pubsub.publish(&channel("__keyspace@0__:", key), &Bytes::copy_from_slice(event.as_bytes()));
+        }
+        if classes.intersects(EventClasses::KEYEVENT) {
+            self.pubsub.publish(&channel("__keyevent@0__:", event.as_bytes()), &Bytes::copy_from_slice(key));
+        }
+    }
+}
+
+fn channel(prefix: &str, suffix: &[u8]) -> Bytes {
+    let mut channel = BytesMut::from(prefix.as_bytes());
+    channel.extend_from_slice(suffix);
+    channel.freeze()
+}
@@ -0,0 +1,46 @@
+use crate::command_handler::Command;
+use crate::storage::Storage;
+
+impl Storage {
+    /// Queues a write for the replicas. Commands whose effect depends on
+    /// when or where they run queue a deterministic equivalent instead, e.g.
+    /// SREM for the members SPOP picked, or LPOP for a served BLPOP.
+    pub fn propagate(&mut self, command: Vec<Command>) {
+        self.propagated.push(Command::Array(command));
+        self.propagated_by_commands += 1;
+    }
+
+    /// Queues the deletion of something that expired on this server, so
+    /// replicas drop it even if their own clocks lag behind.
+    pub(super) fn propagate_expired(&mut self, command: Vec<Command>) {
+        self.propagated.push(Command::Array(command));
+    }
+
+    /// How many writes commands queued themselves, to tell whether a command
+    /// rewrote what it sends to the replicas.
+    pub fn propagated_by_commands(&self) -> usize {
+        self.propagated_by_commands
+    }
+
+    /// The writes queued since the last call, oldest first.
+    pub fn take_propagated(&mut self) -> Vec<Command> {
+        self.propagated_by_commands = 0;
+        std::mem::take(&mut self.propagated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn expired_keys_are_deleted_on_the_replicas_without_counting_as_a_rewrite() {
+        let mut storage = Storage::new();
+        storage.set((Bytes::from("key"), Bytes::from("value")), 1);
+
+        assert!(storage.get(b"key".as_slice()).is_none());
+        assert_eq!(storage.propagated_by_commands(), 0);
+        assert_eq!(storage.take_propagated(), vec![Command::Array(vec![Command::bulk("DEL"), Command::bulk("key")])]);
+    }
+}
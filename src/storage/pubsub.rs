@@ -0,0 +1,215 @@
+use crate::command_handler::Command;
+use crate::util::glob_match;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Notify};
+
+/// How much published data may wait to be written to a subscriber before it
+/// is disconnected, like the hard `client-output-buffer-limit` for pubsub
+pub const PUBSUB_OUTPUT_BUFFER_LIMIT: usize = 32 * 1024 * 1024;
+
+/// Where a connection task receives the messages published to it, or the
+/// replication stream once it serves a replica.
+#[derive(Clone, Debug)]
+pub struct Subscriber {
+    sender: mpsc::UnboundedSender<Command>,
+    output: Arc<OutputBuffer>,
+}
+
+impl Subscriber {
+    /// A subscriber along with the end its connection task reads from.
+    pub fn channel(limit: usize) -> (Self, mpsc::UnboundedReceiver<Command>) {
+        let (sender, messages) = mpsc::unbounded_channel();
+        let output = Arc::new(OutputBuffer {
+            pending: AtomicUsize::new(0),
+            limit: AtomicUsize::new(limit),
+            overflow: Notify::new(),
+        });
+
+        (Subscriber { sender, output }, messages)
+    }
+
+    /// Queues `command` for the connection, returning false if it is gone or
+    /// has fallen too far behind to keep.
+    pub fn send(&self, command: Command) -> bool {
+        self.output.queue(command.clone().serialize().len()) && self.sender.send(command).is_ok()
+    }
+
+    pub fn output(&self) -> &Arc<OutputBuffer> {
+        &self.output
+    }
+}
+
+/// Bytes queued for a connection that it has not written out yet.
+#[derive(Debug)]
+pub struct OutputBuffer {
+    pending: AtomicUsize,
+    limit: AtomicUsize,
+    /// Notified once `pending` goes over the limit
+    overflow: Notify,
+}
+
+impl OutputBuffer {
+    /// Accounts for `len` more bytes queued, returning false once the
+    /// connection is over its limit.
+    fn queue(&self, len: usize) -> bool {
+        let pending = self.pending.fetch_add(len, Ordering::Relaxed) + len;
+        if pending > self.limit.load(Ordering::Relaxed) {
+            self.overflow.notify_one();
+            return false;
+        }
+        true
+    }
+
+    pub fn dequeue(&self, len: usize) {
+        self.pending.fetch_sub(len, Ordering::Relaxed);
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Resolves once the connection has gone over its limit.
+    pub async fn overflowed(&self) {
+        self.overflow.notified().await
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SubscriptionKind {
+    Channel,
+    Pattern,
+    /// SSUBSCRIBE channels, which only SPUBLISH reaches
+    ShardChannel,
+}
+
+impl SubscriptionKind {
+    /// The first element of subscribe and unsubscribe confirmations.
+    pub fn confirmation(&self, subscribe: bool) -> &'static str {
+        match (self, subscribe) {
+            (SubscriptionKind::Channel, true) => "subscribe",
+            (SubscriptionKind::Channel, false) => "unsubscribe",
+            (SubscriptionKind::Pattern, true) => "psubscribe",
+            (SubscriptionKind::Pattern, false) => "punsubscribe",
+            (SubscriptionKind::ShardChannel, true) => "ssubscribe",
+            (SubscriptionKind::ShardChannel, false) => "sunsubscribe",
+        }
+    }
+}
+
+/// The server-wide registry of subscribed connections, keyed by channel or
+/// pattern, then by client ID.
+#[derive(Default)]
+pub struct PubSub {
+    channels: HashMap<Bytes, HashMap<u64, Subscriber>>,
+    patterns: HashMap<Bytes, HashMap<u64, Subscriber>>,
+    shard_channels: HashMap<Bytes, HashMap<u64, Subscriber>>,
+}
+
+impl PubSub {
+    pub fn subscribe(&mut self, kind: SubscriptionKind, name: Bytes, client_id: u64, subscriber: Subscriber) {
+        self.registry_mut(kind).entry(name).or_default().insert(client_id, subscriber);
+    }
+
+    pub fn unsubscribe(&mut self, kind: SubscriptionKind, name: &Bytes, client_id: u64) {
+        let registry = self.registry_mut(kind);
+
+        if let Some(subscribers) = registry.get_mut(name) {
+            subscribers.remove(&client_id);
+
+            if subscribers.is_empty() {
+                registry.remove(name);
+            }
+        }
+    }
+
+    /// Delivers `message` to the subscribers of `channel` and of every
+    /// matching pattern, returning how many received it.
+    pub fn publish(&self, channel: &Bytes, message: &Bytes) -> usize {
+        let mut receivers = 0;
+
+        for subscriber in self.channels.get(channel).into_iter().flat_map(|subscribers| subscribers.values()) {
+            receivers += deliver(subscriber, vec![Command::bulk("message"), Command::BulkString(channel.clone()), Command::BulkString(message.clone())]);
+        }
+
+        for (pattern, subscribers) in &self.patterns {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+
+            for subscriber in subscribers.values() {
+                receivers += deliver(subscriber, vec![
+                    Command::bulk("pmessage"),
+                    Command::BulkString(pattern.clone()),
+                    Command::BulkString(channel.clone()),
+                    Command::BulkString(message.clone()),
+                ]);
+            }
+        }
+
+        receivers
+    }
+
+    /// Delivers `message` to the SSUBSCRIBE subscribers of `channel`.
+    pub fn spublish(&self, channel: &Bytes, message: &Bytes) -> usize {
+        self.shard_channels.get(channel).into_iter()
+            .flat_map(|subscribers| subscribers.values())
+            .map(|subscriber| deliver(subscriber, vec![Command::bulk("smessage"), Command::BulkString(channel.clone()), Command::BulkString(message.clone())]))
+            .sum()
+    }
+
+    /// Channels of `kind` with at least one subscriber.
+    pub fn channels(&self, kind: SubscriptionKind) -> impl Iterator<Item=&Bytes> {
+        self.registry(kind).keys()
+    }
+
+    pub fn subscriber_count(&self, kind: SubscriptionKind, name: &[u8]) -> usize {
+        self.registry(kind).get(name).map_or(0, |subscribers| subscribers.len())
+    }
+
+    fn registry(&self, kind: SubscriptionKind) -> &HashMap<Bytes, HashMap<u64, Subscriber>> {
+        match kind {
+            SubscriptionKind::Channel => &self.channels,
+            SubscriptionKind::Pattern => &self.patterns,
+            SubscriptionKind::ShardChannel => &self.shard_channels,
+        }
+    }
+
+    fn registry_mut(&mut self, kind: SubscriptionKind) -> &mut HashMap<Bytes, HashMap<u64, Subscriber>> {
+        match kind {
+            SubscriptionKind::Channel => &mut self.channels,
+            SubscriptionKind::Pattern => &mut self.patterns,
+            SubscriptionKind::ShardChannel => &mut self.shard_channels,
+        }
+    }
+}
+
+/// Queues a push message for a connection task, returning 1 if the task is
+/// still around to receive it and keeping up with its messages.
+fn deliver(subscriber: &Subscriber, message: Vec<Command>) -> usize {
+    subscriber.send(Command::Push(message)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn subscribers_overflow_past_their_limit() {
+        let (subscriber, mut messages) = Subscriber::channel(100);
+        let mut pubsub = PubSub::default();
+        pubsub.subscribe(SubscriptionKind::Channel, Bytes::from("channel"), 1, subscriber.clone());
+
+        let message = Bytes::from(vec![b'x'; 40]);
+        assert_eq!(pubsub.publish(&Bytes::from("channel"), &message), 1);
+        let delivered = messages.recv().await.unwrap();
+        subscriber.output().dequeue(delivered.serialize().len());
+
+        assert_eq!(pubsub.publish(&Bytes::from("channel"), &message), 1);
+        assert_eq!(pubsub.publish(&Bytes::from("channel"), &message), 0);
+        tokio::time::timeout(Duration::from_secs(1), subscriber.output().overflowed()).await.unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use bytes::Bytes;
+use rand::seq::IndexedRandom;
+use rand::thread_rng;
+use std::collections::HashMap;
+
+/// A set of keys that can be sampled at random in time proportional to the
+/// sample: the keys sit in a vector, with a map from each key to its position
+/// so removing one is a `swap_remove`.
+#[derive(Clone, Debug, Default)]
+pub struct SampleSet {
+    keys: Vec<Bytes>,
+    positions: HashMap<Bytes, usize>,
+}
+
+impl SampleSet {
+    pub fn insert(&mut self, key: Bytes) -> bool {
+        if self.positions.contains_key(&key) {
+            return false;
+        }
+
+        self.positions.insert(key.clone(), self.keys.len());
+        self.keys.push(key);
+        true
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        let position = match self.positions.remove(key) {
+            Some(position) => position,
+            None => return false
+        };
+
+        self.keys.swap_remove(position);
+        if let Some(moved) = self.keys.get(position) {
+            *self.positions.get_mut(moved).unwrap() = position;
+        }
+        true
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Bytes> {
+        self.keys.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.positions.clear();
+    }
+
+    pub fn random(&self) -> Option<&Bytes> {
+        self.keys.choose(&mut thread_rng())
+    }
+
+    /// Up to `count` distinct keys picked at random.
+    pub fn sample(&self, count: usize) -> Vec<&Bytes> {
+        self.keys.choose_multiple(&mut thread_rng(), count).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removing_keeps_the_positions_of_the_rest() {
+        let mut set = SampleSet::default();
+        for key in ["a", "b", "c", "d"] {
+            assert!(set.insert(Bytes::from(key)));
+        }
+        assert!(!set.insert(Bytes::from("b")));
+
+        assert!(set.remove(b"a"));
+        assert!(set.remove(b"d"));
+        assert!(!set.remove(b"a"));
+        assert!(set.contains(b"b") && set.contains(b"c"));
+        assert!(set.remove(b"c"));
+
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.random(), Some(&Bytes::from("b")));
+        assert_eq!(set.sample(5), vec![&Bytes::from("b")]);
+    }
+}
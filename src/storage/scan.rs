@@ -0,0 +1,273 @@
+use crate::storage::SampleSet;
+use bytes::Bytes;
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Deref;
+
+/// A hash of `key` that is stable for the lifetime of the process and
+/// independent of any map's bucket layout.
+fn scan_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The keys of a collection ordered by `scan_hash`, for SCAN-style
+/// iteration. The cursor is the hash to resume from, so every element present
+/// for the whole iteration is returned however the collection changes in
+/// between calls, and each call only walks the elements it returns.
+#[derive(Clone, Debug, Default)]
+struct ScanIndex(BTreeSet<(u64, Bytes)>);
+
+impl ScanIndex {
+    fn insert(&mut self, key: Bytes) {
+        self.0.insert((scan_hash(&key), key));
+    }
+
+    fn remove(&mut self, key: Bytes) {
+        self.0.remove(&(scan_hash(&key), key));
+    }
+
+    /// About `count` keys from `cursor` on, and the cursor to continue from
+    /// (`0` when done). Keys sharing a hash are never split across calls.
+    fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&Bytes>) {
+        let mut keys = vec![];
+        let mut last_hash = None;
+        let mut remaining = self.0.range((cursor, Bytes::new())..).peekable();
+
+        while let Some((hash, key)) = remaining.next_if(|(hash, _)| keys.len() < count.max(1) || Some(*hash) == last_hash) {
+            last_hash = Some(*hash);
+            keys.push(key);
+        }
+
+        let next_cursor = match (remaining.peek(), last_hash) {
+            (Some(_), Some(last_hash)) => last_hash + 1,
+            _ => 0
+        };
+
+        (next_cursor, keys)
+    }
+}
+
+/// A hash map that can also be scanned in O(COUNT) and sampled in
+/// O(sample). Reads go through `Deref`; anything that adds or removes keys
+/// has to go through the map itself.
+#[derive(Clone, Debug, Default)]
+pub struct ScanMap<V> {
+    map: HashMap<Bytes, V>,
+    index: ScanIndex,
+    keys: SampleSet,
+}
+
+impl<V> ScanMap<V> {
+    pub fn new() -> Self {
+        ScanMap {
+            map: HashMap::new(),
+            index: ScanIndex::default(),
+            keys: SampleSet::default(),
+        }
+    }
+
+    pub fn insert(&mut self, key: Bytes, value: V) -> Option<V> {
+        if !self.map.contains_key(&key) {
+            self.index.insert(key.clone());
+            self.keys.insert(key.clone());
+        }
+        self.map.insert(key, value)
+    }
+
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<V> where Bytes: Borrow<Q> {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<(Bytes, V)> where Bytes: Borrow<Q> {
+        let (key, value) = self.map.remove_entry(key)?;
+        self.index.remove(key.clone());
+        self.keys.remove(&key);
+        Some((key, value))
+    }
+
+    pub fn get_or_insert_with(&mut self, key: Bytes, default: impl FnOnce() -> V) -> &mut V {
+        if !self.map.contains_key(&key) {
+            self.index.insert(key.clone());
+            self.keys.insert(key.clone());
+        }
+        self.map.entry(key).or_insert_with(default)
+    }
+
+    pub fn get_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V> where Bytes: Borrow<Q> {
+        self.map.get_mut(key)
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.index = ScanIndex::default();
+        self.keys.clear();
+    }
+
+    /// An entry picked at random.
+    pub fn random(&self) -> Option<(&Bytes, &V)> {
+        self.keys.random().map(|key| (key, &self.map[key]))
+    }
+
+    /// Up to `count` distinct entries picked at random.
+    pub fn sample(&self, count: usize) -> Vec<(&Bytes, &V)> {
+        self.keys.sample(count).into_iter().map(|key| (key, &self.map[key])).collect()
+    }
+
+    /// One SCAN step over the entries, see `ScanIndex`.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&Bytes, &V)>) {
+        let (next_cursor, keys) = self.index.scan(cursor, count);
+        (next_cursor, keys.into_iter().map(|key| (key, &self.map[key])).collect())
+    }
+}
+
+impl<V> Deref for ScanMap<V> {
+    type Target = HashMap<Bytes, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+impl<'a, V> IntoIterator for &'a ScanMap<V> {
+    type Item = (&'a Bytes, &'a V);
+    type IntoIter = std::collections::hash_map::Iter<'a, Bytes, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter()
+    }
+}
+
+impl<V> FromIterator<(Bytes, V)> for ScanMap<V> {
+    fn from_iter<I: IntoIterator<Item=(Bytes, V)>>(entries: I) -> Self {
+        let mut map = ScanMap::new();
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+/// A set that can be scanned in O(COUNT), like `ScanMap`, and sampled in
+/// O(sample) like a `SampleSet`.
+#[derive(Clone, Debug, Default)]
+pub struct ScanSet {
+    set: SampleSet,
+    index: ScanIndex,
+}
+
+impl ScanSet {
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if !self.set.insert(member.clone()) {
+            return false;
+        }
+        self.index.insert(member);
+        true
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        if !self.set.remove(member) {
+            return false;
+        }
+        self.index.remove(Bytes::copy_from_slice(member));
+        true
+    }
+
+    /// One SCAN step over the members, see `ScanIndex`.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&Bytes>) {
+        self.index.scan(cursor, count)
+    }
+}
+
+impl Deref for ScanSet {
+    type Target = SampleSet;
+
+    fn deref(&self) -> &Self::Target {
+        &self.set
+    }
+}
+
+impl FromIterator<Bytes> for ScanSet {
+    fn from_iter<I: IntoIterator<Item=Bytes>>(members: I) -> Self {
+        let mut set = ScanSet::default();
+        for member in members {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan_all<V>(map: &ScanMap<V>, count: usize) -> (Vec<Bytes>, usize) {
+        let (mut cursor, mut keys, mut calls) = (0, vec![], 0);
+        loop {
+            let (next_cursor, entries) = map.scan(cursor, count);
+            assert!(entries.len() <= count, "a call returned more than COUNT without hash collisions");
+            keys.extend(entries.into_iter().map(|(key, _)| key.clone()));
+            calls += 1;
+            if next_cursor == 0 {
+                return (keys, calls);
+            }
+            cursor = next_cursor;
+        }
+    }
+
+    #[test]
+    fn returns_every_key_once_in_count_sized_steps() {
+        let map: ScanMap<usize> = (0..1000).map(|i| (Bytes::from(format!("key:{}", i)), i)).collect();
+
+        let (mut keys, calls) = scan_all(&map, 10);
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 1000);
+        assert_eq!(calls, 100);
+    }
+
+    #[test]
+    fn keys_present_throughout_survive_changes_between_calls() {
+        let mut map: ScanMap<()> = (0..100).map(|i| (Bytes::from(format!("key:{}", i)), ())).collect();
+
+        let (cursor, first) = map.scan(0, 50);
+        let first: Vec<Bytes> = first.into_iter().map(|(key, _)| key.clone()).collect();
+        for i in 0..10 {
+            map.remove(format!("key:{}", i).as_bytes());
+        }
+        for i in 100..1000 {
+            map.insert(Bytes::from(format!("key:{}", i)), ());
+        }
+
+        let mut seen = first;
+        let mut cursor = cursor;
+        while cursor != 0 {
+            let (next_cursor, entries) = map.scan(cursor, 50);
+            seen.extend(entries.into_iter().map(|(key, _)| key.clone()));
+            cursor = next_cursor;
+        }
+
+        for i in 10..100 {
+            assert!(seen.contains(&Bytes::from(format!("key:{}", i))));
+        }
+    }
+
+    #[test]
+    fn removed_members_leave_the_index() {
+        let mut set: ScanSet = ["a", "b", "c"].into_iter().map(Bytes::from).collect();
+        assert!(set.remove(b"b"));
+        assert!(!set.remove(b"b"));
+        assert!(!set.insert(Bytes::from("a")));
+
+        let (cursor, mut members) = set.scan(0, 10);
+        members.sort();
+        assert_eq!(cursor, 0);
+        assert_eq!(members, vec![&Bytes::from("a"), &Bytes::from("c")]);
+
+        let mut sample = set.sample(10);
+        sample.sort();
+        assert_eq!(sample, vec![&Bytes::from("a"), &Bytes::from("c")]);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::util::parse_i64;
+use bytes::Bytes;
+use crate::storage::ScanSet;
+use rand::seq::IndexedRandom;
+use rand::thread_rng;
+
+/// All-integer sets up to this size stay intsets (`set-max-intset-entries`).
+const INTSET_MAX_ENTRIES: usize = 512;
+
+/// A set of members, kept as a sorted vector of integers while every member
+/// is one, like Redis's intset, and as a hash set otherwise.
+#[derive(Clone, Debug)]
+pub enum SetValue {
+    Intset(Vec<i64>),
+    Hashtable(ScanSet),
+}
+
+impl SetValue {
+    pub fn new() -> Self {
+        SetValue::Intset(vec![])
+    }
+
+    /// A set holding `members`, in the most compact encoding that fits them.
+    pub fn from_members(members: impl IntoIterator<Item=Bytes>) -> Self {
+        let mut set = SetValue::new();
+        for member in members {
+            set.insert(member);
+        }
+
+        set
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            SetValue::Intset(ints) => ints.len(),
+            SetValue::Hashtable(members) => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            SetValue::Intset(ints) => parse_i64(member).is_some_and(|int| ints.binary_search(&int).is_ok()),
+            SetValue::Hashtable(members) => members.contains(member),
+        }
+    }
+
+    /// Adds `member`, returning whether it was new. The set is converted to a
+    /// hash set once a non-integer member shows up or it grows too large.
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if let SetValue::Intset(ints) = self {
+            match parse_i64(&member) {
+                Some(int) => match ints.binary_search(&int) {
+                    Ok(_) => return false,
+                    Err(_) if ints.len() >= INTSET_MAX_ENTRIES => self.convert_to_hashtable(),
+                    Err(index) => {
+                        ints.insert(index, int);
+                        return true;
+                    }
+                },
+                None => self.convert_to_hashtable()
+            }
+        }
+
+        match self {
+            SetValue::Hashtable(members) => members.insert(member),
+            SetValue::Intset(_) => unreachable!(),
+        }
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            SetValue::Intset(ints) => match parse_i64(member).map(|int| ints.binary_search(&int)) {
+                Some(Ok(index)) => {
+                    ints.remove(index);
+                    true
+                }
+                _ => false
+            },
+            SetValue::Hashtable(members) => members.remove(member),
+        }
+    }
+
+    /// The members in the set's iteration order, ascending for intsets.
+    pub fn members(&self) -> Vec<Bytes> {
+        match self {
+            SetValue::Intset(ints) => ints.iter().map(|int| Bytes::from(int.to_string())).collect(),
+            SetValue::Hashtable(members) => members.iter().cloned().collect(),
+        }
+    }
+
+    /// A member picked at random.
+    pub fn random(&self) -> Option<Bytes> {
+        match self {
+            SetValue::Intset(ints) => ints.choose(&mut thread_rng()).map(|int| Bytes::from(int.to_string())),
+            SetValue::Hashtable(members) => members.random().cloned(),
+        }
+    }
+
+    /// Up to `count` distinct members picked at random, in time proportional
+    /// to the sample rather than to the set.
+    pub fn sample(&self, count: usize) -> Vec<Bytes> {
+        match self {
+            SetValue::Intset(ints) => ints.choose_multiple(&mut thread_rng(), count).map(|int| Bytes::from(int.to_string())).collect(),
+            SetValue::Hashtable(members) => members.sample(count).into_iter().cloned().collect(),
+        }
+    }
+
+    /// One SCAN step over the members. An intset is small enough to be
+    /// returned whole, as Redis does for its compact encodings.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        match self {
+            SetValue::Intset(_) => (0, self.members()),
+            SetValue::Hashtable(members) => {
+                let (next_cursor, members) = members.scan(cursor, count);
+                (next_cursor, members.into_iter().cloned().collect())
+            }
+        }
+    }
+
+    fn convert_to_hashtable(&mut self) {
+        if let SetValue::Intset(ints) = self {
+            *self = SetValue::Hashtable(ints.iter().map(|int| Bytes::from(int.to_string())).collect());
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use bytes::Bytes;
+use rand::{thread_rng, Rng};
+use std::cmp::Ordering;
+
+/// Same limits as Redis's `ZSKIPLIST_MAXLEVEL` and `ZSKIPLIST_P`.
+const MAX_LEVEL: usize = 32;
+const LEVEL_PROBABILITY: f64 = 0.25;
+
+const HEAD: usize = 0;
+const NIL: usize = usize::MAX;
+
+/// An end of a score range, as given to ZRANGE BYSCORE and ZCOUNT.
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreBound {
+    pub value: f64,
+    pub exclusive: bool,
+}
+
+impl ScoreBound {
+    /// Whether `score` satisfies this bound as the minimum of a range.
+    pub fn fits_min(&self, score: f64) -> bool {
+        if self.exclusive { self.value < score } else { self.value <= score }
+    }
+
+    /// Whether `score` satisfies this bound as the maximum of a range.
+    pub fn fits_max(&self, score: f64) -> bool {
+        if self.exclusive { score < self.value } else { score <= self.value }
+    }
+}
+
+/// An end of a lexicographic range, as given to ZRANGE BYLEX and ZLEXCOUNT.
+#[derive(Clone, Debug)]
+pub enum LexBound {
+    /// `-`, before every member
+    Min,
+    /// `+`, after every member
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+impl LexBound {
+    pub fn fits_min(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(bound) => bound.as_ref() <= member,
+            LexBound::Exclusive(bound) => bound.as_ref() < member,
+        }
+    }
+
+    pub fn fits_max(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(bound) => member <= bound.as_ref(),
+            LexBound::Exclusive(bound) => member < bound.as_ref(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    forward: usize,
+    /// How many nodes `forward` skips over, which makes ranks O(log n)
+    span: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: usize,
+    levels: Vec<Level>,
+}
+
+/// An indexable skiplist ordered by (score, member), modelled on Redis's
+/// `zskiplist`. Nodes live in an arena and link to each other by index;
+/// ranks are 0-based.
+#[derive(Clone, Debug)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: usize,
+    level: usize,
+    len: usize,
+}
+
+impl SkipList {
+    pub fn new() -> Self {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: NIL,
+            levels: vec![Level { forward: NIL, span: 0 }; MAX_LEVEL],
+        };
+
+        SkipList {
+            nodes: vec![head],
+            free: vec![],
+            tail: NIL,
+            level: 1,
+            len: 0,
+        }
+    }
+
+    /// Inserts an element that must not already be in the list.
+    pub fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            loop {
+                let next = self.nodes[x].levels[i].forward;
+                if next == NIL || self.cmp(next, score, &member) != Ordering::Less {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = self.alloc(Node {
+            member,
+            score,
+            backward: if update[0] == HEAD { NIL } else { update[0] },
+            levels: vec![Level { forward: NIL, span: 0 }; level],
+        });
+
+        for i in 0..level {
+            let prev = self.nodes[update[i]].levels[i];
+            self.nodes[node].levels[i] = Level {
+                forward: prev.forward,
+                span: prev.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: node,
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+
+        match self.nodes[node].levels[0].forward {
+            NIL => self.tail = node,
+            next => self.nodes[next].backward = node,
+        }
+        self.len += 1;
+    }
+
+    /// Removes the element, returning whether it was found.
+    pub fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.nodes[x].levels[i].forward;
+                if next == NIL || self.cmp(next, score, member) != Ordering::Less {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let x = self.nodes[x].levels[0].forward;
+        if x == NIL || self.cmp(x, score, member) != Ordering::Equal {
+            return false;
+        }
+
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            if self.nodes[prev].levels[i].forward == x {
+                let removed = self.nodes[x].levels[i];
+                self.nodes[prev].levels[i] = Level {
+                    forward: removed.forward,
+                    span: self.nodes[prev].levels[i].span + removed.span - 1,
+                };
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+
+        match self.nodes[x].levels[0].forward {
+            NIL => self.tail = self.nodes[x].backward,
+            next => self.nodes[next].backward = self.nodes[x].backward,
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward == NIL {
+            self.level -= 1;
+        }
+
+        self.nodes[x].member = Bytes::new();
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    /// The 0-based rank of the element, if present.
+    pub fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.nodes[x].levels[i].forward;
+                if next == NIL || self.cmp(next, score, member) == Ordering::Greater {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+
+            if x != HEAD && self.nodes[x].member.as_ref() == member {
+                return Some(rank - 1);
+            }
+        }
+
+        None
+    }
+
+    /// The element at the 0-based `rank`, as a cursor for walking from it.
+    pub fn by_rank(&self, rank: usize) -> Option<Cursor<'_>> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let level = self.nodes[x].levels[i];
+                if level.forward == NIL || traversed + level.span > target {
+                    break;
+                }
+                traversed += level.span;
+                x = level.forward;
+            }
+
+            if traversed == target {
+                return Some(Cursor { list: self, node: x });
+            }
+        }
+
+        None
+    }
+
+    /// The lowest element whose score is within `min..max`.
+    pub fn first_in_score_range(&self, min: &ScoreBound, max: &ScoreBound) -> Option<Cursor<'_>> {
+        let x = self.last_before(|node| min.fits_min(node.score));
+        self.cursor(self.nodes[x].levels[0].forward)
+            .filter(|cursor| max.fits_max(cursor.score()))
+    }
+
+    /// The highest element whose score is within `min..max`.
+    pub fn last_in_score_range(&self, min: &ScoreBound, max: &ScoreBound) -> Option<Cursor<'_>> {
+        let x = self.last_before(|node| !max.fits_max(node.score));
+        self.cursor(x).filter(|cursor| min.fits_min(cursor.score()))
+    }
+
+    /// The lowest element whose member is within `min..max`. Only meaningful
+    /// when every element has the same score.
+    pub fn first_in_lex_range(&self, min: &LexBound, max: &LexBound) -> Option<Cursor<'_>> {
+        let x = self.last_before(|node| min.fits_min(&node.member));
+        self.cursor(self.nodes[x].levels[0].forward)
+            .filter(|cursor| max.fits_max(cursor.member()))
+    }
+
+    /// The highest element whose member is within `min..max`.
+    pub fn last_in_lex_range(&self, min: &LexBound, max: &LexBound) -> Option<Cursor<'_>> {
+        let x = self.last_before(|node| !max.fits_max(&node.member));
+        self.cursor(x).filter(|cursor| min.fits_min(cursor.member()))
+    }
+
+    pub fn first(&self) -> Option<Cursor<'_>> {
+        self.cursor(self.nodes[HEAD].levels[0].forward)
+    }
+
+    pub fn last(&self) -> Option<Cursor<'_>> {
+        self.cursor(self.tail)
+    }
+
+    /// The last node (possibly the head) for which `reached` is still false,
+    /// given that `reached` flips from false to true exactly once along the
+    /// list.
+    fn last_before(&self, reached: impl Fn(&Node) -> bool) -> usize {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.nodes[x].levels[i].forward;
+                if next == NIL || reached(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+
+        x
+    }
+
+    fn cursor(&self, node: usize) -> Option<Cursor<'_>> {
+        match node {
+            NIL | HEAD => None,
+            node => Some(Cursor { list: self, node }),
+        }
+    }
+
+    fn cmp(&self, node: usize, score: f64, member: &[u8]) -> Ordering {
+        let node = &self.nodes[node];
+        node.score.total_cmp(&score).then_with(|| node.member.as_ref().cmp(member))
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+}
+
+/// A position in a `SkipList`, which can step in either direction.
+#[derive(Clone, Copy)]
+pub struct Cursor<'a> {
+    list: &'a SkipList,
+    node: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn score(&self) -> f64 {
+        self.list.nodes[self.node].score
+    }
+
+    pub fn member(&self) -> &'a Bytes {
+        &self.list.nodes[self.node].member
+    }
+
+    pub fn next(&self) -> Option<Cursor<'a>> {
+        self.list.cursor(self.list.nodes[self.node].levels[0].forward)
+    }
+
+    pub fn prev(&self) -> Option<Cursor<'a>> {
+        self.list.cursor(self.list.nodes[self.node].backward)
+    }
+
+    /// The 0-based rank of this element.
+    pub fn rank(&self) -> usize {
+        self.list.rank(self.score(), self.member()).unwrap()
+    }
+}
+
+fn random_level() -> usize {
+    let mut level = 1;
+    while level < MAX_LEVEL && thread_rng().random::<f64>() < LEVEL_PROBABILITY {
+        level += 1;
+    }
+
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Random inserts and removes, checked against a sorted `Vec` after each.
+    fn random_ops(list: &mut SkipList, model: &mut Vec<(f64, Bytes)>, ops: usize, score: impl Fn(u32) -> f64) {
+        let mut rng = thread_rng();
+        for _ in 0..ops {
+            let member = Bytes::from(format!("m{:03}", rng.gen_range(0..200)));
+            let existing = model.iter().position(|(_, other)| *other == member);
+
+            if let Some(index) = existing {
+                let (old_score, _) = model.remove(index);
+                assert!(list.remove(old_score, &member));
+                assert!(!list.remove(old_score, &member));
+            }
+            if existing.is_none() || rng.gen_bool(0.5) {
+                let score = score(rng.gen_range(0..20));
+                list.insert(score, member.clone());
+                model.push((score, member));
+                model.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+            }
+
+            assert_eq!(list.len, model.len());
+        }
+    }
+
+    fn check_order(list: &SkipList, model: &[(f64, Bytes)]) {
+        for (rank, (score, member)) in model.iter().enumerate() {
+            assert_eq!(list.rank(*score, member), Some(rank));
+            let cursor = list.by_rank(rank).unwrap();
+            assert_eq!((cursor.score(), cursor.member()), (*score, member));
+            assert_eq!(cursor.rank(), rank);
+        }
+        assert!(list.by_rank(model.len()).is_none());
+        assert!(list.rank(-1.0, b"m000").is_none());
+
+        let forward: Vec<_> = std::iter::successors(list.first(), Cursor::next).map(|c| (c.score(), c.member().clone())).collect();
+        let mut backward: Vec<_> = std::iter::successors(list.last(), Cursor::prev).map(|c| (c.score(), c.member().clone())).collect();
+        backward.reverse();
+        assert_eq!(forward, model);
+        assert_eq!(backward, model);
+    }
+
+    #[test]
+    fn score_order_and_ranges_match_a_sorted_vec() {
+        let (mut list, mut model) = (SkipList::new(), vec![]);
+        let mut rng = thread_rng();
+
+        for _ in 0..20 {
+            random_ops(&mut list, &mut model, 50, |n| n as f64);
+            check_order(&list, &model);
+
+            for _ in 0..20 {
+                let min = ScoreBound { value: rng.gen_range(-1..21) as f64, exclusive: rng.gen_bool(0.5) };
+                let max = ScoreBound { value: rng.gen_range(-1..21) as f64, exclusive: rng.gen_bool(0.5) };
+                let in_range: Vec<_> = model.iter().filter(|(score, _)| min.fits_min(*score) && max.fits_max(*score)).collect();
+
+                let first = list.first_in_score_range(&min, &max).map(|c| (c.score(), c.member().clone()));
+                let last = list.last_in_score_range(&min, &max).map(|c| (c.score(), c.member().clone()));
+                assert_eq!(first.as_ref(), in_range.first().copied());
+                assert_eq!(last.as_ref(), in_range.last().copied());
+            }
+        }
+    }
+
+    #[test]
+    fn lex_ranges_match_a_sorted_vec() {
+        let (mut list, mut model) = (SkipList::new(), vec![]);
+        let mut rng = thread_rng();
+        let bound = |rng: &mut rand::rngs::ThreadRng| {
+            let member = Bytes::from(format!("m{:03}", rng.gen_range(0..200)));
+            match rng.gen_range(0..4) {
+                0 => LexBound::Min,
+                1 => LexBound::Max,
+                2 => LexBound::Inclusive(member),
+                _ => LexBound::Exclusive(member),
+            }
+        };
+
+        for _ in 0..20 {
+            random_ops(&mut list, &mut model, 50, |_| 0.0);
+            check_order(&list, &model);
+
+            for _ in 0..20 {
+                let (min, max) = (bound(&mut rng), bound(&mut rng));
+                let in_range: Vec<_> = model.iter().filter(|(_, member)| min.fits_min(member) && max.fits_max(member)).collect();
+
+                let first = list.first_in_lex_range(&min, &max).map(|c| (c.score(), c.member().clone()));
+                let last = list.last_in_lex_range(&min, &max).map(|c| (c.score(), c.member().clone()));
+                assert_eq!(first.as_ref(), in_range.first().copied());
+                assert_eq!(last.as_ref(), in_range.last().copied());
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::storage::skiplist::{Cursor, LexBound, ScoreBound, SkipList};
+use crate::storage::ScanMap;
+use bytes::Bytes;
+
+/// A sorted set: a dictionary for O(1) score lookups next to a skiplist that
+/// keeps members ordered by score, then member, for rank and range queries.
+#[derive(Clone, Debug)]
+pub struct SortedSet {
+    scores: ScanMap<f64>,
+    list: SkipList,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        SortedSet {
+            scores: ScanMap::new(),
+            list: SkipList::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// One SCAN step over the members and their scores.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&Bytes, f64)>) {
+        let (next_cursor, members) = self.scores.scan(cursor, count);
+        (next_cursor, members.into_iter().map(|(member, score)| (member, *score)).collect())
+    }
+
+    /// Members and scores in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item=(&Bytes, f64)> {
+        self.scores.iter().map(|(member, score)| (member, *score))
+    }
+
+    /// Sets the score of `member`, returning whether it was added rather than
+    /// updated.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        // -0.0 and 0.0 must not order differently
+        let score = score + 0.0;
+
+        match self.scores.insert(member.clone(), score) {
+            Some(previous) if previous == score => false,
+            Some(previous) => {
+                self.list.remove(previous, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false
+        }
+    }
+
+    /// The 0-based rank of `member`, counted from the highest score when
+    /// `reverse` is set.
+    pub fn rank(&self, member: &[u8], reverse: bool) -> Option<usize> {
+        let rank = self.list.rank(self.score(member)?, member)?;
+
+        Some(if reverse { self.len() - 1 - rank } else { rank })
+    }
+
+    /// Elements with ranks `start..=stop`, which must be in bounds.
+    pub fn range_by_rank(&self, start: usize, stop: usize, reverse: bool) -> Vec<(Bytes, f64)> {
+        let count = stop + 1 - start;
+        let first = if reverse { self.len() - 1 - start } else { start };
+
+        collect(self.list.by_rank(first), reverse, 0, count)
+    }
+
+    /// Elements with scores within `min..max`, skipping `offset` of them and
+    /// returning at most `count`.
+    pub fn range_by_score(&self, min: &ScoreBound, max: &ScoreBound, reverse: bool, offset: usize, count: usize) -> Vec<(Bytes, f64)> {
+        let first = if reverse {
+            self.list.last_in_score_range(min, max)
+        } else {
+            self.list.first_in_score_range(min, max)
+        };
+
+        let in_range = |cursor: &Cursor| if reverse { min.fits_min(cursor.score()) } else { max.fits_max(cursor.score()) };
+        collect_while(first, reverse, offset, count, in_range)
+    }
+
+    /// Elements with members within `min..max`; see `SkipList::first_in_lex_range`.
+    pub fn range_by_lex(&self, min: &LexBound, max: &LexBound, reverse: bool, offset: usize, count: usize) -> Vec<(Bytes, f64)> {
+        let first = if reverse {
+            self.list.last_in_lex_range(min, max)
+        } else {
+            self.list.first_in_lex_range(min, max)
+        };
+
+        let in_range = |cursor: &Cursor| if reverse { min.fits_min(cursor.member()) } else { max.fits_max(cursor.member()) };
+        collect_while(first, reverse, offset, count, in_range)
+    }
+
+    /// How many scores fall within `min..max`, in O(log n).
+    pub fn count_by_score(&self, min: &ScoreBound, max: &ScoreBound) -> usize {
+        match (self.list.first_in_score_range(min, max), self.list.last_in_score_range(min, max)) {
+            (Some(first), Some(last)) => last.rank() - first.rank() + 1,
+            _ => 0
+        }
+    }
+
+    pub fn count_by_lex(&self, min: &LexBound, max: &LexBound) -> usize {
+        match (self.list.first_in_lex_range(min, max), self.list.last_in_lex_range(min, max)) {
+            (Some(first), Some(last)) => last.rank() - first.rank() + 1,
+            _ => 0
+        }
+    }
+
+    /// Removes and returns up to `count` elements from the low end, or the
+    /// high end when `reverse` is set.
+    pub fn pop(&mut self, count: usize, reverse: bool) -> Vec<(Bytes, f64)> {
+        let first = if reverse { self.list.last() } else { self.list.first() };
+        let popped = collect(first, reverse, 0, count);
+
+        for (member, _) in &popped {
+            self.remove(member);
+        }
+
+        popped
+    }
+}
+
+fn collect(first: Option<Cursor>, reverse: bool, offset: usize, count: usize) -> Vec<(Bytes, f64)> {
+    collect_while(first, reverse, offset, count, |_| true)
+}
+
+/// Walks from `first` in the requested direction while `in_range` holds.
+fn collect_while(first: Option<Cursor>, reverse: bool, offset: usize, count: usize, in_range: impl Fn(&Cursor) -> bool) -> Vec<(Bytes, f64)> {
+    let mut elements = vec![];
+    let mut cursor = first;
+    let mut skipped = 0;
+
+    while let Some(current) = cursor.filter(|cursor| in_range(cursor)) {
+        if elements.len() >= count {
+            break;
+        }
+        if skipped < offset {
+            skipped += 1;
+        } else {
+            elements.push((current.member().clone(), current.score()));
+        }
+
+        cursor = if reverse { current.prev() } else { current.next() };
+    }
+
+    elements
+}
@@ -0,0 +1,255 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Bound;
+
+/// A stream entry ID: a millisecond timestamp and a sequence number within it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    /// Parses `ms-seq`, or a bare `ms` whose sequence is `missing_seq`.
+    pub fn parse(value: &[u8], missing_seq: u64) -> Option<Self> {
+        let value = std::str::from_utf8(value).ok()?;
+
+        match value.split_once('-') {
+            Some((ms, seq)) => Some(StreamId::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(StreamId::new(value.parse().ok()?, missing_seq)),
+        }
+    }
+
+    pub fn next(&self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    pub fn prev(&self) -> Option<Self> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+pub type StreamFields = Vec<(Bytes, Bytes)>;
+
+/// An entry delivered to a consumer but not acknowledged yet.
+#[derive(Clone, Debug)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    pub delivery_time: i64,
+    pub delivery_count: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Consumer {
+    /// Last time the consumer attempted an interaction
+    pub seen_time: i64,
+    /// Last time the consumer actually read or claimed something, if ever
+    pub active_time: Option<i64>,
+    pub pending: BTreeSet<StreamId>,
+}
+
+impl Consumer {
+    pub fn new(now: i64) -> Self {
+        Consumer {
+            seen_time: now,
+            active_time: None,
+            pending: BTreeSet::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    /// How many entries the group has read, when that can be known exactly
+    pub entries_read: Option<u64>,
+    /// The group's pending entries list (PEL)
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup {
+            last_delivered,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    /// The consumer called `name`, created on first use.
+    pub fn consumer(&mut self, name: &Bytes, now: i64) -> &mut Consumer {
+        self.consumers.entry(name.clone()).or_insert_with(|| Consumer::new(now))
+    }
+
+    /// Drops `id` from the PEL and from its consumer's list, returning
+    /// whether it was pending.
+    pub fn acknowledge(&mut self, id: &StreamId) -> bool {
+        match self.pending.remove(id) {
+            Some(entry) => {
+                if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+                    consumer.pending.remove(id);
+                }
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Records `id` as delivered to `consumer`, taking it over from whichever
+    /// consumer had it before.
+    pub fn assign(&mut self, id: StreamId, consumer: &Bytes, now: i64, delivery_count: u64) {
+        self.acknowledge(&id);
+        self.consumer(consumer, now).pending.insert(id);
+        self.pending.insert(id, PendingEntry {
+            consumer: consumer.clone(),
+            delivery_time: now,
+            delivery_count,
+        });
+    }
+}
+
+/// An append-only log of field/value entries ordered by ID, plus the
+/// consumer groups reading from it.
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    pub entries: BTreeMap<StreamId, StreamFields>,
+    pub last_id: StreamId,
+    /// The highest ID removed by XDEL, which makes group lag unknowable
+    pub max_deleted_id: StreamId,
+    /// Every entry ever added, including deleted and trimmed ones
+    pub entries_added: u64,
+    pub groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Stream::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn first_id(&self) -> Option<StreamId> {
+        self.entries.keys().next().copied()
+    }
+
+    /// The ID XADD assigns for `*`: the current time, or the next sequence
+    /// after the last ID if the clock has not moved past it.
+    pub fn next_id(&self, now: u64) -> Option<StreamId> {
+        if now > self.last_id.ms {
+            Some(StreamId::new(now, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    pub fn append(&mut self, id: StreamId, fields: StreamFields) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn delete(&mut self, id: &StreamId) -> bool {
+        if self.entries.remove(id).is_none() {
+            return false;
+        }
+
+        self.max_deleted_id = self.max_deleted_id.max(*id);
+        true
+    }
+
+    /// Entries with IDs within `start..=end`, newest first when `reverse` is
+    /// set, at most `count` of them.
+    pub fn range(&self, start: StreamId, end: StreamId, count: usize, reverse: bool) -> Vec<(StreamId, &StreamFields)> {
+        if start > end {
+            return vec![];
+        }
+
+        let range = self.entries.range(start..=end).map(|(id, fields)| (*id, fields));
+        if reverse {
+            range.rev().take(count).collect()
+        } else {
+            range.take(count).collect()
+        }
+    }
+
+    /// Entries with IDs strictly greater than `after`.
+    pub fn after(&self, after: StreamId, count: usize) -> Vec<(StreamId, &StreamFields)> {
+        self.entries.range((Bound::Excluded(after), Bound::Unbounded))
+            .take(count)
+            .map(|(id, fields)| (*id, fields))
+            .collect()
+    }
+
+    /// Removes the oldest entries until at most `max_len` remain, deleting no
+    /// more than `limit`. Returns how many were removed.
+    pub fn trim_to_len(&mut self, max_len: usize, limit: usize) -> usize {
+        let mut removed = 0;
+        while self.entries.len() > max_len && removed < limit {
+            self.entries.pop_first();
+            removed += 1;
+        }
+
+        removed
+    }
+
+    /// Removes entries with IDs below `min_id`, deleting no more than `limit`.
+    pub fn trim_to_min_id(&mut self, min_id: StreamId, limit: usize) -> usize {
+        let mut removed = 0;
+        while self.first_id().is_some_and(|id| id < min_id) && removed < limit {
+            self.entries.pop_first();
+            removed += 1;
+        }
+
+        removed
+    }
+
+    /// How many entries come before and including `id` over the stream's
+    /// whole history, when deletions don't make that ambiguous.
+    pub fn estimate_entries_read(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 || id >= self.last_id {
+            return Some(self.entries_added);
+        }
+
+        let first = self.first_id()?;
+        let has_tombstones = self.max_deleted_id >= first;
+        if !has_tombstones && id < first {
+            return Some(self.entries_added - self.len() as u64);
+        }
+
+        None
+    }
+
+    /// How many entries `group` has yet to read, if that can be known.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        let read = match group.entries_read {
+            Some(read) if self.max_deleted_id < group.last_delivered || self.max_deleted_id == StreamId::MIN => Some(read),
+            _ => self.estimate_entries_read(group.last_delivered)
+        };
+
+        read.map(|read| self.entries_added.saturating_sub(read))
+    }
+}
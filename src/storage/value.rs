@@ -0,0 +1,169 @@
+use crate::error::CommandError;
+use crate::storage::{HashValue, SetValue, SortedSet, Stream};
+use crate::util::parse_i64;
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+/// Collections up to this many elements report the compact `listpack`
+/// encoding, mirroring Redis's `*-max-listpack-entries` defaults.
+const LISTPACK_MAX_ENTRIES: usize = 128;
+/// ...as long as no element is longer than this (`*-max-listpack-value`).
+const LISTPACK_MAX_VALUE: usize = 64;
+/// Strings up to this length are `embstr` rather than `raw`.
+const EMBSTR_MAX_LENGTH: usize = 44;
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(HashValue),
+    Set(SetValue),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// The name TYPE reports for the value
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// The name OBJECT ENCODING reports, following the thresholds Redis uses
+    /// to switch between compact and full representations.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(s) if s.len() <= 20 && parse_i64(s).is_some() => "int",
+            Value::String(s) if s.len() <= EMBSTR_MAX_LENGTH => "embstr",
+            Value::String(_) => "raw",
+            Value::List(list) if is_compact(list.len(), list.iter()) => "listpack",
+            Value::List(_) => "quicklist",
+            Value::Hash(hash) if is_compact(hash.len(), hash.iter().flat_map(|(k, v)| [k, &v.value])) => {
+                if self.has_volatile_fields() { "listpackex" } else { "listpack" }
+            }
+            Value::Hash(_) => "hashtable",
+            Value::Set(SetValue::Intset(_)) => "intset",
+            Value::Set(SetValue::Hashtable(set)) if is_compact(set.len(), set.iter()) => "listpack",
+            Value::Set(_) => "hashtable",
+            Value::SortedSet(zset) if is_compact(zset.len(), zset.iter().map(|(member, _)| member)) => "listpack",
+            Value::SortedSet(_) => "skiplist",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    pub fn as_string(&self) -> Result<&Bytes, CommandError> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_string_mut(&mut self) -> Result<&mut Bytes, CommandError> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&VecDeque<Bytes>, CommandError> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Result<&mut VecDeque<Bytes>, CommandError> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_hash(&self) -> Result<&HashValue, CommandError> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Result<&mut HashValue, CommandError> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_set(&self) -> Result<&SetValue, CommandError> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Result<&mut SetValue, CommandError> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_sorted_set(&self) -> Result<&SortedSet, CommandError> {
+        match self {
+            Value::SortedSet(zset) => Ok(zset),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_sorted_set_mut(&mut self) -> Result<&mut SortedSet, CommandError> {
+        match self {
+            Value::SortedSet(zset) => Ok(zset),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_stream(&self) -> Result<&Stream, CommandError> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    pub fn as_stream_mut(&mut self) -> Result<&mut Stream, CommandError> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(CommandError::WrongType)
+        }
+    }
+
+    /// Whether this is a hash with at least one field carrying a TTL.
+    pub fn has_volatile_fields(&self) -> bool {
+        match self {
+            Value::Hash(hash) => hash.has_volatile_fields(),
+            _ => false
+        }
+    }
+
+    /// Whether the value is a collection with no elements left. Redis never
+    /// keeps empty collections around, so such keys are deleted.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::String(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::SortedSet(zset) => zset.is_empty(),
+            // Streams outlive their entries, as they also carry groups and IDs
+            Value::Stream(_) => false,
+        }
+    }
+}
+
+fn is_compact<'a>(len: usize, mut elements: impl Iterator<Item=&'a Bytes>) -> bool {
+    len <= LISTPACK_MAX_ENTRIES && elements.all(|element| element.len() <= LISTPACK_MAX_VALUE)
+}
@@ -0,0 +1,62 @@
+use crate::storage::Storage;
+use bytes::Bytes;
+use std::collections::HashMap;
+
+/// Versions of the keys some connection WATCHes. A key is only tracked while
+/// it has watchers, so unwatched writes cost a single lookup.
+#[derive(Default)]
+pub struct WatchedKeys {
+    keys: HashMap<Bytes, WatchedKey>,
+}
+
+struct WatchedKey {
+    watchers: usize,
+    version: u64,
+}
+
+impl Storage {
+    /// Starts tracking `key` for one more watcher, returning its current
+    /// version. Keys that have already expired are reclaimed first.
+    pub fn watch(&mut self, key: &Bytes) -> u64 {
+        self.get(key);
+
+        let watched = self.watched.keys.entry(key.clone()).or_insert(WatchedKey { watchers: 0, version: 0 });
+        watched.watchers += 1;
+        watched.version
+    }
+
+    pub fn unwatch(&mut self, key: &[u8]) {
+        if let Some(watched) = self.watched.keys.get_mut(key) {
+            watched.watchers -= 1;
+
+            if watched.watchers == 0 {
+                self.watched.keys.remove(key);
+            }
+        }
+    }
+
+    /// The version of a watched key. A key whose TTL passed since it was
+    /// watched is expired here, which counts as a change.
+    pub fn key_version(&mut self, key: &[u8]) -> u64 {
+        self.get(key);
+
+        self.watched.keys.get(key).map_or(0, |watched| watched.version)
+    }
+
+    /// Records a change to `key`, failing the EXEC of every connection that
+    /// watches it.
+    pub fn touch(&mut self, key: &[u8]) {
+        if let Some(watched) = self.watched.keys.get_mut(key) {
+            watched.version += 1;
+        }
+    }
+
+    /// Touches every watched key that currently exists, e.g. before a flush.
+    pub fn touch_all(&mut self) {
+        for (key, watched) in self.watched.keys.iter_mut() {
+            if self._set.contains_key(key) {
+                watched.version += 1;
+            }
+        }
+    }
+}
@@ -1,10 +1,130 @@
+use chrono::Utc;
 use rand::{thread_rng, Rng};
 use rand::distr::Alphanumeric;
 
 pub fn generate_random_string(size: i32) -> String {
     thread_rng()
         .sample_iter(&Alphanumeric)
-        .take(usize::try_from(size).unwrap_or_else(|_| 40))
+        .take(usize::try_from(size).unwrap_or(40))
         .map(char::from)
         .collect()
-}
\ No newline at end of file
+}
+/// Milliseconds since the Unix epoch, the unit `StorageRecord::expires_at` uses.
+pub fn now_millis() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+/// Glob-style matching as used by KEYS, SCAN MATCH and PSUBSCRIBE: `*`, `?`,
+/// `[abc]`, `[^a-z]` and `\` escapes, over raw bytes.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the most recent `*` if the current attempt fails
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        let matched = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, s));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p, string[s]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == string[s]).then_some(p + 2),
+            Some(c) => (*c == string[s]).then_some(p + 1),
+            None => None,
+        };
+
+        match (matched, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                s += 1;
+            }
+            (None, Some((star, from))) => {
+                p = star + 1;
+                s = from + 1;
+                backtrack = Some((star, from + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// Matches `c` against the `[...]` class starting at `pattern[start]`,
+/// returning the index just past the class on success.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<usize> {
+    let mut i = start + 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    while i < pattern.len() && pattern[i] != b']' {
+        if pattern[i] == b'\\' && i + 1 < pattern.len() {
+            matched |= pattern[i + 1] == c;
+            i += 2;
+        } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+            let (low, high) = (pattern[i].min(pattern[i + 2]), pattern[i].max(pattern[i + 2]));
+            matched |= low <= c && c <= high;
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+
+    // An unterminated class is treated as ending at the end of the pattern
+    (matched != negate).then_some((i + 1).min(pattern.len()))
+}
+
+/// Parses a 64-bit integer the way Redis does: no whitespace, no leading `+`
+/// and no leading zeros.
+pub fn parse_i64(value: &[u8]) -> Option<i64> {
+    let string = std::str::from_utf8(value).ok()?;
+
+    let digits = string.strip_prefix('-').unwrap_or(string);
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) || (digits.len() > 1 && digits.starts_with('0')) {
+        return None;
+    }
+
+    string.parse::<i64>().ok()
+}
+
+/// Parses a memory amount such as `1mb` or `16384`: `k`, `m` and `g` are
+/// powers of 1000, `kb`, `mb` and `gb` powers of 1024, in any case.
+pub fn parse_memory(value: &str) -> Option<usize> {
+    let value = value.to_lowercase();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None
+    };
+
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Resolves Redis-style inclusive `start`/`stop` indexes, where negative
+/// values count from the end, against a collection of `len` elements.
+/// Returns `None` when the range selects nothing.
+pub fn normalize_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+
+    if start > stop || start >= len {
+        return None;
+    }
+
+    Some((start as usize, stop as usize))
+}